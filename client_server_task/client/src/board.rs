@@ -0,0 +1,71 @@
+use crate::data;
+use std::time::Instant;
+
+/// Версия прошивки, с которой плата стартует.
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Li-ion: полный заряд и напряжение, ниже которого плата не работает
+const BATTERY_FULL: f32 = 4.2;
+const BATTERY_EMPTY: f32 = 3.0;
+// Разряд за одно показание
+const BATTERY_DRAIN: f32 = 0.0002;
+
+/// Имитация самой платы: батарея, WiFi, время работы, свободная куча.
+pub struct Board {
+    battery_voltage: f32,
+    rssi_dbm: i32,
+    free_heap_bytes: u32,
+    booted: Instant,
+    firmware_version: String,
+}
+
+impl Board {
+    pub fn new() -> Self {
+        Board {
+            battery_voltage: rand::random_range(3.9..BATTERY_FULL),
+            rssi_dbm: rand::random_range(-80..-50),
+            free_heap_bytes: rand::random_range(180_000..220_000),
+            booted: Instant::now(),
+            firmware_version: FIRMWARE_VERSION.to_string(),
+        }
+    }
+
+    pub fn firmware_version(&self) -> &str {
+        &self.firmware_version
+    }
+
+    /// Ставит проверенный образ; следом плата перезагружается.
+    pub fn install(&mut self, version: &str) {
+        self.firmware_version = version.to_string();
+    }
+
+    // Батарея и WiFi переживают перезагрузку, а время работы и куча - нет
+    pub fn reboot(&mut self) {
+        self.booted = Instant::now();
+        self.free_heap_bytes = rand::random_range(180_000..220_000);
+    }
+
+    pub fn health(&mut self) -> data::Health {
+        self.battery_voltage -= BATTERY_DRAIN;
+        self.battery_voltage = self.battery_voltage.max(BATTERY_EMPTY);
+        self.rssi_dbm = (self.rssi_dbm + rand::random_range(-3..=3)).clamp(-95, -35);
+        // медленная утечка памяти, как у настоящей прошивки
+        let heap = self.free_heap_bytes as i64 + rand::random_range(-2_000..1_500);
+        self.free_heap_bytes = heap.clamp(20_000, 240_000) as u32;
+
+        data::Health {
+            // шум АЦП
+            battery_voltage: self.battery_voltage + rand::random_range(-0.01..0.01),
+            rssi_dbm: self.rssi_dbm,
+            uptime_secs: self.booted.elapsed().as_secs(),
+            free_heap_bytes: self.free_heap_bytes,
+            firmware_version: self.firmware_version.clone(),
+        }
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
+}
@@ -0,0 +1,446 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, io};
+
+const ENV_PREFIX: &str = "SENSOR_";
+
+// Все ключи, которые можно переопределить через SENSOR_* или --set
+const KEYS: &[&str] = &[
+    "device_id",
+    "interval_secs",
+    "sensor_model",
+    "server.address",
+    "server.port",
+    "retry.max_attempts",
+    "retry.backoff_ms",
+    "retry.max_backoff_ms",
+    "queue.path",
+    "health.every",
+    "ota.chunk_bytes",
+    "trace.path",
+    "trace.speed",
+    "trace.loop",
+    "faults.truncated",
+    "faults.oversized",
+    "faults.garbage",
+    "faults.duplicate",
+    "faults.out_of_order",
+    "faults.half_open",
+    "tls.enabled",
+    "tls.ca_cert",
+    "tls.server_name",
+];
+
+// Короткие флаги командной строки и ключи, которые они задают
+const FLAGS: &[(&str, &str)] = &[
+    ("--device-id", "device_id"),
+    ("--interval", "interval_secs"),
+    ("--model", "sensor_model"),
+    ("--address", "server.address"),
+    ("--port", "server.port"),
+    ("--queue", "queue.path"),
+    ("--trace", "trace.path"),
+];
+
+const USAGE: &str = "Usage: client [--config <file>] [--device-id <id>] [--address <host>] \
+[--port <port>] [--interval <secs>] [--model dht11|dht22|bme280] [--queue <file>] [--trace <file>] \
+[--set <key>=<value>]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorModel {
+    Dht11,
+    Dht22,
+    /// Ещё и давление
+    Bme280,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub device_id: u32,
+    pub interval_secs: f64,
+    pub sensor_model: SensorModel,
+    pub server: ServerConfig,
+    pub retry: RetryConfig,
+    pub queue: QueueConfig,
+    pub health: HealthConfig,
+    pub ota: OtaConfig,
+    pub trace: TraceConfig,
+    pub faults: FaultConfig,
+    pub tls: TlsConfig,
+    /// Откуда взято значение ключа, для сообщений об ошибках; ключей
+    /// со значением по умолчанию здесь нет.
+    #[serde(skip)]
+    origins: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+}
+
+/// Сколько раз пытаться подключиться ради одного показания
+/// и как увеличивать паузу между попытками.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+/// Файл, куда складываются неотправленные показания.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub path: Option<PathBuf>,
+}
+
+/// Как часто прикладывать к показанию состояние платы.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Каждое N-е показание; 0 - никогда.
+    pub every: u64,
+}
+
+/// Загрузка прошивки по команде UpdateFirmware.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtaConfig {
+    /// Размер куска, который просит плата; 0 - на усмотрение сервера.
+    pub chunk_bytes: u32,
+}
+
+/// Запись показаний настоящей платы, см. `trace::Trace`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceConfig {
+    pub path: Option<PathBuf>,
+    /// 1 - исходный темп, 10 - в десять раз быстрее, 0 - без пауз.
+    pub speed: f64,
+    /// Дойдя до конца, начать сначала.
+    #[serde(rename = "loop")]
+    pub repeat: bool,
+}
+
+/// Вероятности нарочных неисправностей на каждое показание, от 0 до 1,
+/// см. `faults::Fault`. По умолчанию все нули.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaultConfig {
+    pub truncated: f64,
+    pub oversized: f64,
+    pub garbage: f64,
+    /// Номер предыдущего показания ещё раз.
+    pub duplicate: f64,
+    /// Время показания из прошлого.
+    pub out_of_order: f64,
+    pub half_open: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// PEM с корневыми сертификатами; без него используются webpki-roots.
+    pub ca_cert: Option<PathBuf>,
+    /// Имя для проверки сертификата; по умолчанию `server.address`.
+    pub server_name: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            device_id: 121,
+            interval_secs: 1.0,
+            sensor_model: SensorModel::Dht11,
+            server: ServerConfig::default(),
+            retry: RetryConfig::default(),
+            queue: QueueConfig::default(),
+            health: HealthConfig::default(),
+            ota: OtaConfig::default(),
+            trace: TraceConfig::default(),
+            faults: FaultConfig::default(),
+            tls: TlsConfig::default(),
+            origins: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "127.0.0.1".to_string(),
+            port: 7878,
+        }
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig { every: 10 }
+    }
+}
+
+// Столько помещается в буфер приёма у ESP32
+impl Default for OtaConfig {
+    fn default() -> Self {
+        OtaConfig { chunk_bytes: 4096 }
+    }
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            path: None,
+            speed: 1.0,
+            repeat: false,
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            backoff_ms: 500,
+            max_backoff_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Usage(String),
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// Неверное значение ключа; `origin` - откуда оно пришло.
+    Key {
+        key: String,
+        origin: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Usage(msg) => write!(f, "{}\n{}", msg, USAGE),
+            ConfigError::Read(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Key {
+                key,
+                origin,
+                message,
+            } => write!(f, "{} (from {}): {}", key, origin, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Собирает конфигурацию: значения по умолчанию, затем TOML-файл,
+    /// затем переменные `SENSOR_*`, затем флаги командной строки.
+    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        args.next(); // Пропускаем имя программы
+
+        let mut file = env::var(format!("{}CONFIG", ENV_PREFIX)).ok().map(PathBuf::from);
+        let mut overrides: Vec<(String, String, String)> = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::Usage(format!("Missing value after {}", arg)))?;
+            if arg == "--config" {
+                file = Some(PathBuf::from(value));
+            } else if arg == "--set" {
+                let (key, value) = value.split_once('=').ok_or_else(|| {
+                    ConfigError::Usage(format!("Expected <key>=<value> after --set, got {}", value))
+                })?;
+                overrides.push((key.to_string(), value.to_string(), "--set".to_string()));
+            } else if let Some((_, key)) = FLAGS.iter().find(|(flag, _)| *flag == arg) {
+                overrides.push((key.to_string(), value, arg));
+            } else {
+                return Err(ConfigError::Usage(format!("Unknown argument: {}", arg)));
+            }
+        }
+
+        let mut config = match file {
+            Some(path) => Config::from_file(&path)?,
+            None => Config::default(),
+        };
+
+        for key in KEYS {
+            let var = env_name(key);
+            if let Ok(value) = env::var(&var) {
+                config.set(key, &value, &format!("env {}", var))?;
+            }
+        }
+        for (key, value, origin) in overrides {
+            config.set(&key, &value, &origin)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        let parse_err = |e| ConfigError::Parse(path.to_path_buf(), e);
+        let mut config: Config = toml::from_str(&text).map_err(parse_err)?;
+        let table: toml::Table = toml::from_str(&text).map_err(parse_err)?;
+        for key in KEYS {
+            let mut value = Some(&table);
+            let mut parts = key.split('.').peekable();
+            while let Some(part) = parts.next() {
+                if parts.peek().is_none() {
+                    if value.is_some_and(|t| t.contains_key(part)) {
+                        config.origins.insert(key.to_string(), path.display().to_string());
+                    }
+                } else {
+                    value = value.and_then(|t| t.get(part)).and_then(|v| v.as_table());
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Откуда взято значение ключа: файл, переменная, флаг или `default`.
+    pub fn origin(&self, key: &str) -> &str {
+        self.origins.get(key).map_or("default", String::as_str)
+    }
+
+    /// Ошибка в значении ключа с указанием, откуда оно взято.
+    pub fn key_error(&self, key: &str, message: impl Into<String>) -> ConfigError {
+        ConfigError::Key {
+            key: key.to_string(),
+            origin: self.origin(key).to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str, origin: &str) -> Result<(), ConfigError> {
+        let err = |message: String| ConfigError::Key {
+            key: key.to_string(),
+            origin: origin.to_string(),
+            message,
+        };
+        match key {
+            "device_id" => self.device_id = parse(value).map_err(err)?,
+            "interval_secs" => self.interval_secs = parse(value).map_err(err)?,
+            "sensor_model" => {
+                self.sensor_model = match value {
+                    "dht11" => SensorModel::Dht11,
+                    "dht22" => SensorModel::Dht22,
+                    "bme280" => SensorModel::Bme280,
+                    _ => {
+                        return Err(err(format!(
+                            "unknown sensor model {:?}, expected dht11, dht22 or bme280",
+                            value
+                        )));
+                    }
+                }
+            }
+            "server.address" => self.server.address = value.to_string(),
+            "server.port" => self.server.port = parse(value).map_err(err)?,
+            "retry.max_attempts" => self.retry.max_attempts = parse(value).map_err(err)?,
+            "retry.backoff_ms" => self.retry.backoff_ms = parse(value).map_err(err)?,
+            "retry.max_backoff_ms" => self.retry.max_backoff_ms = parse(value).map_err(err)?,
+            "queue.path" => self.queue.path = non_empty(value).map(PathBuf::from),
+            "health.every" => self.health.every = parse(value).map_err(err)?,
+            "ota.chunk_bytes" => self.ota.chunk_bytes = parse(value).map_err(err)?,
+            "trace.path" => self.trace.path = non_empty(value).map(PathBuf::from),
+            "trace.speed" => self.trace.speed = parse(value).map_err(err)?,
+            "trace.loop" => self.trace.repeat = parse(value).map_err(err)?,
+            "faults.truncated" => self.faults.truncated = parse(value).map_err(err)?,
+            "faults.oversized" => self.faults.oversized = parse(value).map_err(err)?,
+            "faults.garbage" => self.faults.garbage = parse(value).map_err(err)?,
+            "faults.duplicate" => self.faults.duplicate = parse(value).map_err(err)?,
+            "faults.out_of_order" => self.faults.out_of_order = parse(value).map_err(err)?,
+            "faults.half_open" => self.faults.half_open = parse(value).map_err(err)?,
+            "tls.enabled" => self.tls.enabled = parse(value).map_err(err)?,
+            "tls.ca_cert" => self.tls.ca_cert = non_empty(value).map(PathBuf::from),
+            "tls.server_name" => self.tls.server_name = non_empty(value).map(str::to_string),
+            _ => return Err(err("unknown key".to_string())),
+        }
+        self.origins.insert(key.to_string(), origin.to_string());
+        Ok(())
+    }
+
+    /// Проверки, которые не выразить типами.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let err = |key: &str, message: &str| self.key_error(key, message);
+        if self.server.address.is_empty() {
+            return Err(err("server.address", "must not be empty"));
+        }
+        if self.server.port == 0 {
+            return Err(err("server.port", "must be between 1 and 65535"));
+        }
+        if !(self.interval_secs.is_finite() && self.interval_secs > 0.0) {
+            return Err(err("interval_secs", "must be a positive number of seconds"));
+        }
+        if !(self.trace.speed.is_finite() && self.trace.speed >= 0.0) {
+            return Err(err("trace.speed", "must be a non-negative number"));
+        }
+        let faults = &self.faults;
+        for (key, probability) in [
+            ("faults.truncated", faults.truncated),
+            ("faults.oversized", faults.oversized),
+            ("faults.garbage", faults.garbage),
+            ("faults.duplicate", faults.duplicate),
+            ("faults.out_of_order", faults.out_of_order),
+            ("faults.half_open", faults.half_open),
+        ] {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(err(key, "must be a probability between 0 and 1"));
+            }
+        }
+        if self.retry.max_attempts == 0 {
+            return Err(err("retry.max_attempts", "must be at least 1"));
+        }
+        if self.retry.max_backoff_ms < self.retry.backoff_ms {
+            return Err(err("retry.max_backoff_ms", "must not be less than retry.backoff_ms"));
+        }
+        if let Some(path) = self.tls.ca_cert.as_ref().filter(|_| self.tls.enabled)
+            && !path.is_file()
+        {
+            return Err(err("tls.ca_cert", &format!("file {} not found", path.display())));
+        }
+        Ok(())
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.server.address, self.server.port)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.interval_secs)
+    }
+}
+
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
+}
+
+fn parse<T>(value: &str) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid value {:?}: {}", value, e))
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value).filter(|v| !v.is_empty())
+}
@@ -0,0 +1,127 @@
+use crate::config::{Config, ConfigError};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Соединение с сервером: обычный TCP или TLS поверх него.
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Connection {
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Connection::Plain(stream) => stream,
+            Connection::Tls(stream) => &stream.sock,
+        }
+    }
+
+    /// Сообщает серверу, что данных больше не будет; ответы читаются до EOF.
+    pub fn shutdown_write(&mut self) -> io::Result<()> {
+        if let Connection::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            stream.flush()?;
+        }
+        self.socket().shutdown(Shutdown::Write)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.socket().set_read_timeout(Some(timeout))
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.flush(),
+            Connection::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+pub struct Connector {
+    addr: String,
+    tls: Option<(Arc<ClientConfig>, ServerName<'static>)>,
+}
+
+impl Connector {
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        let tls = if config.tls.enabled {
+            Some(tls_config(config)?)
+        } else {
+            None
+        };
+        Ok(Connector {
+            addr: config.addr(),
+            tls,
+        })
+    }
+
+    pub fn connect(&self) -> io::Result<Connection> {
+        let stream = TcpStream::connect(&self.addr)?;
+        match &self.tls {
+            None => Ok(Connection::Plain(stream)),
+            Some((tls, name)) => {
+                let conn = ClientConnection::new(Arc::clone(tls), name.clone())
+                    .map_err(io::Error::other)?;
+                Ok(Connection::Tls(Box::new(StreamOwned::new(conn, stream))))
+            }
+        }
+    }
+}
+
+fn tls_config(config: &Config) -> Result<(Arc<ClientConfig>, ServerName<'static>), ConfigError> {
+    let err = |key: &str, message: String| config.key_error(key, message);
+
+    let mut roots = RootCertStore::empty();
+    match &config.tls.ca_cert {
+        Some(path) => {
+            let certs = CertificateDer::pem_file_iter(path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| err("tls.ca_cert", e.to_string()))?;
+            let (added, _) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                return Err(err("tls.ca_cert", "no certificates found".to_string()));
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let tls = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| err("tls.enabled", e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let name = config
+        .tls
+        .server_name
+        .clone()
+        .unwrap_or_else(|| config.server.address.clone());
+    let key = if config.tls.server_name.is_some() { "tls.server_name" } else { "server.address" };
+    let name = ServerName::try_from(name).map_err(|e| err(key, e.to_string()))?;
+
+    Ok((Arc::new(tls), name))
+}
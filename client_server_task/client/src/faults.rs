@@ -0,0 +1,77 @@
+use crate::config::FaultConfig;
+use prost_types::Timestamp;
+use protocol::{HEADER_LEN, MAX_FRAME_LEN};
+use std::fmt;
+
+/// Неисправность кадра, которую клиент изображает нарочно, чтобы
+/// проверить, как сервер переживает плохие платы и плохую сеть.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Кадр обрывается на середине, соединение закрывается.
+    Truncated,
+    /// Длина в заголовке больше `MAX_FRAME_LEN`.
+    Oversized,
+    /// Длина верная, внутри - не protobuf.
+    Garbage,
+    /// Часть кадра, и соединение бросается открытым, без FIN.
+    HalfOpen,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Fault::Truncated => "truncated frame",
+            Fault::Oversized => "oversized length prefix",
+            Fault::Garbage => "garbage protobuf",
+            Fault::HalfOpen => "half-open connection",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Fault {
+    /// Не больше одной неисправности кадра на показание; вероятности
+    /// проверяются по очереди.
+    pub fn pick(config: &FaultConfig) -> Option<Fault> {
+        [
+            (config.truncated, Fault::Truncated),
+            (config.oversized, Fault::Oversized),
+            (config.garbage, Fault::Garbage),
+            (config.half_open, Fault::HalfOpen),
+        ]
+        .into_iter()
+        .find(|(probability, _)| roll(*probability))
+        .map(|(_, fault)| fault)
+    }
+
+    pub fn apply(self, mut frame: Vec<u8>) -> Vec<u8> {
+        match self {
+            Fault::Truncated | Fault::HalfOpen => {
+                frame.truncate(rand::random_range(1..frame.len()));
+            }
+            Fault::Oversized => {
+                let len = rand::random_range(MAX_FRAME_LEN as u32 + 1..=u32::MAX);
+                frame[..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+            }
+            Fault::Garbage => {
+                frame[HEADER_LEN..].iter_mut().for_each(|b| *b = rand::random());
+                // поле 1 с типом 7: такого типа нет, prost не разберёт наверняка
+                if let Some(first) = frame.get_mut(HEADER_LEN) {
+                    *first = 0x0f;
+                }
+            }
+        }
+        frame
+    }
+}
+
+/// Время показания из прошлого: от минуты до часа назад. Возвращает сдвиг в секундах.
+pub fn backdate(time: &mut Timestamp) -> i64 {
+    let seconds = rand::random_range(60..3600);
+    time.seconds -= seconds;
+    seconds
+}
+
+pub fn roll(probability: f64) -> bool {
+    probability > 0.0 && rand::random_bool(probability)
+}
@@ -0,0 +1,400 @@
+
+use prost_types::Timestamp;
+use protocol::Frame;
+use std::collections::VecDeque;
+use std::io::{self, Result};
+use std::{io::Write, time::Duration, thread};
+use std::time::{SystemTime, UNIX_EPOCH};
+use board::Board;
+use config::{Config, SensorModel};
+use connection::{Connection, Connector};
+use faults::Fault;
+use ota::Download;
+use queue::Queue;
+use trace::Trace;
+
+pub mod board;
+pub mod config;
+pub mod connection;
+pub mod faults;
+pub mod ota;
+pub mod queue;
+pub mod trace;
+
+
+pub struct DHT {
+    humidity: f32,
+    temperature: f32,
+    humidity_range: (f32, f32),
+    temperature_range: (f32, f32),
+    humidity_offset: f32,
+    temperature_offset: f32,
+    /// Па; `None` у датчиков без барометра
+    pressure: Option<f32>,
+}
+
+impl DHT {
+
+
+    pub fn new(model: SensorModel) -> Self {
+        // рабочие диапазоны из даташитов
+        let (humidity_range, temperature_range) = match model {
+            SensorModel::Dht11 => ((20.0, 90.0), (0.0, 50.0)),
+            SensorModel::Dht22 => ((0.0, 100.0), (-40.0, 80.0)),
+            SensorModel::Bme280 => ((0.0, 100.0), (-40.0, 85.0)),
+        };
+        Self {
+            humidity: rand::random_range(humidity_range.0..humidity_range.1),
+            temperature: rand::random_range(temperature_range.0..temperature_range.1),
+            humidity_range,
+            temperature_range,
+            humidity_offset: 0.0,
+            temperature_offset: 0.0,
+            pressure: (model == SensorModel::Bme280).then(|| rand::random_range(99_000.0..103_000.0)),
+        }
+    }
+
+    // Поправки, присланные сервером командой Recalibrate
+    pub fn recalibrate(&mut self, temperature_offset: f32, humidity_offset: f32) {
+        self.temperature_offset = temperature_offset;
+        self.humidity_offset = humidity_offset;
+    }
+
+    pub fn get_humidity(&mut self) -> f32 {
+        self.humidity += rand::random_range(-10.0..10.0);
+        self.humidity = self.humidity.clamp(self.humidity_range.0, self.humidity_range.1);
+        self.humidity + self.humidity_offset
+    }
+
+
+    pub fn get_temperature(&mut self) -> f32 {
+        self.temperature += rand::random_range(-10.0..10.0);
+        self.temperature = self.temperature.clamp(self.temperature_range.0, self.temperature_range.1);
+        self.temperature + self.temperature_offset
+    }
+
+    // Давление меняется медленно, на десятки паскалей между показаниями
+    pub fn get_pressure(&mut self) -> Option<f32> {
+        let pressure = self.pressure.as_mut()?;
+        *pressure = (*pressure + rand::random_range(-50.0..50.0)).clamp(30_000.0, 110_000.0);
+        Some(*pressure)
+    }
+
+    /// Все величины показания; давление - в паскалях, как его отдаёт BME280,
+    /// в гектопаскали его переводит сервер.
+    pub fn measurements(&mut self) -> Vec<data::Measurement> {
+        // разрешение датчиков - сотые доли, дальше в f64 был бы только шум от f32
+        let measurement = |metric: &str, value: f32, unit: &str| data::Measurement {
+            metric: metric.to_string(),
+            value: (value as f64 * 100.0).round() / 100.0,
+            unit: unit.to_string(),
+        };
+        let mut measurements = vec![
+            measurement("temperature", self.get_temperature(), "°C"),
+            measurement("humidity", self.get_humidity(), "%"),
+        ];
+        if let Some(pressure) = self.get_pressure() {
+            measurements.push(measurement("pressure", pressure, "Pa"));
+        }
+        measurements
+    }
+
+    
+}
+
+
+
+
+pub use protocol::data;
+
+pub struct SERVER {
+    config: Config,
+    connector: Connector,
+    queue: Option<Queue>,
+    event_id: u64,
+    dht: DHT,
+    board: Board,
+    /// Незаконченная загрузка прошивки
+    update: Option<Download>,
+    /// Запись вместо случайных показаний
+    trace: Option<Trace>,
+    /// Брошенные соединения неисправности HalfOpen: пока они здесь,
+    /// сокет не закрывается и сервер не получает FIN
+    half_open: VecDeque<Connection>,
+}
+
+// Больше брошенных соединений не держим, чтобы не кончились дескрипторы
+const MAX_HALF_OPEN: usize = 16;
+
+impl SERVER {
+    pub fn new(config: Config, connector: Connector) -> Self {
+        SERVER {
+            queue: config.queue.path.as_ref().map(Queue::new),
+            dht: DHT::new(config.sensor_model),
+            board: Board::new(),
+            update: None,
+            trace: None,
+            half_open: VecDeque::new(),
+            config,
+            connector,
+            event_id: 0,
+        }
+    }
+
+    /// Показания берутся из записи, а не со случайного датчика; паузы
+    /// между ними - как в записи, `interval_secs` и SetInterval не действуют.
+    pub fn play(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            let pause = match &self.trace {
+                Some(trace) => match trace.pause() {
+                    Some(pause) => pause,
+                    None => {
+                        println!("Trace finished");
+                        return Ok(());
+                    }
+                },
+                None => self.config.interval(),
+            };
+            thread::sleep(pause);
+            self.step()?;
+        }
+    }
+
+    /// Одно показание: снять, отправить (или положить в очередь) и применить
+    /// полученные в ответ команды. Ошибкой считается только сбой очереди.
+    pub fn step(&mut self) -> Result<()> {
+        let every = self.config.health.every;
+        let measurements = match &mut self.trace {
+            Some(trace) => match trace.next_measurements() {
+                Some(measurements) => measurements,
+                None => return Ok(()),
+            },
+            None => self.dht.measurements(),
+        };
+        // прежние поля - для серверов, которые ещё не знают о measurements
+        let legacy = |metric: &str| {
+            measurements
+                .iter()
+                .find(|m| m.metric == metric)
+                .map_or(0.0, |m| m.value as f32)
+        };
+        let probability = &self.config.faults;
+        // повтор - номер уже отправленного показания, счётчик при этом стоит
+        let duplicate = self.event_id > 0 && faults::roll(probability.duplicate);
+        let event_id = if duplicate { self.event_id - 1 } else { self.event_id };
+        let mut read_time = current_timestamp();
+        if faults::roll(probability.out_of_order) {
+            let seconds = faults::backdate(&mut read_time);
+            println!("Fault injected: read time {} s in the past", seconds);
+        }
+        if duplicate {
+            println!("Fault injected: duplicate event id {}", event_id);
+        }
+        let data = data::Data {
+            device_id: self.config.device_id,
+            event_id,
+            humidity: legacy("humidity"),
+            temperature: legacy("temperature"),
+            read_time: Some(read_time),
+            health: (every > 0 && event_id.is_multiple_of(every)).then(|| self.board.health()),
+            measurements,
+            firmware_request: None,
+        };
+        if !duplicate {
+            self.event_id += 1;
+        }
+
+        let mut frame = data.encode_frame();
+        let fault = Fault::pick(&self.config.faults);
+        if let Some(fault) = fault {
+            println!("Fault injected: {}", fault);
+            frame = fault.apply(frame);
+        }
+        // испорченный кадр в очередь не кладём, он сломал бы и остальные
+        let enqueue = |server: &Self, frame: &[u8]| match fault {
+            Some(_) => Ok(()),
+            None => server.enqueue(frame),
+        };
+
+        let mut stream = match self.connect() {
+            Some(stream) => stream,
+            None => return enqueue(self, &frame),
+        };
+        if fault == Some(Fault::HalfOpen) {
+            if let Err(e) = stream.write_all(&frame).and_then(|_| stream.flush()) {
+                eprintln!("Send error: {}", e);
+            }
+            if self.half_open.len() == MAX_HALF_OPEN {
+                self.half_open.pop_front();
+            }
+            self.half_open.push_back(stream);
+            return Ok(());
+        }
+        if let Err(e) = self.send(&mut stream, &frame) {
+            eprintln!("Send error: {}", e);
+            return enqueue(self, &frame);
+        }
+        match read_replies(&mut stream) {
+            Ok(commands) => commands.into_iter().for_each(|c| self.apply(c)),
+            Err(e) => eprintln!("Reply error: {}", e),
+        }
+        self.download();
+        Ok(())
+    }
+
+    fn enqueue(&self, frame: &[u8]) -> Result<()> {
+        match &self.queue {
+            Some(queue) => queue.push(frame),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, command: data::Command) {
+        use data::command::Action;
+        match command.action {
+            Some(Action::SetInterval(set)) if set.interval_ms > 0 => {
+                println!("Command {}: interval {} ms", command.command_id, set.interval_ms);
+                self.config.interval_secs = set.interval_ms as f64 / 1000.0;
+            }
+            Some(Action::Reboot(_)) => {
+                println!("Command {}: reboot", command.command_id);
+                self.reboot();
+            }
+            Some(Action::Recalibrate(cal)) => {
+                println!(
+                    "Command {}: recalibrate temperature {:+} humidity {:+}",
+                    command.command_id, cal.temperature_offset, cal.humidity_offset
+                );
+                self.dht.recalibrate(cal.temperature_offset, cal.humidity_offset);
+            }
+            Some(Action::UpdateFirmware(update)) => {
+                println!("Command {}: update firmware to {}", command.command_id, update.version);
+                if update.version == self.board.firmware_version() {
+                    println!("Firmware {}: already running", update.version);
+                } else if self.update.as_ref().is_none_or(|d| d.version != update.version) {
+                    self.update = Some(Download::new(&update));
+                }
+            }
+            _ => eprintln!("Command {}: ignored unsupported action", command.command_id),
+        }
+    }
+
+    // Имитация перезагрузки: счётчик и датчик начинают заново,
+    // настройки считаются сохранёнными во flash
+    fn reboot(&mut self) {
+        thread::sleep(Duration::from_secs(2));
+        self.event_id = 0;
+        let (t, h) = (self.dht.temperature_offset, self.dht.humidity_offset);
+        self.dht = DHT::new(self.config.sensor_model);
+        self.dht.recalibrate(t, h);
+        self.board.reboot();
+    }
+
+    // Докачивает прошивку, проверяет и ставит её. Оборванная загрузка
+    // продолжается после следующего показания с того же места.
+    fn download(&mut self) {
+        let Some(mut download) = self.update.take() else {
+            return;
+        };
+        let result = self.fetch(&mut download).map_err(|e| (e.kind(), e.to_string()));
+        let result = match result {
+            Err((io::ErrorKind::InvalidData, e)) => Err(e),
+            Err((_, e)) => {
+                eprintln!(
+                    "Firmware {}: download interrupted at {} bytes: {}",
+                    download.version,
+                    download.offset(),
+                    e
+                );
+                self.update = Some(download);
+                return;
+            }
+            Ok(()) => download.verify(),
+        };
+        match result {
+            Ok(()) => {
+                println!("Firmware {}: installed, rebooting", download.version);
+                self.board.install(&download.version);
+                self.reboot();
+            }
+            Err(e) => eprintln!("Firmware {}: update aborted: {}", download.version, e),
+        }
+    }
+
+    // Куски по одному соединению, запрос - ответ; InvalidData - загрузку
+    // продолжать бессмысленно
+    fn fetch(&self, download: &mut Download) -> Result<()> {
+        let mut stream = self.connect().ok_or_else(|| io::Error::other("server unreachable"))?;
+        stream.set_read_timeout(Duration::from_secs(5))?;
+        while !download.is_complete() {
+            let request = download.request(self.config.device_id, self.config.ota.chunk_bytes);
+            stream.write_all(&request.encode_frame())?;
+            stream.flush()?;
+            let reply = data::Reply::read_frame(&mut stream)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))?;
+            let chunk = reply
+                .firmware_chunk
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no firmware chunk in reply"))?;
+            download
+                .append(chunk)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        stream.shutdown_write()
+    }
+
+    // Несколько попыток подключения с растущей паузой, см. [retry]
+    fn connect(&self) -> Option<Connection> {
+        let retry = &self.config.retry;
+        let mut backoff = Duration::from_millis(retry.backoff_ms);
+        for attempt in 1..=retry.max_attempts {
+            match self.connector.connect() {
+                Ok(stream) => return Some(stream),
+                Err(e) => eprintln!("Connection error (attempt {}/{}): {}", attempt, retry.max_attempts, e),
+            }
+            if attempt < retry.max_attempts {
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(Duration::from_millis(retry.max_backoff_ms));
+            }
+        }
+        None
+    }
+
+    fn send(&self, stream: &mut Connection, frame: &[u8]) -> Result<()> {
+        if let Some(queue) = &self.queue {
+            let sent = queue.drain_into(stream)?;
+            if sent > 0 {
+                println!("Sent {} queued bytes", sent);
+            }
+        }
+        stream.write_all(frame)?;
+        stream.flush()?;
+        stream.shutdown_write()
+    }
+}
+
+// Сервер отвечает на каждое показание кадром Reply и закрывает соединение
+// после нашего shutdown; старый сервер просто закрывает его без ответов.
+fn read_replies(stream: &mut Connection) -> Result<Vec<data::Command>> {
+    stream.set_read_timeout(Duration::from_secs(5))?;
+    let mut commands = Vec::new();
+    while let Some(reply) = data::Reply::read_frame(stream)? {
+        commands.extend(reply.commands);
+    }
+    Ok(commands)
+}
+
+
+fn current_timestamp() -> Timestamp {
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap();
+    
+    Timestamp {
+        seconds: duration.as_secs() as i64,
+        nanos: duration.subsec_nanos() as i32,
+    }
+}
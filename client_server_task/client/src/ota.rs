@@ -0,0 +1,78 @@
+use crate::data;
+use sha2::{Digest, Sha256};
+
+/// Загрузка прошивки по команде UpdateFirmware. Скачанное хранится между
+/// показаниями: после обрыва загрузка продолжается с `offset()`.
+pub struct Download {
+    pub version: String,
+    size: u64,
+    sha256: String,
+    image: Vec<u8>,
+}
+
+impl Download {
+    pub fn new(update: &data::UpdateFirmware) -> Self {
+        Download {
+            version: update.version.clone(),
+            size: update.size,
+            sha256: update.sha256.to_lowercase(),
+            image: Vec::new(),
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.image.len() as u64
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.offset() >= self.size
+    }
+
+    /// Кадр с запросом следующего куска.
+    pub fn request(&self, device_id: u32, chunk_bytes: u32) -> data::Data {
+        data::Data {
+            device_id,
+            firmware_request: Some(data::FirmwareRequest {
+                version: self.version.clone(),
+                offset: self.offset(),
+                max_len: chunk_bytes,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Дописывает кусок. Ошибка - загрузку продолжать бессмысленно.
+    pub fn append(&mut self, chunk: data::FirmwareChunk) -> Result<(), String> {
+        if !chunk.error.is_empty() {
+            return Err(chunk.error);
+        }
+        if chunk.version != self.version || chunk.total_size != self.size {
+            return Err(format!(
+                "server sent {} of {} bytes, expected {} of {} bytes",
+                chunk.version, chunk.total_size, self.version, self.size
+            ));
+        }
+        // запоздавший кусок из прошлого соединения не нужен
+        if chunk.offset != self.offset() {
+            return Ok(());
+        }
+        if chunk.data.is_empty() && !self.is_complete() {
+            return Err(format!("empty chunk at offset {}", chunk.offset));
+        }
+        self.image.extend_from_slice(&chunk.data);
+        if self.offset() > self.size {
+            return Err(format!("image is longer than {} bytes", self.size));
+        }
+        Ok(())
+    }
+
+    /// Сверяет SHA-256 скачанного образа с присланным в команде.
+    pub fn verify(&self) -> Result<(), String> {
+        let actual: String = Sha256::digest(&self.image).iter().map(|b| format!("{:02x}", b)).collect();
+        if actual == self.sha256 {
+            Ok(())
+        } else {
+            Err(format!("sha256 mismatch: expected {}, got {}", self.sha256, actual))
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Очередь неотправленных показаний на диске.
+///
+/// Кадры хранятся ровно в том виде, в каком уходят в сеть
+/// (длина u32 LE + protobuf), поэтому содержимое файла
+/// можно отправить серверу как есть.
+pub struct Queue {
+    path: PathBuf,
+}
+
+impl Queue {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Queue { path: path.into() }
+    }
+
+    pub fn push(&self, frame: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(frame)?;
+        file.sync_data()
+    }
+
+    /// Отправляет накопленные кадры и очищает очередь, если запись удалась.
+    pub fn drain_into(&self, out: &mut impl Write) -> io::Result<usize> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if contents.is_empty() {
+            return Ok(0);
+        }
+
+        out.write_all(&contents)?;
+        out.flush()?;
+        fs::remove_file(&self.path)?;
+        Ok(contents.len())
+    }
+}
@@ -0,0 +1,290 @@
+use crate::config::{Config, ConfigError};
+use crate::data;
+use chrono::{DateTime, NaiveDateTime};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// Столбцы со временем показания; если их несколько, берётся первый непустой
+const TIME_COLUMNS: &[&str] = &["time", "timestamp", "read_time"];
+// Столбцы выгрузки `server export`, которые не являются величинами
+const SKIPPED_COLUMNS: &[&str] = &["device_id", "event_id", "received_at"];
+// Строки одного показания в выгрузке `server export`: у всех его величин
+// общие устройство, время и номер
+const READING_COLUMNS: &[&str] = &["device_id", "read_time", "event_id"];
+
+/// Показания настоящей платы вместо случайных: CSV или JSONL (по
+/// расширению `.jsonl`/`.json`), одна строка - одно показание.
+///
+/// В CSV первая строка - заголовок: столбец времени (`time`, `timestamp`
+/// или `read_time`) и по столбцу на величину, единица - в скобках:
+/// `pressure (Pa)`. Без единицы величина считается в единицах сервера.
+/// В JSONL те же имена - ключи объекта. Время - RFC 3339, `YYYY-MM-DD HH:MM:SS`
+/// (UTC) или секунды Unix.
+///
+/// Выгрузка `server export` подходит как есть: в ней строка на величину
+/// (столбцы `metric`, `value`, `unit`), и идущие подряд строки с общими
+/// `device_id`, `read_time` и `event_id` собираются в одно показание.
+pub struct Trace {
+    points: Vec<Point>,
+    position: usize,
+    speed: f64,
+    repeat: bool,
+}
+
+/// Одно показание записи.
+pub struct Point {
+    /// Секунды Unix.
+    pub time: f64,
+    pub measurements: Vec<data::Measurement>,
+}
+
+enum Column {
+    Time,
+    Metric { metric: String, unit: String },
+    Skip,
+}
+
+impl Trace {
+    /// `None`, если `trace.path` не задан.
+    pub fn load(config: &Config) -> Result<Option<Trace>, ConfigError> {
+        let Some(path) = &config.trace.path else {
+            return Ok(None);
+        };
+        let err = |message: String| config.key_error("trace.path", message);
+        let points = read(path).map_err(|e| err(format!("{}: {}", path.display(), e)))?;
+        if points.is_empty() {
+            return Err(err(format!("{}: no readings", path.display())));
+        }
+        Ok(Some(Trace {
+            points,
+            position: 0,
+            speed: config.trace.speed,
+            repeat: config.trace.repeat,
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Пауза перед следующим показанием: исходный промежуток, делённый на
+    /// `speed`. `None` - запись кончилась и повторять её не нужно.
+    pub fn pause(&self) -> Option<Duration> {
+        let gap = match self.position {
+            0 => 0.0,
+            // по кругу: средний шаг записи, чтобы не слать последнее и первое разом
+            n if n == self.points.len() && self.repeat => {
+                let span = self.points[n - 1].time - self.points[0].time;
+                span / (n - 1).max(1) as f64
+            }
+            n if n == self.points.len() => return None,
+            n => self.points[n].time - self.points[n - 1].time,
+        };
+        if self.speed == 0.0 {
+            return Some(Duration::ZERO);
+        }
+        // строки не по порядку времени идут без паузы
+        Some(Duration::from_secs_f64((gap / self.speed).max(0.0)))
+    }
+
+    /// Величины следующего показания; `None` - запись кончилась.
+    pub fn next_measurements(&mut self) -> Option<Vec<data::Measurement>> {
+        if self.position == self.points.len() {
+            if !self.repeat {
+                return None;
+            }
+            self.position = 0;
+        }
+        self.position += 1;
+        Some(self.points[self.position - 1].measurements.clone())
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<Point>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("jsonl" | "json") => parse_jsonl(&text),
+        _ => parse_csv(&text),
+    }
+}
+
+pub fn parse_csv(text: &str) -> Result<Vec<Point>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let columns: Vec<Column> = headers.iter().map(|h| column(h)).collect();
+    if !columns.iter().any(|c| matches!(c, Column::Time)) {
+        return Err(format!("no time column, expected one of {}", TIME_COLUMNS.join(", ")));
+    }
+    let index = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let long = match (index("metric"), index("value")) {
+        (Some(metric), Some(value)) => Some((metric, value, index("unit"))),
+        _ => None,
+    };
+    let reading: Vec<usize> = READING_COLUMNS.iter().filter_map(|c| index(c)).collect();
+
+    let mut points = Vec::new();
+    let mut last = None;
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let line = record.position().map_or(0, |p| p.line());
+        let err = |e: String| format!("line {}: {}", line, e);
+        let mut time = None;
+        for (column, value) in columns.iter().zip(record.iter()) {
+            if matches!(column, Column::Time) && !value.is_empty() {
+                time = Some(parse_time(value).map_err(err)?);
+                break;
+            }
+        }
+
+        if let Some((metric, value, unit)) = long {
+            let cell = |i: usize| record.get(i).unwrap_or_default();
+            let (metric, value, unit) = (cell(metric), cell(value), unit.map_or("", cell));
+            let value = value
+                .parse()
+                .map_err(|_| err(format!("invalid {} value {:?}", metric, value)))?;
+            let key: Vec<&str> = reading.iter().map(|&i| cell(i)).collect();
+            push_long(&mut points, &mut last, key.join(","), time, measurement(metric, value, unit)).map_err(err)?;
+            continue;
+        }
+
+        let mut measurements = Vec::new();
+        for (column, value) in columns.iter().zip(record.iter()) {
+            if let (Column::Metric { metric, unit }, false) = (column, value.is_empty()) {
+                let value = value
+                    .parse()
+                    .map_err(|_| err(format!("invalid {} value {:?}", metric, value)))?;
+                measurements.push(measurement(metric, value, unit));
+            }
+        }
+        push(&mut points, time, measurements).map_err(err)?;
+    }
+    Ok(points)
+}
+
+pub fn parse_jsonl(text: &str) -> Result<Vec<Point>, String> {
+    let mut points = Vec::new();
+    let mut last = None;
+    for (i, line) in text.lines().enumerate() {
+        let err = |e: String| format!("line {}: {}", i + 1, e);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(line).map_err(|e| err(e.to_string()))?;
+        let mut time = None;
+        for (key, value) in &object {
+            match (column(key), value) {
+                (Column::Time, serde_json::Value::String(s)) => time = Some(parse_time(s).map_err(err)?),
+                (Column::Time, serde_json::Value::Number(n)) => time = n.as_f64(),
+                _ => {}
+            }
+        }
+
+        if let Some(metric) = object.get("metric") {
+            let metric = metric.as_str().ok_or_else(|| err("metric must be a string".to_string()))?;
+            let value = object
+                .get("value")
+                .and_then(serde_json::Value::as_f64)
+                .ok_or_else(|| err(format!("invalid {} value", metric)))?;
+            let unit = object.get("unit").and_then(serde_json::Value::as_str).unwrap_or_default();
+            let key: Vec<String> = READING_COLUMNS
+                .iter()
+                .map(|c| object.get(*c).map(|v| v.to_string()).unwrap_or_default())
+                .collect();
+            push_long(&mut points, &mut last, key.join(","), time, measurement(metric, value, unit)).map_err(err)?;
+            continue;
+        }
+
+        let mut measurements = Vec::new();
+        for (key, value) in &object {
+            // null, строки и прочее - не показания
+            if let (Column::Metric { metric, unit }, Some(value)) = (column(key), value.as_f64()) {
+                measurements.push(measurement(&metric, value, &unit));
+            }
+        }
+        push(&mut points, time, measurements).map_err(err)?;
+    }
+    Ok(points)
+}
+
+// Величина из выгрузки `server export`: к показанию предыдущей строки, если оно то же
+fn push_long(
+    points: &mut Vec<Point>,
+    last: &mut Option<String>,
+    key: String,
+    time: Option<f64>,
+    measurement: data::Measurement,
+) -> Result<(), String> {
+    let time = time.ok_or("missing time")?;
+    match points.last_mut() {
+        Some(point) if last.as_ref() == Some(&key) => point.measurements.push(measurement),
+        _ => {
+            points.push(Point {
+                time,
+                measurements: vec![measurement],
+            });
+            *last = Some(key);
+        }
+    }
+    Ok(())
+}
+
+// Строка без единой величины пропускается: плата в тот раз ничего не прислала
+fn push(points: &mut Vec<Point>, time: Option<f64>, measurements: Vec<data::Measurement>) -> Result<(), String> {
+    let time = time.ok_or("missing time")?;
+    if !measurements.is_empty() {
+        points.push(Point { time, measurements });
+    }
+    Ok(())
+}
+
+fn column(header: &str) -> Column {
+    let header = header.trim();
+    let (name, unit) = match header.split_once('(') {
+        Some((name, unit)) if unit.ends_with(')') => (name.trim(), unit.trim_end_matches(')').trim()),
+        _ => (header, ""),
+    };
+    let name = name.to_lowercase();
+    if TIME_COLUMNS.contains(&name.as_str()) {
+        Column::Time
+    } else if SKIPPED_COLUMNS.contains(&name.as_str()) || name.starts_with("raw_") || name.is_empty() {
+        Column::Skip
+    } else {
+        Column::Metric {
+            metric: name,
+            unit: unit.to_string(),
+        }
+    }
+}
+
+fn measurement(metric: &str, value: f64, unit: &str) -> data::Measurement {
+    data::Measurement {
+        metric: metric.to_string(),
+        value,
+        unit: unit.to_string(),
+    }
+}
+
+fn parse_time(value: &str) -> Result<f64, String> {
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Ok(seconds);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp_micros() as f64 / 1e6);
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .map(|time| time.and_utc().timestamp_micros() as f64 / 1e6)
+        .map_err(|_| format!("invalid time {:?}", value))
+}
@@ -0,0 +1,44 @@
+// Встроенные значения совпадают с примером client.toml, а ошибка в
+// значении называет место, откуда оно взято.
+
+use client::config::{Config, ConfigError};
+use std::path::Path;
+use std::{fs, process};
+
+fn build(args: &[&str]) -> Result<Config, ConfigError> {
+    let args = ["client"].iter().chain(args).map(|a| a.to_string());
+    Config::build(args)
+}
+
+#[test]
+fn defaults_match_the_example_config() {
+    let example = Config::from_file(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/client.toml"))).unwrap();
+    let defaults = Config::default();
+    assert_eq!(defaults.retry.max_attempts, example.retry.max_attempts);
+    assert_eq!(defaults.retry.backoff_ms, example.retry.backoff_ms);
+    assert_eq!(defaults.retry.max_backoff_ms, example.retry.max_backoff_ms);
+    assert_eq!(defaults.health.every, example.health.every);
+    assert_eq!(defaults.ota.chunk_bytes, example.ota.chunk_bytes);
+}
+
+#[test]
+fn invalid_file_value_names_the_file() {
+    let path = std::env::temp_dir().join(format!("client-config-{}.toml", process::id()));
+    fs::write(&path, "[retry]\nmax_attempts = 0\n").unwrap();
+    let err = build(&["--config", path.to_str().unwrap()]).unwrap_err();
+    fs::remove_file(&path).unwrap();
+
+    match err {
+        ConfigError::Key { key, origin, .. } => {
+            assert_eq!(key, "retry.max_attempts");
+            assert_eq!(origin, path.display().to_string());
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn invalid_override_names_the_flag() {
+    let err = build(&["--set", "retry.max_attempts=0"]).unwrap_err();
+    assert!(err.to_string().contains("retry.max_attempts (from --set)"), "{}", err);
+}
@@ -0,0 +1,62 @@
+use crate::{FrameError, HEADER_LEN, MAX_FRAME_LEN};
+use bytes::{Buf, BufMut, BytesMut};
+use prost::Message;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Те же кадры `[длина u32 LE][protobuf]` для асинхронных соединений.
+///
+/// Декодер отдаёт тело кадра без разбора (как `read_body`), кодировщик
+/// принимает любое сообщение protobuf.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCodec;
+
+impl Decoder for FrameCodec {
+    type Item = BytesMut;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, FrameError> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes(src[..HEADER_LEN].try_into().unwrap()) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(FrameError::TooLarge(len));
+        }
+        if src.len() < HEADER_LEN + len {
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+        src.advance(HEADER_LEN);
+        Ok(Some(src.split_to(len)))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, FrameError> {
+        if let Some(body) = self.decode(src)? {
+            return Ok(Some(body));
+        }
+        if src.is_empty() {
+            return Ok(None);
+        }
+        // поток кончился посреди кадра
+        let (expected, received) = if src.len() < HEADER_LEN {
+            (HEADER_LEN, src.len())
+        } else {
+            let len = u32::from_le_bytes(src[..HEADER_LEN].try_into().unwrap()) as usize;
+            (len, src.len() - HEADER_LEN)
+        };
+        src.clear();
+        Err(FrameError::Truncated { expected, received })
+    }
+}
+
+impl<M: Message> Encoder<M> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, message: M, dst: &mut BytesMut) -> Result<(), FrameError> {
+        let len = message.encoded_len();
+        dst.reserve(HEADER_LEN + len);
+        dst.put_u32_le(len as u32);
+        message.encode(dst).expect("buffer has enough capacity");
+        Ok(())
+    }
+}
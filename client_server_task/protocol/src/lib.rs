@@ -0,0 +1,138 @@
+//! Протокол между платой и сервером: сообщения из `data.proto`
+//! и кадры вида `[длина u32 LE][protobuf]`.
+
+use prost::Message;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub mod data {
+    include!(concat!(env!("OUT_DIR"), "/_.rs"));
+}
+
+#[cfg(feature = "codec")]
+mod codec;
+#[cfg(feature = "codec")]
+pub use codec::FrameCodec;
+
+pub const HEADER_LEN: usize = 4;
+
+/// Кадры длиннее этого не принимаются: настоящие сообщения в сотни раз
+/// меньше, а испорченная длина не должна заставлять выделять гигабайты.
+pub const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    /// Поток оборвался посреди кадра.
+    Truncated { expected: usize, received: usize },
+    TooLarge(usize),
+    Decode(prost::DecodeError),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "{}", e),
+            FrameError::Truncated { expected, received } => {
+                write!(f, "truncated frame: expected {} bytes, got {}", expected, received)
+            }
+            FrameError::TooLarge(len) => {
+                write!(f, "frame of {} bytes exceeds limit of {}", len, MAX_FRAME_LEN)
+            }
+            FrameError::Decode(e) => write!(f, "decode error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+impl From<FrameError> for io::Error {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::Io(e) => e,
+            FrameError::Truncated { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Сообщение, которое передаётся кадрами. Реализовано для всех сообщений protobuf.
+pub trait Frame: Message + Default {
+    /// Кадр целиком: длина и тело.
+    fn encode_frame(&self) -> Vec<u8> {
+        let len = self.encoded_len() as u32;
+        let mut frame = Vec::with_capacity(HEADER_LEN + len as usize);
+        frame.extend_from_slice(&len.to_le_bytes());
+        self.encode(&mut frame).expect("Vec has enough capacity");
+        frame
+    }
+
+    fn write_frame(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.encode_frame())?;
+        out.flush()
+    }
+
+    /// Разбирает тело кадра, уже без длины.
+    fn decode_frame(body: &[u8]) -> Result<Self, FrameError> {
+        Self::decode(body).map_err(FrameError::Decode)
+    }
+
+    /// Следующий кадр из потока; `None` - поток закончился ровно между кадрами.
+    fn read_frame(input: &mut impl Read) -> Result<Option<Self>, FrameError> {
+        read_body(input)?
+            .map(|body| Self::decode_frame(&body))
+            .transpose()
+    }
+}
+
+impl<M: Message + Default> Frame for M {}
+
+/// Тело следующего кадра без разбора: сервер сохраняет кадры как есть
+/// до декодирования. `None` - поток закончился ровно между кадрами.
+pub fn read_body(input: &mut impl Read) -> Result<Option<Vec<u8>>, FrameError> {
+    let mut header = [0u8; HEADER_LEN];
+    let received = read_full(input, &mut header)?;
+    if received == 0 {
+        return Ok(None);
+    }
+    if received < HEADER_LEN {
+        return Err(FrameError::Truncated {
+            expected: HEADER_LEN,
+            received,
+        });
+    }
+
+    let len = u32::from_le_bytes(header) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(FrameError::TooLarge(len));
+    }
+    let mut body = vec![0u8; len];
+    let received = read_full(input, &mut body)?;
+    if received < len {
+        return Err(FrameError::Truncated {
+            expected: len,
+            received,
+        });
+    }
+    Ok(Some(body))
+}
+
+// Как read_exact, но при EOF сообщает, сколько успели прочитать
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
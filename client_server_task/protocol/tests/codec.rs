@@ -0,0 +1,79 @@
+// FrameCodec должен понимать ровно те же кадры, что и синхронный read_body,
+// как бы поток ни был порезан на куски при чтении из сокета.
+
+use bytes::BytesMut;
+use protocol::data::Data;
+use protocol::{Frame, FrameCodec, FrameError};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio_util::codec::{Decoder, Encoder};
+
+const CASES: usize = 200;
+
+fn random_data(rng: &mut StdRng) -> Data {
+    Data {
+        device_id: rng.random(),
+        event_id: rng.random(),
+        humidity: rng.random_range(0.0..100.0),
+        temperature: rng.random_range(-40.0..80.0),
+        read_time: Some(prost_types::Timestamp {
+            seconds: rng.random_range(0..4_000_000_000),
+            nanos: rng.random_range(0..1_000_000_000),
+        }),
+        health: None,
+        measurements: Vec::new(),
+        firmware_request: None,
+    }
+}
+
+// Подаёт поток в декодер случайными кусками, как их отдаёт сеть
+fn decode_chunked(stream: &[u8], rng: &mut StdRng) -> Result<Vec<Data>, FrameError> {
+    let mut codec = FrameCodec;
+    let mut buf = BytesMut::new();
+    let mut decoded = Vec::new();
+    let mut rest = stream;
+    while !rest.is_empty() {
+        let n = rng.random_range(1..=rest.len().min(64));
+        buf.extend_from_slice(&rest[..n]);
+        rest = &rest[n..];
+        while let Some(body) = codec.decode(&mut buf)? {
+            decoded.push(Data::decode_frame(&body)?);
+        }
+    }
+    while let Some(body) = codec.decode_eof(&mut buf)? {
+        decoded.push(Data::decode_frame(&body)?);
+    }
+    Ok(decoded)
+}
+
+#[test]
+fn chunked_stream_round_trips() {
+    let mut rng = StdRng::seed_from_u64(0xc0de);
+    for case in 0..CASES {
+        let messages: Vec<Data> = (0..rng.random_range(1..30)).map(|_| random_data(&mut rng)).collect();
+        let stream: Vec<u8> = messages.iter().flat_map(|m| m.encode_frame()).collect();
+        assert_eq!(decode_chunked(&stream, &mut rng).unwrap(), messages, "case {}", case);
+    }
+}
+
+#[test]
+fn encoder_matches_encode_frame() {
+    let mut rng = StdRng::seed_from_u64(0xc0de);
+    for case in 0..CASES {
+        let data = random_data(&mut rng);
+        let mut buf = BytesMut::new();
+        FrameCodec.encode(data.clone(), &mut buf).unwrap();
+        assert_eq!(&buf[..], &data.encode_frame()[..], "case {}", case);
+    }
+}
+
+#[test]
+fn truncated_stream_is_an_error() {
+    let mut rng = StdRng::seed_from_u64(0xc0de);
+    for case in 0..CASES {
+        let frame = random_data(&mut rng).encode_frame();
+        let cut = rng.random_range(1..frame.len());
+        let result = decode_chunked(&frame[..cut], &mut rng);
+        assert!(matches!(result, Err(FrameError::Truncated { .. })), "case {}: {:?}", case, result);
+    }
+}
@@ -0,0 +1,182 @@
+// Свойства кодека на случайных сообщениях. Генератор с фиксированным
+// зерном, так что падение воспроизводится; номер случая есть в сообщении.
+
+use protocol::data::command::Action;
+use protocol::data::{
+    Command, Data, FirmwareChunk, FirmwareRequest, Health, Measurement, Recalibrate, Reboot, Reply, SetInterval,
+    UpdateFirmware,
+};
+use protocol::{read_body, Frame, FrameError, HEADER_LEN, MAX_FRAME_LEN};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io::Cursor;
+
+const CASES: usize = 500;
+
+fn rng() -> StdRng {
+    StdRng::seed_from_u64(0x5e45_0d47)
+}
+
+fn random_data(rng: &mut StdRng) -> Data {
+    Data {
+        device_id: rng.random(),
+        event_id: rng.random(),
+        humidity: rng.random_range(-1000.0..1000.0),
+        temperature: rng.random_range(-1000.0..1000.0),
+        read_time: rng.random_bool(0.9).then(|| prost_types::Timestamp {
+            seconds: rng.random_range(0..4_000_000_000),
+            nanos: rng.random_range(0..1_000_000_000),
+        }),
+        health: rng.random_bool(0.3).then(|| random_health(rng)),
+        measurements: (0..rng.random_range(0..4)).map(|_| random_measurement(rng)).collect(),
+        firmware_request: rng.random_bool(0.1).then(|| FirmwareRequest {
+            version: random_version(rng),
+            offset: rng.random(),
+            max_len: rng.random(),
+        }),
+    }
+}
+
+fn random_measurement(rng: &mut StdRng) -> Measurement {
+    let known = [("temperature", "°C"), ("humidity", "%"), ("pressure", "Pa"), ("co2", "ppm")];
+    let (metric, unit) = known[rng.random_range(0..known.len())];
+    Measurement {
+        metric: metric.to_string(),
+        value: rng.random_range(-1e6..1e6),
+        unit: unit.to_string(),
+    }
+}
+
+fn random_health(rng: &mut StdRng) -> Health {
+    Health {
+        battery_voltage: rng.random_range(2.5..4.5),
+        rssi_dbm: rng.random_range(-100..0),
+        uptime_secs: rng.random(),
+        free_heap_bytes: rng.random(),
+        firmware_version: random_version(rng),
+    }
+}
+
+fn random_version(rng: &mut StdRng) -> String {
+    format!("{}.{}.{}", rng.random::<u8>(), rng.random::<u8>(), rng.random::<u8>())
+}
+
+fn random_reply(rng: &mut StdRng) -> Reply {
+    let count = rng.random_range(0..5);
+    let commands = (0..count)
+        .map(|_| {
+            let action = match rng.random_range(0..5) {
+                0 => Some(Action::SetInterval(SetInterval {
+                    interval_ms: rng.random(),
+                })),
+                1 => Some(Action::Reboot(Reboot {})),
+                2 => Some(Action::Recalibrate(Recalibrate {
+                    temperature_offset: rng.random_range(-50.0..50.0),
+                    humidity_offset: rng.random_range(-50.0..50.0),
+                })),
+                3 => Some(Action::UpdateFirmware(UpdateFirmware {
+                    version: random_version(rng),
+                    size: rng.random(),
+                    sha256: format!("{:064x}", rng.random::<u128>()),
+                })),
+                _ => None,
+            };
+            Command {
+                command_id: rng.random(),
+                action,
+            }
+        })
+        .collect();
+    let firmware_chunk = rng.random_bool(0.2).then(|| FirmwareChunk {
+        version: random_version(rng),
+        offset: rng.random(),
+        data: (0..rng.random_range(0..256)).map(|_| rng.random()).collect(),
+        total_size: rng.random(),
+        error: String::new(),
+    });
+    Reply {
+        commands,
+        firmware_chunk,
+    }
+}
+
+#[test]
+fn data_round_trips() {
+    let mut rng = rng();
+    for case in 0..CASES {
+        let data = random_data(&mut rng);
+        let frame = data.encode_frame();
+        assert_eq!(frame.len(), HEADER_LEN + u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize);
+
+        let mut input = Cursor::new(frame);
+        let decoded = Data::read_frame(&mut input).unwrap();
+        assert_eq!(decoded, Some(data), "case {}", case);
+        assert!(Data::read_frame(&mut input).unwrap().is_none(), "case {}", case);
+    }
+}
+
+#[test]
+fn reply_round_trips() {
+    let mut rng = rng();
+    for case in 0..CASES {
+        let reply = random_reply(&mut rng);
+        let mut stream = Vec::new();
+        reply.write_frame(&mut stream).unwrap();
+        let decoded = Reply::read_frame(&mut Cursor::new(stream)).unwrap();
+        assert_eq!(decoded, Some(reply), "case {}", case);
+    }
+}
+
+// Так шлёт клиент с накопленной очередью: много кадров подряд в одном соединении
+#[test]
+fn frame_stream_round_trips() {
+    let mut rng = rng();
+    for case in 0..CASES / 10 {
+        let messages: Vec<Data> = (0..rng.random_range(0..20)).map(|_| random_data(&mut rng)).collect();
+        let stream: Vec<u8> = messages.iter().flat_map(|m| m.encode_frame()).collect();
+
+        let mut input = Cursor::new(stream);
+        let mut decoded = Vec::new();
+        while let Some(message) = Data::read_frame(&mut input).unwrap() {
+            decoded.push(message);
+        }
+        assert_eq!(decoded, messages, "case {}", case);
+    }
+}
+
+#[test]
+fn truncated_frame_is_an_error() {
+    let mut rng = rng();
+    for case in 0..CASES {
+        let frame = random_data(&mut rng).encode_frame();
+        let cut = rng.random_range(1..frame.len());
+        match Data::read_frame(&mut Cursor::new(&frame[..cut])) {
+            Err(FrameError::Truncated { expected, received }) => {
+                assert!(received < expected, "case {}", case);
+            }
+            other => panic!("case {}: cut at {} of {}: {:?}", case, cut, frame.len(), other),
+        }
+    }
+}
+
+#[test]
+fn oversized_length_is_rejected_before_reading() {
+    let len = (MAX_FRAME_LEN + 1) as u32;
+    let mut input = Cursor::new(len.to_le_bytes().to_vec());
+    assert!(matches!(read_body(&mut input), Err(FrameError::TooLarge(n)) if n == len as usize));
+}
+
+#[test]
+fn garbage_bodies_never_panic() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let body: Vec<u8> = (0..rng.random_range(0..64)).map(|_| rng.random()).collect();
+        let mut frame = (body.len() as u32).to_le_bytes().to_vec();
+        frame.extend_from_slice(&body);
+        // либо разбирается, либо ошибка декодирования - но не паника и не другой вид ошибки
+        match Data::read_frame(&mut Cursor::new(frame)) {
+            Ok(Some(_)) | Err(FrameError::Decode(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Нагрузочный замер асинхронного приёма: тысячи одновременно открытых
+// соединений, каждое шлёт несколько показаний и ждёт ответа на каждое.
+//
+//     cargo bench -p server --bench connections
+//
+// BENCH_CONNECTIONS (по умолчанию 2000) и BENCH_FRAMES (10 на соединение).
+// С DATABASE_URL показания пишутся в Postgres через PgPool, иначе - в память.
+// Каждое соединение - два дескриптора в этом процессе, проверьте `ulimit -n`.
+
+use futures_util::{SinkExt, StreamExt};
+use protocol::data::Data;
+use protocol::FrameCodec;
+use server::commands::CommandQueue;
+use server::db::Database;
+use server::firmware::FirmwareStore;
+use server::live::Broadcaster;
+use server::metrics::Metrics;
+use server::pipeline::Pipeline;
+use server::pool::PgPool;
+use server::store::{AsyncStore, MemoryStore};
+use server::Ingest;
+use std::env;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Barrier;
+use tokio_util::codec::Framed;
+
+// Номера «плат» замера, чтобы не смешаться с настоящими устройствами в общей БД
+const FIRST_DEVICE_ID: u32 = 900_000;
+
+fn main() {
+    let connections = env_or("BENCH_CONNECTIONS", 2000);
+    let frames = env_or("BENCH_FRAMES", 10);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    if env::var("DATABASE_URL").is_ok() {
+        Database::new();
+        println!("Store: Postgres");
+        runtime.block_on(run(PgPool::from_env(), connections, frames));
+    } else {
+        println!("Store: memory");
+        runtime.block_on(run(MemoryStore::default(), connections, frames));
+    }
+}
+
+async fn run<S: AsyncStore>(store: S, connections: usize, frames: usize) {
+    let metrics = Metrics::shared();
+    let ingest = Ingest::new(metrics.clone());
+    let pipeline = Arc::new(Pipeline::new(
+        ingest,
+        store,
+        None,
+        CommandQueue::shared(),
+        Broadcaster::shared(),
+        FirmwareStore::new(env::temp_dir()),
+    ));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(pipeline.serve(listener));
+
+    // сначала открываются все соединения, потом все разом начинают слать
+    let gate = Arc::new(Barrier::new(connections + 1));
+    let tasks: Vec<_> = (0..connections)
+        .map(|device| {
+            let gate = Arc::clone(&gate);
+            tokio::spawn(async move {
+                let socket = TcpStream::connect(addr).await;
+                gate.wait().await;
+                device_session(socket?, FIRST_DEVICE_ID + device as u32, frames).await
+            })
+        })
+        .collect();
+    gate.wait().await;
+    println!("{} connections open", connections);
+
+    let started = Instant::now();
+    let mut latencies = Vec::with_capacity(connections * frames);
+    let mut failed = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(session) => latencies.extend(session),
+            Err(e) => {
+                failed += 1;
+                if failed == 1 {
+                    eprintln!("Connection failed: {}", e);
+                }
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort();
+    let percentile = |p: f64| match latencies.len() {
+        0 => Duration::ZERO,
+        n => latencies[((n - 1) as f64 * p) as usize],
+    };
+    println!("connections: {} ok, {} failed", connections - failed, failed);
+    println!(
+        "frames:      {} in {:.2?} ({:.0} frames/s)",
+        latencies.len(),
+        elapsed,
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "latency:     p50 {:.2?}, p99 {:.2?}, max {:.2?}",
+        percentile(0.50),
+        percentile(0.99),
+        percentile(1.0)
+    );
+    println!("rejected:    {}", metrics.lock().unwrap().frames_rejected);
+}
+
+// Одна «плата»: показания по очереди, каждое после ответа на предыдущее
+async fn device_session(socket: TcpStream, device_id: u32, frames: usize) -> io::Result<Vec<Duration>> {
+    let mut framed = Framed::new(socket, FrameCodec);
+    let mut latencies = Vec::with_capacity(frames);
+    for event_id in 0..frames {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let data = Data {
+            device_id,
+            event_id: event_id as u64,
+            humidity: 40.0 + (event_id % 7) as f32,
+            temperature: 20.0 + (event_id % 5) as f32,
+            read_time: Some(prost_types::Timestamp {
+                seconds: now.as_secs() as i64,
+                nanos: now.subsec_nanos() as i32,
+            }),
+            health: None,
+            measurements: Vec::new(),
+            firmware_request: None,
+        };
+
+        let sent = Instant::now();
+        framed.send(data).await?;
+        match framed.next().await {
+            Some(Ok(_reply)) => latencies.push(sent.elapsed()),
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed connection")),
+        }
+    }
+    Ok(latencies)
+}
+
+fn env_or(key: &str, default: usize) -> usize {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
@@ -1,6 +0,0 @@
-use std::io::Result;
-
-fn main() -> Result<()> {
-    prost_build::compile_protos(&["src/data.proto"], &["src/"])?;
-    Ok(())
-}
\ No newline at end of file
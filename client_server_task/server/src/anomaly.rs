@@ -0,0 +1,179 @@
+use crate::calibration::Metric;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// Значение далеко от среднего по скользящему окну.
+    ZScore,
+    /// Сглаженное значение вышло за контрольные границы (медленный дрейф).
+    Ewma,
+    /// Значение не меняется слишком долго - датчик, скорее всего, завис.
+    FlatLine,
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AnomalyKind::ZScore => "zscore",
+            AnomalyKind::Ewma => "ewma",
+            AnomalyKind::FlatLine => "flatline",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub metric: Metric,
+    pub value: f64,
+    pub score: f64,
+    pub message: String,
+}
+
+pub struct AnomalyConfig {
+    /// Размер скользящего окна и минимум точек до первых проверок.
+    pub window: usize,
+    pub z_threshold: f64,
+    /// Вес нового значения в EWMA и ширина контрольных границ в сигмах.
+    pub ewma_lambda: f64,
+    pub ewma_width: f64,
+    /// Сколько одинаковых подряд значений считать зависанием.
+    pub flat_count: usize,
+    pub flat_epsilon: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        AnomalyConfig {
+            window: 60,
+            z_threshold: 3.0,
+            ewma_lambda: 0.2,
+            ewma_width: 3.0,
+            flat_count: 30,
+            flat_epsilon: 1e-3,
+        }
+    }
+}
+
+impl AnomalyConfig {
+    /// `ANOMALY_WINDOW`, `ANOMALY_Z_THRESHOLD`, `ANOMALY_FLAT_COUNT`.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(window) = env_parse("ANOMALY_WINDOW") {
+            config.window = window;
+        }
+        if let Some(z) = env_parse("ANOMALY_Z_THRESHOLD") {
+            config.z_threshold = z;
+        }
+        if let Some(count) = env_parse("ANOMALY_FLAT_COUNT") {
+            config.flat_count = count;
+        }
+        config
+    }
+
+    fn warmup(&self) -> usize {
+        (self.window / 2).max(5)
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    window: VecDeque<f64>,
+    ewma: Option<f64>,
+    ewma_alarm: bool,
+    last: Option<f64>,
+    flat_run: usize,
+}
+
+/// Состояние детекторов по каждому устройству и метрике, только в памяти.
+/// Об EWMA и зависании сообщается один раз при входе в состояние,
+/// а не на каждое показание, пока оно длится.
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    series: HashMap<(u32, Metric), Series>,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: AnomalyConfig) -> Self {
+        AnomalyDetector {
+            config,
+            series: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, device_id: u32, metric: Metric, value: f64) -> Vec<Anomaly> {
+        let config = &self.config;
+        let series = self.series.entry((device_id, metric)).or_default();
+        let mut anomalies = Vec::new();
+        let mut report = |kind, score, message: String| {
+            anomalies.push(Anomaly {
+                kind,
+                metric,
+                value,
+                score,
+                message,
+            })
+        };
+
+        // зависание не зависит от окна: считаем одинаковые значения подряд
+        match series.last {
+            Some(last) if (value - last).abs() <= config.flat_epsilon => series.flat_run += 1,
+            _ => series.flat_run = 1,
+        }
+        series.last = Some(value);
+        if series.flat_run == config.flat_count {
+            report(
+                AnomalyKind::FlatLine,
+                series.flat_run as f64,
+                format!("{} stuck at {:.2} for {} readings", metric, value, series.flat_run),
+            );
+        }
+
+        if series.window.len() >= config.warmup() {
+            let (mean, std_dev) = mean_std(&series.window);
+            if std_dev > 0.0 {
+                let z = (value - mean) / std_dev;
+                if z.abs() > config.z_threshold {
+                    report(
+                        AnomalyKind::ZScore,
+                        z,
+                        format!("{} {:.2} is {:.1} sigma from mean {:.2}", metric, value, z, mean),
+                    );
+                }
+
+                let lambda = config.ewma_lambda;
+                let ewma = lambda * value + (1.0 - lambda) * series.ewma.unwrap_or(mean);
+                let limit = config.ewma_width * std_dev * (lambda / (2.0 - lambda)).sqrt();
+                let out_of_control = (ewma - mean).abs() > limit;
+                if out_of_control && !series.ewma_alarm {
+                    report(
+                        AnomalyKind::Ewma,
+                        (ewma - mean) / limit,
+                        format!("{} trend {:.2} outside {:.2} ± {:.2}", metric, ewma, mean, limit),
+                    );
+                }
+                series.ewma = Some(ewma);
+                series.ewma_alarm = out_of_control;
+            }
+        }
+
+        series.window.push_back(value);
+        if series.window.len() > config.window {
+            series.window.pop_front();
+        }
+        anomalies
+    }
+}
+
+fn mean_std(values: &VecDeque<f64>) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok().and_then(|v| v.parse().ok())
+}
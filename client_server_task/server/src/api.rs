@@ -0,0 +1,247 @@
+use crate::calibration::CalibrationRequest;
+use crate::commands::{CommandRequest, SharedCommands};
+use crate::db::{Database, SharedDatabase};
+use crate::export::{self, ExportQuery};
+use crate::health::Readiness;
+use crate::http::{Request, Response};
+use crate::live::Broadcaster;
+use crate::metrics::SharedMetrics;
+use crate::tenant;
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Комментарий-пинг, чтобы прокси не закрывали молчащий поток
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Маршруты служебного HTTP-интерфейса сервера.
+pub struct Api {
+    pub metrics: SharedMetrics,
+    pub commands: SharedCommands,
+    pub live: Arc<Broadcaster>,
+    pub readiness: Arc<Readiness>,
+    /// Токен админа (`ADMIN_TOKEN`) для `Authorization: Bearer`. Без него
+    /// админского доступа нет ни у кого, работают только токены команд.
+    pub admin_token: Option<String>,
+    pub db: SharedDatabase,
+}
+
+/// Кто обращается: админ видит все устройства, команда - только свои.
+enum Caller {
+    Admin,
+    Tenant(String),
+}
+
+impl Api {
+    pub fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            // процесс жив и отвечает; базу не трогает
+            ("GET", ["healthz"]) => Response::text(200, "ok\n"),
+            ("GET", ["readyz"]) => match self.readiness.check() {
+                Ok(()) => Response::text(200, "ready\n"),
+                Err(e) => Response::text(503, format!("{}\n", e)),
+            },
+            // счётчики и состояние всех устройств: только для админа и сборщика метрик
+            ("GET", ["metrics"]) => {
+                if let Err(response) = self.caller(request).and_then(admin_only) {
+                    return response;
+                }
+                let mut body = self.metrics.lock().unwrap().render();
+                body.push_str("# TYPE sensor_live_subscribers gauge\n");
+                body.push_str(&format!("sensor_live_subscribers {}\n", self.live.subscriber_count()));
+                Response::new(200, "text/plain; version=0.0.4", body)
+            }
+            ("GET", ["live"]) => match self.caller(request) {
+                Ok(caller) => self.live_stream(request, caller),
+                Err(response) => response,
+            },
+            ("GET", ["export"]) => match self.caller(request) {
+                Ok(caller) => self.export(request, caller),
+                Err(response) => response,
+            },
+            (method, ["devices", device_id, resource @ ("commands" | "calibration")]) => {
+                let caller = match self.caller(request) {
+                    Ok(caller) => caller,
+                    Err(response) => return response,
+                };
+                let device_id = match device_id.parse::<u32>() {
+                    Ok(id) => id,
+                    Err(_) => return Response::text(400, "invalid device id\n"),
+                };
+                if let Err(response) = self.check_device(&caller, device_id) {
+                    return response;
+                }
+                match (method, *resource) {
+                    ("GET", "commands") => self.list_commands(device_id),
+                    ("POST", "commands") => self.queue_command(device_id, &request.body),
+                    ("GET", "calibration") => self.list_calibrations(device_id),
+                    ("POST", "calibration") => self.set_calibration(device_id, &request.body),
+                    _ => Response::text(405, "method not allowed\n"),
+                }
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    // Server-Sent Events: GET /live?device=121,122 (без параметра - все устройства)
+    fn live_stream(&self, request: &Request, caller: Caller) -> Response {
+        let devices = match request.query.get("device").filter(|v| !v.is_empty()) {
+            Some(list) => match list.split(',').map(|id| id.trim().parse::<u32>()).collect() {
+                Ok(devices) => Some(devices),
+                Err(_) => return Response::text(400, "invalid device list\n"),
+            },
+            None => None::<HashSet<u32>>,
+        };
+
+        let tenant_id = match caller {
+            Caller::Admin => None,
+            Caller::Tenant(id) => Some(id),
+        };
+        let events = self.live.subscribe(tenant_id, devices);
+        Response::stream("text/event-stream", move |out| {
+            out.write_all(b": connected\n\n")?;
+            out.flush()?;
+            loop {
+                match events.recv_timeout(KEEPALIVE) {
+                    Ok(event) => write!(out, "event: reading\ndata: {}\n\n", event)?,
+                    Err(RecvTimeoutError::Timeout) => out.write_all(b": keepalive\n\n")?,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+                out.flush()?;
+            }
+        })
+    }
+
+    // GET /export?device=121&from=...&to=...&format=csv|parquet|jsonl[&tenant=...]
+    fn export(&self, request: &Request, caller: Caller) -> Response {
+        let mut query = match ExportQuery::from_params(|name| request.query.get(name).cloned()) {
+            Ok(query) => query,
+            Err(e) => return Response::text(400, format!("{}\n", e)),
+        };
+        // команда выгружает только своё, что бы ни было в параметре tenant
+        if let Caller::Tenant(id) = caller {
+            query.tenant_id = Some(id);
+        }
+        let mut db = match Database::connect() {
+            Ok(db) => db,
+            Err(e) => return Response::text(500, format!("{}\n", e)),
+        };
+        // заголовки уже отправлены, так что об ошибке клиент узнает только по оборванному ответу
+        Response::stream(query.format.content_type(), move |out| {
+            export::export(&mut db, &query, out)
+                .map(|_| ())
+                .map_err(|e| std::io::Error::other(e.to_string()))
+        })
+    }
+
+    // ADMIN_TOKEN - админ, токен команды - команда, остальные получают 401
+    fn caller(&self, request: &Request) -> Result<Caller, Response> {
+        let unauthorized = || Response::text(401, "unauthorized\n");
+        let token = request.bearer_token().ok_or_else(unauthorized)?;
+        if Some(token) == self.admin_token.as_deref() {
+            return Ok(Caller::Admin);
+        }
+        // не похожее на токен команды незачем искать в базе
+        if !tenant::is_token(token) {
+            return Err(unauthorized());
+        }
+        match self.db.with(|db| db.tenant_by_token(&tenant::hash_token(token))) {
+            Ok(Some(id)) => Ok(Caller::Tenant(id)),
+            Ok(None) => Err(unauthorized()),
+            Err(e) => Err(Response::text(500, format!("{}\n", e))),
+        }
+    }
+
+    // Чужое устройство для команды не существует
+    fn check_device(&self, caller: &Caller, device_id: u32) -> Result<(), Response> {
+        let Caller::Tenant(tenant_id) = caller else {
+            return Ok(());
+        };
+        match self.db.with(|db| db.device_tenant(device_id)) {
+            Ok(Some(owner)) if owner == *tenant_id => Ok(()),
+            Ok(_) => Err(Response::text(404, "unknown device\n")),
+            Err(e) => Err(Response::text(500, format!("{}\n", e))),
+        }
+    }
+
+    fn list_commands(&self, device_id: u32) -> Response {
+        let pending = self.commands.lock().unwrap().pending(device_id);
+        json(200, &pending)
+    }
+
+    fn queue_command(&self, device_id: u32, body: &[u8]) -> Response {
+        let command: CommandRequest = match serde_json::from_slice(body) {
+            Ok(command) => command,
+            Err(e) => return Response::text(400, format!("invalid command: {}\n", e)),
+        };
+        let command = match command {
+            CommandRequest::SetInterval { interval_ms: 0 } => {
+                return Response::text(400, "interval_ms must be positive\n");
+            }
+            CommandRequest::UpdateFirmware { version, .. } => {
+                match self.db.with(|db| db.firmware(&version)) {
+                    Ok(Some(firmware)) => CommandRequest::UpdateFirmware {
+                        version,
+                        size: firmware.size,
+                        sha256: firmware.sha256,
+                    },
+                    Ok(None) => return Response::text(400, format!("unknown firmware version {}\n", version)),
+                    Err(e) => return Response::text(500, format!("{}\n", e)),
+                }
+            }
+            command => command,
+        };
+        let pending = self.commands.lock().unwrap().push(device_id, command);
+        json(202, &pending)
+    }
+}
+
+impl Api {
+    fn list_calibrations(&self, device_id: u32) -> Response {
+        let result = self.db.with(|db| db.calibrations(Some(device_id)));
+        match result {
+            Ok(rows) => {
+                let list: Vec<_> = rows.into_iter().map(|(_, calibration)| calibration).collect();
+                json(200, &list)
+            }
+            Err(e) => Response::text(500, format!("{}\n", e)),
+        }
+    }
+
+    // Сервер подхватит новую калибровку при следующем перечитывании
+    fn set_calibration(&self, device_id: u32, body: &[u8]) -> Response {
+        let request: CalibrationRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return Response::text(400, format!("invalid calibration: {}\n", e)),
+        };
+        let result = self.db.with(|db| {
+            let calibration = match request.resolve(db, device_id) {
+                Ok(calibration) => calibration,
+                Err(e) => return Ok(Err(e)),
+            };
+            db.insert_calibration(device_id, &calibration).map(Ok)
+        });
+        match result {
+            Ok(Ok(calibration)) => json(201, &calibration),
+            Ok(Err(e)) => Response::text(400, format!("{}\n", e)),
+            Err(e) => Response::text(500, format!("{}\n", e)),
+        }
+    }
+}
+
+fn admin_only(caller: Caller) -> Result<(), Response> {
+    match caller {
+        Caller::Admin => Ok(()),
+        Caller::Tenant(_) => Err(Response::text(403, "forbidden\n")),
+    }
+}
+
+fn json<T: serde::Serialize>(status: u16, value: &T) -> Response {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::new(status, "application/json", body),
+        Err(e) => Response::text(500, format!("{}\n", e)),
+    }
+}
@@ -0,0 +1,168 @@
+use crate::db::Database;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    Temperature,
+    Humidity,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Temperature => "temperature",
+            Metric::Humidity => "humidity",
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "temperature" => Ok(Metric::Temperature),
+            "humidity" => Ok(Metric::Humidity),
+            _ => Err(format!("unknown metric {:?}, expected temperature or humidity", s)),
+        }
+    }
+}
+
+/// Линейная поправка `value = raw * gain + offset`, действующая
+/// с `valid_from` до `valid_to` (открытый конец - до сих пор).
+#[derive(Debug, Clone, Serialize)]
+pub struct Calibration {
+    pub metric: Metric,
+    pub gain: f64,
+    pub offset: f64,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+impl Calibration {
+    pub fn apply(&self, raw: f64) -> f64 {
+        raw * self.gain + self.offset
+    }
+
+    fn covers(&self, at: DateTime<Utc>) -> bool {
+        self.valid_from <= at && self.valid_to.is_none_or(|to| at < to)
+    }
+}
+
+/// Вставляет калибровку в историю одного устройства и метрики. Новая
+/// действует на всём своём интервале: прежние обрезаются или делятся
+/// вокруг него, с тем же началом - заменяются. Открытый конец задним
+/// числом тянется только до следующей по времени калибровки.
+pub fn insert(history: Vec<Calibration>, mut calibration: Calibration) -> Vec<Calibration> {
+    let from = calibration.valid_from;
+    if calibration.valid_to.is_none() {
+        calibration.valid_to = history.iter().map(|c| c.valid_from).filter(|&t| t > from).min();
+    }
+    let to = calibration.valid_to;
+
+    let mut result = Vec::with_capacity(history.len() + 2);
+    for old in history {
+        if old.valid_from < from {
+            let mut head = old.clone();
+            head.valid_to = Some(old.valid_to.map_or(from, |t| t.min(from)));
+            result.push(head);
+        }
+        if let Some(to) = to {
+            if old.valid_to.is_none_or(|t| t > to) {
+                let mut tail = old;
+                tail.valid_from = tail.valid_from.max(to);
+                result.push(tail);
+            }
+        }
+    }
+    result.push(calibration);
+    result.sort_by_key(|c| c.valid_from);
+    result
+}
+
+/// Калибровки всех устройств в памяти, чтобы не ходить в БД на каждое показание.
+#[derive(Default)]
+pub struct Calibrations {
+    by_device: HashMap<(u32, Metric), Vec<Calibration>>,
+}
+
+impl Calibrations {
+    pub fn new(rows: Vec<(u32, Calibration)>) -> Self {
+        let mut by_device: HashMap<(u32, Metric), Vec<Calibration>> = HashMap::new();
+        for (device_id, calibration) in rows {
+            by_device
+                .entry((device_id, calibration.metric))
+                .or_default()
+                .push(calibration);
+        }
+        Calibrations { by_device }
+    }
+
+    /// Применяет калибровку, действовавшую в момент `at`; без неё значение не меняется.
+    pub fn apply(&self, device_id: u32, metric: Metric, raw: f64, at: DateTime<Utc>) -> f64 {
+        self.by_device
+            .get(&(device_id, metric))
+            .and_then(|list| {
+                list.iter()
+                    .filter(|c| c.covers(at))
+                    .max_by_key(|c| c.valid_from)
+            })
+            .map_or(raw, |c| c.apply(raw))
+    }
+}
+
+/// Новая калибровка: либо явные `gain`/`offset`, либо эталонное значение
+/// `reference`, снятое рядом с устройством в момент `at` (по умолчанию -
+/// последнее показание). Во втором случае offset подбирается так, чтобы
+/// сырое показание совпало с эталоном.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalibrationRequest {
+    pub metric: Metric,
+    pub reference: Option<f64>,
+    pub at: Option<DateTime<Utc>>,
+    pub gain: Option<f64>,
+    pub offset: Option<f64>,
+    pub valid_from: Option<DateTime<Utc>>,
+}
+
+impl CalibrationRequest {
+    pub fn resolve(&self, db: &mut Database, device_id: u32) -> Result<Calibration, String> {
+        let gain = self.gain.unwrap_or(1.0);
+        if !gain.is_finite() || gain == 0.0 {
+            return Err("gain must be a non-zero number".to_string());
+        }
+
+        let offset = match (self.reference, self.offset) {
+            (Some(_), Some(_)) => return Err("use either reference or offset, not both".to_string()),
+            (Some(reference), None) => {
+                let at = self.at.unwrap_or_else(Utc::now);
+                let raw = db
+                    .raw_reading(device_id, self.metric, at)
+                    .map_err(|e| e.to_string())?
+                    .ok_or(format!("no {} readings from device {} before {}", self.metric, device_id, at))?;
+                reference - raw * gain
+            }
+            (None, offset) => offset.unwrap_or(0.0),
+        };
+
+        Ok(Calibration {
+            metric: self.metric,
+            gain,
+            offset,
+            valid_from: self.valid_from.unwrap_or_else(Utc::now),
+            valid_to: None,
+        })
+    }
+}
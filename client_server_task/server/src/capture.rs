@@ -0,0 +1,215 @@
+use chrono::Utc;
+use protocol::MAX_FRAME_LEN;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+
+// Каждый файл захвата начинается с этой сигнатуры
+const MAGIC: &[u8; 8] = b"SCAPv1\0\0";
+
+const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 16;
+
+// Столько кадров может ждать записи на диск; следующие в захват не попадут
+const QUEUE_LEN: usize = 4096;
+
+/// Сырой кадр, как он пришёл от устройства, вместе со временем приёма.
+pub struct CapturedFrame {
+    pub received: SystemTime,
+    pub frame: Vec<u8>,
+}
+
+/// Пишет все принятые кадры в ротируемые файлы захвата.
+///
+/// Формат записи: `[u64 LE наносекунды приёма][u32 LE длина][кадр]`,
+/// тот же length-prefix, что и в протоколе клиента.
+pub struct CaptureWriter {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    written: u64,
+}
+
+impl CaptureWriter {
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let file = new_capture_file(&dir)?;
+        let writer = CaptureWriter {
+            dir,
+            max_bytes,
+            max_files: max_files.max(1),
+            file,
+            written: MAGIC.len() as u64,
+        };
+        writer.remove_old_files()?;
+        Ok(writer)
+    }
+
+    /// Включается переменной `CAPTURE_DIR`; размер и число файлов
+    /// задаются `CAPTURE_MAX_BYTES` и `CAPTURE_MAX_FILES`.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let dir = match std::env::var("CAPTURE_DIR") {
+            Ok(dir) if !dir.is_empty() => dir,
+            _ => return Ok(None),
+        };
+        let max_bytes = env_or("CAPTURE_MAX_BYTES", DEFAULT_MAX_BYTES);
+        let max_files = env_or("CAPTURE_MAX_FILES", DEFAULT_MAX_FILES);
+        Self::open(dir, max_bytes, max_files).map(Some)
+    }
+
+    pub fn record(&mut self, received: SystemTime, frame: &[u8]) -> io::Result<()> {
+        let record_len = 12 + frame.len() as u64;
+        if self.written + record_len > self.max_bytes && self.written > MAGIC.len() as u64 {
+            self.rotate()?;
+        }
+
+        let nanos = received
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        self.file.write_all(&nanos.to_le_bytes())?;
+        self.file.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.file.write_all(frame)?;
+        // сбрасываем сразу: файл нужен именно тогда, когда что-то упало
+        self.file.flush()?;
+        self.written += record_len;
+        Ok(())
+    }
+
+    /// Переносит запись в отдельный поток: приём только кладёт кадр в
+    /// очередь и не ждёт диска. Поток завершается вместе с очередью.
+    pub fn spawn(mut self) -> CaptureQueue {
+        let (sender, frames) = mpsc::sync_channel::<CapturedFrame>(QUEUE_LEN);
+        thread::spawn(move || {
+            for captured in frames {
+                if let Err(e) = self.record(captured.received, &captured.frame) {
+                    error!(error = %e, "Capture error");
+                }
+            }
+        });
+        CaptureQueue(sender)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file = new_capture_file(&self.dir)?;
+        self.written = MAGIC.len() as u64;
+        self.remove_old_files()
+    }
+
+    // Оставляем только max_files последних файлов
+    fn remove_old_files(&self) -> io::Result<()> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| is_capture_file(path))
+            .collect();
+        files.sort();
+        while files.len() > self.max_files {
+            fs::remove_file(files.remove(0))?;
+        }
+        Ok(())
+    }
+}
+
+/// Очередь кадров к `CaptureWriter`, который пишет их в своём потоке.
+pub struct CaptureQueue(SyncSender<CapturedFrame>);
+
+impl CaptureQueue {
+    /// Не блокирует: если диск не успевает и очередь полна, кадр теряется для захвата.
+    pub fn record(&self, received: SystemTime, frame: &[u8]) {
+        let captured = CapturedFrame {
+            received,
+            frame: frame.to_vec(),
+        };
+        match self.0.try_send(captured) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!("Capture queue full, frame not captured"),
+            Err(TrySendError::Disconnected(_)) => error!("Capture writer stopped"),
+        }
+    }
+}
+
+/// Читает записи из файла захвата по порядку.
+pub struct CaptureReader<R> {
+    inner: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        inner.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a capture file"));
+        }
+        Ok(CaptureReader { inner })
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut header = [0u8; 12];
+        match self.inner.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        }
+
+        let nanos = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let len = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        // такой кадр сервер не принял бы: длина испорчена, а не кадр велик
+        if len > MAX_FRAME_LEN {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corrupt capture: record of {} bytes exceeds frame limit of {}", len, MAX_FRAME_LEN),
+            )));
+        }
+        let mut frame = vec![0u8; len];
+        if let Err(e) = self.inner.read_exact(&mut frame) {
+            return Some(Err(e));
+        }
+
+        Some(Ok(CapturedFrame {
+            received: UNIX_EPOCH + Duration::from_nanos(nanos),
+            frame,
+        }))
+    }
+}
+
+fn new_capture_file(dir: &Path) -> io::Result<BufWriter<File>> {
+    let name = format!("capture-{}.bin", Utc::now().format("%Y%m%dT%H%M%S%.6f"));
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dir.join(name))?;
+    let mut file = BufWriter::new(file);
+    file.write_all(MAGIC)?;
+    file.flush()?;
+    Ok(file)
+}
+
+fn is_capture_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("capture-") && name.ends_with(".bin"))
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,79 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+const DEFAULT_TOLERANCE_MS: i64 = 5_000;
+
+// Вес нового замера в сглаженной оценке смещения
+const SMOOTHING: f64 = 0.1;
+
+/// Результат сверки часов устройства с часами сервера для одного показания.
+#[derive(Clone)]
+pub struct ClockCheck {
+    pub received_at: DateTime<Utc>,
+    /// Оценка смещения: сколько нужно прибавить к часам устройства, мс.
+    pub offset_ms: i64,
+    pub skewed: bool,
+    pub corrected_time: Option<DateTime<Utc>>,
+}
+
+/// Оценивает смещение часов каждого устройства по разнице между
+/// временем приёма на сервере и `read_time` из показания.
+///
+/// Замер включает сетевую задержку, поэтому оценка сглаживается.
+/// Скачок больше допуска (перезагрузка платы, синхронизация NTP)
+/// сбрасывает оценку вместо медленного дрейфа к новому значению.
+pub struct ClockTracker {
+    tolerance_ms: i64,
+    correct: bool,
+    offsets: HashMap<u32, f64>,
+}
+
+impl ClockTracker {
+    pub fn new(tolerance_ms: i64, correct: bool) -> Self {
+        ClockTracker {
+            tolerance_ms,
+            correct,
+            offsets: HashMap::new(),
+        }
+    }
+
+    /// `CLOCK_SKEW_TOLERANCE_MS` - допуск, `CLOCK_CORRECT=true` - сохранять
+    /// исправленное время для показаний вне допуска.
+    pub fn from_env() -> Self {
+        let tolerance_ms = std::env::var("CLOCK_SKEW_TOLERANCE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TOLERANCE_MS);
+        let correct = std::env::var("CLOCK_CORRECT")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        Self::new(tolerance_ms, correct)
+    }
+
+    pub fn check(
+        &mut self,
+        device_id: u32,
+        read_time: DateTime<Utc>,
+        received_at: DateTime<Utc>,
+    ) -> ClockCheck {
+        let sample = (received_at - read_time).num_milliseconds() as f64;
+        let offset = self.offsets.entry(device_id).or_insert(sample);
+        if (sample - *offset).abs() > self.tolerance_ms as f64 {
+            *offset = sample;
+        } else {
+            *offset += SMOOTHING * (sample - *offset);
+        }
+
+        let offset_ms = offset.round() as i64;
+        let skewed = offset_ms.abs() > self.tolerance_ms;
+        let corrected_time = (skewed && self.correct)
+            .then(|| read_time + Duration::milliseconds(offset_ms));
+
+        ClockCheck {
+            received_at,
+            offset_ms,
+            skewed,
+            corrected_time,
+        }
+    }
+}
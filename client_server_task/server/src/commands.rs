@@ -0,0 +1,133 @@
+use crate::data;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+pub type SharedCommands = Arc<Mutex<CommandQueue>>;
+
+/// Команда в том виде, в каком её принимает и отдаёт HTTP API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CommandRequest {
+    SetInterval {
+        interval_ms: u32,
+    },
+    Reboot,
+    Recalibrate {
+        #[serde(default)]
+        temperature_offset: f32,
+        #[serde(default)]
+        humidity_offset: f32,
+    },
+    /// Размер и SHA-256 подставляет сервер из таблицы `firmware`.
+    UpdateFirmware {
+        version: String,
+        #[serde(skip_deserializing)]
+        size: u64,
+        #[serde(skip_deserializing)]
+        sha256: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingCommand {
+    pub id: u64,
+    pub queued_at: DateTime<Utc>,
+    pub command: CommandRequest,
+}
+
+impl PendingCommand {
+    pub fn to_proto(&self) -> data::Command {
+        use data::command::Action;
+        let action = match &self.command {
+            CommandRequest::SetInterval { interval_ms } => Action::SetInterval(data::SetInterval {
+                interval_ms: *interval_ms,
+            }),
+            CommandRequest::Reboot => Action::Reboot(data::Reboot {}),
+            CommandRequest::Recalibrate {
+                temperature_offset,
+                humidity_offset,
+            } => Action::Recalibrate(data::Recalibrate {
+                temperature_offset: *temperature_offset,
+                humidity_offset: *humidity_offset,
+            }),
+            CommandRequest::UpdateFirmware { version, size, sha256 } => {
+                Action::UpdateFirmware(data::UpdateFirmware {
+                    version: version.clone(),
+                    size: *size,
+                    sha256: sha256.clone(),
+                })
+            }
+        };
+        data::Command {
+            command_id: self.id,
+            action: Some(action),
+        }
+    }
+}
+
+/// Команды, ожидающие доставки. Устройство получает их в ответ
+/// на ближайшее показание.
+#[derive(Default)]
+pub struct CommandQueue {
+    next_id: u64,
+    pending: HashMap<u32, VecDeque<PendingCommand>>,
+    // версии прошивки, которые устройству велели поставить
+    offered: HashMap<u32, HashSet<String>>,
+}
+
+impl CommandQueue {
+    pub fn shared() -> SharedCommands {
+        Arc::new(Mutex::new(CommandQueue::default()))
+    }
+
+    pub fn push(&mut self, device_id: u32, command: CommandRequest) -> PendingCommand {
+        self.next_id += 1;
+        let pending = PendingCommand {
+            id: self.next_id,
+            queued_at: Utc::now(),
+            command,
+        };
+        self.pending
+            .entry(device_id)
+            .or_default()
+            .push_back(pending.clone());
+        pending
+    }
+
+    pub fn pending(&self, device_id: u32) -> Vec<PendingCommand> {
+        self.pending
+            .get(&device_id)
+            .map(|queue| queue.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn take(&mut self, device_id: u32) -> Vec<PendingCommand> {
+        let taken: Vec<PendingCommand> = self
+            .pending
+            .remove(&device_id)
+            .map(Vec::from)
+            .unwrap_or_default();
+        for pending in &taken {
+            if let CommandRequest::UpdateFirmware { version, .. } = &pending.command {
+                self.offered.entry(device_id).or_default().insert(version.clone());
+            }
+        }
+        taken
+    }
+
+    /// Отправлялась ли устройству команда поставить эту версию: скачать
+    /// можно только такой образ.
+    pub fn offered(&self, device_id: u32, version: &str) -> bool {
+        self.offered.get(&device_id).is_some_and(|versions| versions.contains(version))
+    }
+
+    /// Возвращает недоставленные команды в начало очереди.
+    pub fn restore(&mut self, device_id: u32, commands: Vec<PendingCommand>) {
+        let queue = self.pending.entry(device_id).or_default();
+        for command in commands.into_iter().rev() {
+            queue.push_front(command);
+        }
+    }
+}
@@ -0,0 +1,663 @@
+use crate::anomaly::Anomaly;
+use crate::calibration::{self, Calibration, Metric};
+use crate::clock::ClockCheck;
+use crate::data::Health;
+use crate::firmware::Firmware;
+use crate::measurement::Measurement;
+use crate::sequence::SequenceEvent;
+use crate::store::Store;
+use crate::tenant::Tenant;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use postgres::types::ToSql;
+use postgres::{Client, NoTls, Row, RowIter};
+use serde_json::{json, Value};
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Показание в том виде, в каком оно попадает в `measurements`: строка на величину.
+#[derive(Clone)]
+pub struct Reading {
+    pub device_id: u32,
+    pub event_id: u64,
+    pub measurements: Vec<Measurement>,
+    pub read_time: DateTime<Utc>,
+    pub clock: ClockCheck,
+    /// Чьё устройство на момент приёма (`devices`).
+    pub tenant_id: Option<String>,
+}
+
+impl Reading {
+    pub fn measurement(&self, metric: &str) -> Option<&Measurement> {
+        self.measurements.iter().find(|m| m.metric == metric)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut json = json!({
+            "device_id": self.device_id,
+            "event_id": self.event_id,
+            "measurements": self.measurements,
+            "read_time": self.read_time,
+            "received_at": self.clock.received_at,
+            "clock_skewed": self.clock.skewed,
+        });
+        // прежние поля - для подписчиков, которые ещё не знают о measurements
+        for metric in ["temperature", "humidity"] {
+            if let Some(m) = self.measurement(metric) {
+                json[metric] = json!(m.value as f32);
+                json[format!("raw_{}", metric)] = json!(m.raw_value as f32);
+            }
+        }
+        json
+    }
+
+    pub(crate) fn to_row(&self) -> ReadingRow {
+        ReadingRow {
+            device_id: self.device_id as i64,
+            event_id: self.event_id as i64,
+            read_time: self.read_time.naive_utc(),
+            received_at: self.clock.received_at.naive_utc(),
+            offset_ms: self.clock.offset_ms,
+            skewed: self.clock.skewed,
+            corrected_time: self.clock.corrected_time.map(|t| t.naive_utc()),
+            tenant_id: self.tenant_id.clone(),
+            metrics: self.measurements.iter().map(|m| m.metric.clone()).collect(),
+            values: self.measurements.iter().map(|m| m.value).collect(),
+            raw_values: self.measurements.iter().map(|m| m.raw_value).collect(),
+            units: self.measurements.iter().map(|m| m.unit.clone()).collect(),
+        }
+    }
+}
+
+/// Параметры `INSERT_READING`, общие для `Database` и `PgPool`.
+pub(crate) struct ReadingRow {
+    device_id: i64,
+    event_id: i64,
+    read_time: NaiveDateTime,
+    received_at: NaiveDateTime,
+    offset_ms: i64,
+    skewed: bool,
+    corrected_time: Option<NaiveDateTime>,
+    tenant_id: Option<String>,
+    metrics: Vec<String>,
+    values: Vec<f64>,
+    raw_values: Vec<f64>,
+    units: Vec<String>,
+}
+
+impl ReadingRow {
+    pub(crate) fn params(&self) -> [&(dyn ToSql + Sync); 12] {
+        [
+            &self.device_id,
+            &self.event_id,
+            &self.read_time,
+            &self.received_at,
+            &self.offset_ms,
+            &self.skewed,
+            &self.corrected_time,
+            &self.tenant_id,
+            &self.metrics,
+            &self.values,
+            &self.raw_values,
+            &self.units,
+        ]
+    }
+}
+
+// Все величины показания одним запросом. sensor_data больше не пишется:
+// в ней остаются только показания, принятые до узкой таблицы.
+pub(crate) const INSERT_READING: &str = "INSERT INTO measurements
+    (device_id, metric, ts, value, unit, tenant_id,
+     event_id, raw_value, received_at, clock_offset_ms, clock_skewed, corrected_time)
+SELECT $1, m.metric, $3, m.value, m.unit, $8,
+       $2, m.raw_value, $4, $5, $6, $7
+FROM unnest($9::TEXT[], $10::DOUBLE PRECISION[], $11::DOUBLE PRECISION[], $12::TEXT[])
+    AS m (metric, value, raw_value, unit)";
+
+pub(crate) const INSERT_GAP: &str = "INSERT INTO sensor_gaps
+    (device_id, kind, expected_event_id, received_event_id, missing, detected_at, tenant_id)
+ VALUES ($1, $2, $3, $4, $5, $6, $7)";
+
+pub(crate) const INSERT_ANOMALY: &str = "INSERT INTO sensor_anomalies
+    (device_id, metric, kind, value, score, message, time, tenant_id)
+ VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+pub(crate) const INSERT_HEALTH: &str = "INSERT INTO device_health
+    (device_id, battery_voltage, rssi_dbm, uptime_secs, free_heap_bytes, firmware_version,
+     reported_at, received_at, tenant_id)
+ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+
+pub(crate) const SELECT_CALIBRATIONS: &str = "SELECT device_id, metric, gain, offset_value, valid_from, valid_to
+ FROM device_calibration
+ WHERE $1::BIGINT IS NULL OR device_id = $1
+ ORDER BY device_id, metric, valid_from";
+
+pub(crate) const SELECT_DEVICE_TENANTS: &str = "SELECT device_id, tenant_id FROM devices";
+
+pub struct Database(Client);
+
+impl Database {
+    // подключение к базе - не значение по умолчанию
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        // let mut client = Client::connect("host=localhost user=postgres password=0330", NoTls).unwrap();
+
+        Self::open().unwrap()
+    }
+
+    /// Подключение и миграции; то же, что `new`, но без паники.
+    pub fn open() -> Result<Self, DbError> {
+        let mut db = Self::connect()?;
+        db.migrate()?;
+        Ok(db)
+    }
+
+    pub fn connect() -> Result<Self, DbError> {
+        let database_url = env::var("DATABASE_URL").map_err(|_| DbError::NotConfigured)?;
+        Ok(Client::connect(&database_url, NoTls).map(Database)?)
+    }
+
+    /// Отдельное короткое подключение и `SELECT 1`: жива ли база.
+    /// Ошибка - готовый текст для `/readyz`.
+    pub fn ping(timeout: Duration) -> Result<(), String> {
+        let database_url = env::var("DATABASE_URL").map_err(|_| "DATABASE_URL is not set".to_string())?;
+        let unreachable = |e: postgres::Error| format!("database unreachable: {}", e);
+        let mut config: postgres::Config = database_url.parse().map_err(unreachable)?;
+        let mut client = config.connect_timeout(timeout).connect(NoTls).map_err(unreachable)?;
+        client.simple_query("SELECT 1").map(|_| ()).map_err(unreachable)
+    }
+
+    fn migrate(&mut self) -> Result<(), postgres::Error> {
+        self.0.batch_execute(
+            // без NOTICE "already exists, skipping" в журнале на каждом запуске
+            "SET client_min_messages TO WARNING;
+            CREATE TABLE IF NOT EXISTS sensor_data (
+                device_id BIGINT NOT NULL,
+                event_id BIGINT NOT NULL,
+                humidity REAL NOT NULL,
+                temperature REAL NOT NULL,
+                read_time TIMESTAMP NOT NULL
+            );
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS received_at TIMESTAMP;
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS clock_offset_ms BIGINT;
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS clock_skewed BOOLEAN NOT NULL DEFAULT FALSE;
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS corrected_time TIMESTAMP;
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS raw_humidity REAL;
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS raw_temperature REAL;
+            CREATE TABLE IF NOT EXISTS sensor_gaps (
+                device_id BIGINT NOT NULL,
+                kind TEXT NOT NULL,
+                expected_event_id BIGINT NOT NULL,
+                received_event_id BIGINT NOT NULL,
+                missing BIGINT NOT NULL,
+                detected_at TIMESTAMP NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS device_calibration (
+                device_id BIGINT NOT NULL,
+                metric TEXT NOT NULL,
+                gain DOUBLE PRECISION NOT NULL DEFAULT 1,
+                offset_value DOUBLE PRECISION NOT NULL DEFAULT 0,
+                valid_from TIMESTAMP NOT NULL,
+                valid_to TIMESTAMP
+            );
+            CREATE TABLE IF NOT EXISTS sensor_anomalies (
+                device_id BIGINT NOT NULL,
+                metric TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                value DOUBLE PRECISION NOT NULL,
+                score DOUBLE PRECISION NOT NULL,
+                message TEXT NOT NULL,
+                time TIMESTAMP NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS tenants (
+                tenant_id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                token_hash TEXT NOT NULL UNIQUE,
+                created_at TIMESTAMP NOT NULL DEFAULT now()
+            );
+            CREATE TABLE IF NOT EXISTS devices (
+                device_id BIGINT PRIMARY KEY,
+                tenant_id TEXT NOT NULL REFERENCES tenants,
+                registered_at TIMESTAMP NOT NULL DEFAULT now()
+            );
+            ALTER TABLE sensor_data ADD COLUMN IF NOT EXISTS tenant_id TEXT;
+            ALTER TABLE sensor_gaps ADD COLUMN IF NOT EXISTS tenant_id TEXT;
+            ALTER TABLE sensor_anomalies ADD COLUMN IF NOT EXISTS tenant_id TEXT;
+            CREATE INDEX IF NOT EXISTS sensor_data_tenant ON sensor_data (tenant_id, device_id, read_time);
+            CREATE TABLE IF NOT EXISTS device_health (
+                device_id BIGINT NOT NULL,
+                battery_voltage REAL NOT NULL,
+                rssi_dbm INTEGER NOT NULL,
+                uptime_secs BIGINT NOT NULL,
+                free_heap_bytes BIGINT NOT NULL,
+                firmware_version TEXT NOT NULL,
+                reported_at TIMESTAMP NOT NULL,
+                received_at TIMESTAMP NOT NULL,
+                tenant_id TEXT
+            );
+            CREATE INDEX IF NOT EXISTS device_health_latest ON device_health (device_id, reported_at DESC);
+            CREATE INDEX IF NOT EXISTS sensor_data_device_time ON sensor_data (device_id, read_time);
+            CREATE TABLE IF NOT EXISTS firmware (
+                version TEXT PRIMARY KEY,
+                size BIGINT NOT NULL,
+                sha256 TEXT NOT NULL,
+                uploaded_at TIMESTAMP NOT NULL
+            );
+            DO $$
+            BEGIN
+                IF to_regclass('measurements') IS NULL THEN
+                    CREATE TABLE measurements (
+                        device_id BIGINT NOT NULL,
+                        metric TEXT NOT NULL,
+                        ts TIMESTAMP NOT NULL,
+                        value DOUBLE PRECISION NOT NULL,
+                        unit TEXT NOT NULL,
+                        tenant_id TEXT
+                    );
+                    CREATE INDEX measurements_device_metric_ts ON measurements (device_id, metric, ts);
+                    -- показания, принятые до узкой таблицы
+                    INSERT INTO measurements (device_id, metric, ts, value, unit, tenant_id)
+                        SELECT device_id, 'temperature', read_time, temperature, '°C', tenant_id FROM sensor_data
+                        UNION ALL
+                        SELECT device_id, 'humidity', read_time, humidity, '%', tenant_id FROM sensor_data;
+                END IF;
+            END
+            $$;
+            -- сведения о приёме, которые раньше хранились только в sensor_data
+            DO $$
+            BEGIN
+                IF NOT EXISTS (
+                    SELECT 1 FROM information_schema.columns
+                    WHERE table_name = 'measurements' AND column_name = 'event_id'
+                ) THEN
+                    ALTER TABLE measurements
+                        ADD COLUMN event_id BIGINT,
+                        ADD COLUMN raw_value DOUBLE PRECISION,
+                        ADD COLUMN received_at TIMESTAMP,
+                        ADD COLUMN clock_offset_ms BIGINT,
+                        ADD COLUMN clock_skewed BOOLEAN NOT NULL DEFAULT FALSE,
+                        ADD COLUMN corrected_time TIMESTAMP;
+                    UPDATE measurements m
+                    SET event_id = s.event_id,
+                        raw_value = CASE m.metric
+                            WHEN 'temperature' THEN COALESCE(s.raw_temperature, s.temperature)
+                            ELSE COALESCE(s.raw_humidity, s.humidity)
+                        END,
+                        received_at = s.received_at,
+                        clock_offset_ms = s.clock_offset_ms,
+                        clock_skewed = s.clock_skewed,
+                        corrected_time = s.corrected_time
+                    FROM sensor_data s
+                    WHERE s.device_id = m.device_id AND s.read_time = m.ts
+                      AND m.metric IN ('temperature', 'humidity');
+                END IF;
+            END
+            $$;
+            CREATE INDEX IF NOT EXISTS measurements_device_ts ON measurements (device_id, ts);
+            CREATE INDEX IF NOT EXISTS measurements_tenant ON measurements (tenant_id, device_id, ts);
+            -- устройство, приславшее хоть что-то: показание или состояние
+            CREATE OR REPLACE VIEW device_last_seen AS
+                SELECT d.device_id, COALESCE(h.tenant_id, r.tenant_id) AS tenant_id,
+                       GREATEST(h.reported_at, r.last_reading_at) AS last_seen,
+                       r.last_reading_at, h.reported_at AS last_health_at,
+                       h.battery_voltage, h.rssi_dbm, h.uptime_secs, h.free_heap_bytes, h.firmware_version
+                FROM (
+                    SELECT device_id FROM measurements
+                    UNION
+                    SELECT device_id FROM device_health
+                ) d
+                LEFT JOIN LATERAL (
+                    SELECT ts AS last_reading_at, tenant_id FROM measurements m
+                    WHERE m.device_id = d.device_id
+                    ORDER BY ts DESC LIMIT 1
+                ) r ON TRUE
+                LEFT JOIN LATERAL (
+                    SELECT * FROM device_health dh
+                    WHERE dh.device_id = d.device_id
+                    ORDER BY reported_at DESC LIMIT 1
+                ) h ON TRUE;"
+        )
+    }
+
+    /// Устройства, от которых хоть раз приходили показания (все или одной команды).
+    pub fn devices(&mut self, tenant_id: Option<&str>) -> Result<Vec<u32>, postgres::Error> {
+        let rows = self.0.query(
+            "SELECT DISTINCT device_id FROM measurements
+             WHERE $1::TEXT IS NULL OR tenant_id = $1
+             ORDER BY device_id",
+            &[&tenant_id],
+        )?;
+        Ok(rows.iter().map(|row| row.get::<_, i64>(0) as u32).collect())
+    }
+
+    pub fn add_tenant(&mut self, tenant_id: &str, name: &str, token_hash: &str) -> Result<(), postgres::Error> {
+        self.0
+            .execute(
+                "INSERT INTO tenants (tenant_id, name, token_hash) VALUES ($1, $2, $3)",
+                &[&tenant_id, &name, &token_hash],
+            )
+            .map(|_| ())
+    }
+
+    /// Заменяет токен команды; `false`, если такой команды нет.
+    pub fn set_tenant_token(&mut self, tenant_id: &str, token_hash: &str) -> Result<bool, postgres::Error> {
+        let updated = self.0.execute(
+            "UPDATE tenants SET token_hash = $2 WHERE tenant_id = $1",
+            &[&tenant_id, &token_hash],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn tenants(&mut self) -> Result<Vec<Tenant>, postgres::Error> {
+        let rows = self.0.query(
+            "SELECT t.tenant_id, t.name, array_remove(array_agg(d.device_id ORDER BY d.device_id), NULL)
+             FROM tenants t LEFT JOIN devices d ON d.tenant_id = t.tenant_id
+             GROUP BY t.tenant_id, t.name
+             ORDER BY t.tenant_id",
+            &[],
+        )?;
+        Ok(rows
+            .iter()
+            .map(|row| Tenant {
+                id: row.get(0),
+                name: row.get(1),
+                devices: row.get::<_, Vec<i64>>(2).into_iter().map(|id| id as u32).collect(),
+            })
+            .collect())
+    }
+
+    pub fn tenant_by_token(&mut self, token_hash: &str) -> Result<Option<String>, postgres::Error> {
+        let row = self
+            .0
+            .query_opt("SELECT tenant_id FROM tenants WHERE token_hash = $1", &[&token_hash])?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    pub fn device_tenant(&mut self, device_id: u32) -> Result<Option<String>, postgres::Error> {
+        let row = self
+            .0
+            .query_opt("SELECT tenant_id FROM devices WHERE device_id = $1", &[&(device_id as i64)])?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Закрепляет устройство за командой. Данные, принятые до регистрации,
+    /// отдаются ей же; данные, записанные за прежней командой, остаются у неё.
+    /// Возвращает число перенесённых показаний.
+    pub fn assign_device(&mut self, device_id: u32, tenant_id: &str) -> Result<u64, postgres::Error> {
+        let device_id = device_id as i64;
+        let mut tx = self.0.transaction()?;
+        tx.execute(
+            "INSERT INTO devices (device_id, tenant_id) VALUES ($1, $2)
+             ON CONFLICT (device_id) DO UPDATE SET tenant_id = $2, registered_at = now()",
+            &[&device_id, &tenant_id],
+        )?;
+        // показание - все величины с одним временем
+        let claimed = tx.query_one(
+            "WITH claimed AS (
+                UPDATE measurements SET tenant_id = $2 WHERE device_id = $1 AND tenant_id IS NULL RETURNING ts
+             )
+             SELECT count(DISTINCT ts) FROM claimed",
+            &[&device_id, &tenant_id],
+        )?;
+        for table in ["sensor_data", "sensor_gaps", "sensor_anomalies", "device_health"] {
+            tx.execute(
+                &format!("UPDATE {} SET tenant_id = $2 WHERE device_id = $1 AND tenant_id IS NULL", table),
+                &[&device_id, &tenant_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(claimed.get::<_, i64>(0) as u64)
+    }
+
+    pub fn device_tenants(&mut self) -> Result<Vec<(u32, String)>, postgres::Error> {
+        let rows = self.0.query(SELECT_DEVICE_TENANTS, &[])?;
+        Ok(rows.iter().map(device_tenant_from_row).collect())
+    }
+
+    /// Все калибровки или только калибровки одного устройства.
+    pub fn calibrations(&mut self, device_id: Option<u32>) -> Result<Vec<(u32, Calibration)>, postgres::Error> {
+        let rows = self.0.query(SELECT_CALIBRATIONS, &[&device_id.map(|id| id as i64)])?;
+        Ok(rows.iter().filter_map(calibration_from_row).collect())
+    }
+
+    /// Добавляет калибровку, перестраивая интервалы прежних (см. `calibration::insert`).
+    /// Возвращает её в том виде, в каком она сохранена.
+    pub fn insert_calibration(&mut self, device_id: u32, calibration: &Calibration) -> Result<Calibration, postgres::Error> {
+        let metric = calibration.metric.as_str();
+        let mut tx = self.0.transaction()?;
+        // чтение не блокирует, а две одновременные вставки не перемешают интервалы
+        tx.batch_execute("LOCK TABLE device_calibration IN EXCLUSIVE MODE")?;
+        let rows = tx.query(
+            "SELECT device_id, metric, gain, offset_value, valid_from, valid_to
+             FROM device_calibration WHERE device_id = $1 AND metric = $2",
+            &[&(device_id as i64), &metric],
+        )?;
+        let history = rows.iter().filter_map(calibration_from_row).map(|(_, c)| c).collect();
+        let updated = calibration::insert(history, calibration.clone());
+
+        tx.execute(
+            "DELETE FROM device_calibration WHERE device_id = $1 AND metric = $2",
+            &[&(device_id as i64), &metric],
+        )?;
+        for row in &updated {
+            tx.execute(
+                "INSERT INTO device_calibration (device_id, metric, gain, offset_value, valid_from, valid_to)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &(device_id as i64),
+                    &metric,
+                    &row.gain,
+                    &row.offset,
+                    &row.valid_from.naive_utc(),
+                    &row.valid_to.map(|t| t.naive_utc()),
+                ],
+            )?;
+        }
+        tx.commit()?;
+        let stored = updated.into_iter().find(|c| c.valid_from == calibration.valid_from);
+        Ok(stored.expect("inserted calibration is kept"))
+    }
+
+    /// Величины за период построчно (строка на величину), без загрузки
+    /// всей выборки в память.
+    pub fn readings(
+        &mut self,
+        tenant_id: Option<&str>,
+        device_id: Option<u32>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<RowIter<'_>, postgres::Error> {
+        let device_id = device_id.map(|id| id as i64);
+        let from = from.map(|t| t.naive_utc());
+        let to = to.map(|t| t.naive_utc());
+        let params: [&(dyn ToSql + Sync); 4] = [&device_id, &from, &to, &tenant_id];
+        self.0.query_raw(
+            "SELECT device_id, event_id, ts, metric, value, unit, raw_value, received_at
+             FROM measurements
+             WHERE ($1::BIGINT IS NULL OR device_id = $1)
+               AND ($2::TIMESTAMP IS NULL OR ts >= $2)
+               AND ($3::TIMESTAMP IS NULL OR ts < $3)
+               AND ($4::TEXT IS NULL OR tenant_id = $4)
+             ORDER BY device_id, ts, metric",
+            params,
+        )
+    }
+
+    pub fn add_firmware(&mut self, firmware: &Firmware) -> Result<(), postgres::Error> {
+        self.0
+            .execute(
+                "INSERT INTO firmware (version, size, sha256, uploaded_at) VALUES ($1, $2, $3, $4)",
+                &[
+                    &firmware.version,
+                    &(firmware.size as i64),
+                    &firmware.sha256,
+                    &firmware.uploaded_at.naive_utc(),
+                ],
+            )
+            .map(|_| ())
+    }
+
+    pub fn firmware(&mut self, version: &str) -> Result<Option<Firmware>, postgres::Error> {
+        let row = self.0.query_opt(
+            "SELECT version, size, sha256, uploaded_at FROM firmware WHERE version = $1",
+            &[&version],
+        )?;
+        Ok(row.as_ref().map(firmware_from_row))
+    }
+
+    pub fn firmwares(&mut self) -> Result<Vec<Firmware>, postgres::Error> {
+        let rows = self
+            .0
+            .query("SELECT version, size, sha256, uploaded_at FROM firmware ORDER BY uploaded_at", &[])?;
+        Ok(rows.iter().map(firmware_from_row).collect())
+    }
+
+    /// Последнее сырое показание устройства не позже `at`.
+    pub fn raw_reading(&mut self, device_id: u32, metric: Metric, at: DateTime<Utc>) -> Result<Option<f64>, postgres::Error> {
+        let row = self.0.query_opt(
+            "SELECT COALESCE(raw_value, value) FROM measurements
+             WHERE device_id = $1 AND metric = $2 AND ts <= $3
+             ORDER BY ts DESC LIMIT 1",
+            &[&(device_id as i64), &metric.as_str(), &at.naive_utc()],
+        )?;
+        Ok(row.map(|row| row.get(0)))
+    }
+}
+
+/// Почему не удалось подключиться к базе.
+#[derive(Debug)]
+pub enum DbError {
+    /// Нет `DATABASE_URL`: ошибка настройки, повторять бесполезно.
+    NotConfigured,
+    Postgres(postgres::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::NotConfigured => write!(f, "DATABASE_URL is not set"),
+            DbError::Postgres(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<postgres::Error> for DbError {
+    fn from(e: postgres::Error) -> Self {
+        DbError::Postgres(e)
+    }
+}
+
+/// Одно подключение на короткие запросы HTTP API, вместо нового на
+/// каждый запрос. Открывается при первом обращении и заново после обрыва.
+#[derive(Default)]
+pub struct SharedDatabase(Mutex<Option<Database>>);
+
+impl SharedDatabase {
+    pub fn with<T>(&self, f: impl FnOnce(&mut Database) -> Result<T, postgres::Error>) -> Result<T, DbError> {
+        let mut slot = self.0.lock().unwrap();
+        if slot.as_ref().is_none_or(|db| db.0.is_closed()) {
+            *slot = Some(Database::connect()?);
+        }
+        Ok(f(slot.as_mut().unwrap())?)
+    }
+}
+
+impl Store for Database {
+    fn save(&mut self, reading: &Reading) {
+        self.0.execute(INSERT_READING, &reading.to_row().params()).unwrap();
+    }
+
+    fn save_gap(&mut self, reading: &Reading, event: &SequenceEvent) {
+        self.0.execute(
+            INSERT_GAP,
+            &[
+                &(reading.device_id as i64),
+                &event.kind.to_string(),
+                &(event.expected as i64),
+                &(event.received as i64),
+                &(event.missing as i64),
+                &reading.clock.received_at.naive_utc(),
+                &reading.tenant_id,
+            ]
+        ).unwrap();
+    }
+
+    fn save_anomaly(&mut self, reading: &Reading, anomaly: &Anomaly) {
+        self.0.execute(
+            INSERT_ANOMALY,
+            &[
+                &(reading.device_id as i64),
+                &anomaly.metric.as_str(),
+                &anomaly.kind.to_string(),
+                &anomaly.value,
+                &anomaly.score,
+                &anomaly.message,
+                &reading.read_time.naive_utc(),
+                &reading.tenant_id,
+            ]
+        ).unwrap();
+    }
+
+    fn save_health(&mut self, reading: &Reading, health: &Health) {
+        self.0.execute(
+            INSERT_HEALTH,
+            &[
+                &(reading.device_id as i64),
+                &health.battery_voltage,
+                &health.rssi_dbm,
+                &(health.uptime_secs as i64),
+                &(health.free_heap_bytes as i64),
+                &health.firmware_version,
+                &reading.read_time.naive_utc(),
+                &reading.clock.received_at.naive_utc(),
+                &reading.tenant_id,
+            ]
+        ).unwrap();
+    }
+
+    fn load_calibrations(&mut self) -> Result<Vec<(u32, Calibration)>, String> {
+        self.calibrations(None).map_err(|e| e.to_string())
+    }
+
+    fn load_device_tenants(&mut self) -> Result<Vec<(u32, String)>, String> {
+        self.device_tenants().map_err(|e| e.to_string())
+    }
+}
+
+fn firmware_from_row(row: &Row) -> Firmware {
+    Firmware {
+        version: row.get(0),
+        size: row.get::<_, i64>(1) as u64,
+        sha256: row.get(2),
+        uploaded_at: row.get::<_, NaiveDateTime>(3).and_utc(),
+    }
+}
+
+/// Строка `SELECT_DEVICE_TENANTS`.
+pub(crate) fn device_tenant_from_row(row: &Row) -> (u32, String) {
+    (row.get::<_, i64>(0) as u32, row.get(1))
+}
+
+/// Строка `SELECT_CALIBRATIONS`; калибровки с неизвестной метрикой пропускаются.
+pub(crate) fn calibration_from_row(row: &Row) -> Option<(u32, Calibration)> {
+    let metric: String = row.get(1);
+    let metric = match metric.parse::<Metric>() {
+        Ok(metric) => metric,
+        Err(e) => {
+            tracing::warn!(error = %e, "Skipping calibration");
+            return None;
+        }
+    };
+    let valid_to: Option<NaiveDateTime> = row.get(5);
+    Some((
+        row.get::<_, i64>(0) as u32,
+        Calibration {
+            metric,
+            gain: row.get(2),
+            offset: row.get(3),
+            valid_from: row.get::<_, NaiveDateTime>(4).and_utc(),
+            valid_to: valid_to.map(|t| t.and_utc()),
+        },
+    ))
+}
@@ -0,0 +1,310 @@
+use crate::db::Database;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::Row;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Строк в одной группе Parquet: столько строк держится в памяти при выгрузке
+const ROW_GROUP_SIZE: usize = 16 * 1024;
+
+// Строка на величину: набор величин у устройств разный, а схема
+// Parquet и заголовок CSV должны быть известны до первой строки
+const PARQUET_SCHEMA: &str = "
+message measurement {
+    REQUIRED INT64 device_id;
+    OPTIONAL INT64 event_id;
+    REQUIRED INT64 read_time (TIMESTAMP(MICROS,true));
+    REQUIRED BYTE_ARRAY metric (UTF8);
+    REQUIRED DOUBLE value;
+    REQUIRED BYTE_ARRAY unit (UTF8);
+    OPTIONAL DOUBLE raw_value;
+    OPTIONAL INT64 received_at (TIMESTAMP(MICROS,true));
+}";
+
+const CSV_HEADER: &str = "device_id,event_id,read_time,metric,value,unit,raw_value,received_at\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Jsonl => "application/x-ndjson",
+            Format::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(format!("unknown format {:?}, expected csv, parquet or jsonl", s)),
+        }
+    }
+}
+
+/// Какие строки `measurements` выгружать: `to` не включается.
+#[derive(Debug, Clone)]
+pub struct ExportQuery {
+    /// Только показания, записанные за этой командой.
+    pub tenant_id: Option<String>,
+    pub device_id: Option<u32>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub format: Format,
+}
+
+impl ExportQuery {
+    /// Параметры `tenant`, `device`, `from`, `to` (RFC 3339) и `format` (по умолчанию csv),
+    /// одинаковые для `server export` и `GET /export`.
+    pub fn from_params(mut param: impl FnMut(&str) -> Option<String>) -> Result<Self, String> {
+        let time = |name: &str, value: Option<String>| -> Result<Option<DateTime<Utc>>, String> {
+            value
+                .map(|v| {
+                    DateTime::parse_from_rfc3339(&v)
+                        .map(|t| t.with_timezone(&Utc))
+                        .map_err(|e| format!("Invalid {} {}: {}", name, v, e))
+                })
+                .transpose()
+        };
+
+        let tenant_id = param("tenant");
+        let device_id = param("device")
+            .map(|v| v.parse::<u32>().map_err(|_| format!("Invalid device: {}", v)))
+            .transpose()?;
+        let from = time("from", param("from"))?;
+        let to = time("to", param("to"))?;
+        let format = param("format").as_deref().unwrap_or("csv").parse()?;
+        Ok(ExportQuery {
+            tenant_id,
+            device_id,
+            from,
+            to,
+            format,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Db(postgres::Error),
+    Io(io::Error),
+    Parquet(parquet::errors::ParquetError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Db(e) => write!(f, "database error: {}", e),
+            ExportError::Io(e) => write!(f, "write error: {}", e),
+            ExportError::Parquet(e) => write!(f, "parquet error: {}", e),
+        }
+    }
+}
+
+impl From<postgres::Error> for ExportError {
+    fn from(e: postgres::Error) -> Self {
+        ExportError::Db(e)
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for ExportError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        ExportError::Parquet(e)
+    }
+}
+
+/// Строка выгрузки: одна величина одного показания.
+#[derive(Serialize)]
+pub struct ExportRow {
+    pub device_id: i64,
+    pub event_id: Option<i64>,
+    pub read_time: DateTime<Utc>,
+    pub metric: String,
+    pub value: f64,
+    pub unit: String,
+    pub raw_value: Option<f64>,
+    pub received_at: Option<DateTime<Utc>>,
+}
+
+impl From<&Row> for ExportRow {
+    fn from(row: &Row) -> Self {
+        ExportRow {
+            device_id: row.get(0),
+            event_id: row.get(1),
+            read_time: row.get::<_, NaiveDateTime>(2).and_utc(),
+            metric: row.get(3),
+            value: row.get(4),
+            unit: row.get(5),
+            raw_value: row.get(6),
+            received_at: row.get::<_, Option<NaiveDateTime>>(7).map(|t| t.and_utc()),
+        }
+    }
+}
+
+/// Выгружает показания в `out` по строке на величину, не загружая их
+/// целиком в память. Возвращает число строк.
+pub fn export<W: Write + Send>(db: &mut Database, query: &ExportQuery, out: W) -> Result<u64, ExportError> {
+    let rows = db.readings(query.tenant_id.as_deref(), query.device_id, query.from, query.to)?;
+    write(rows.map(|row| Ok(ExportRow::from(&row))), query.format, out)
+}
+
+/// Пишет строки в формате `format`; `export` берёт их из базы.
+pub fn write<W: Write + Send>(
+    mut rows: impl FallibleIterator<Item = ExportRow, Error = postgres::Error>,
+    format: Format,
+    out: W,
+) -> Result<u64, ExportError> {
+    let mut out = BufWriter::new(out);
+    let mut count = 0;
+
+    match format {
+        Format::Csv => {
+            out.write_all(CSV_HEADER.as_bytes())?;
+            while let Some(r) = rows.next()? {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{}",
+                    r.device_id,
+                    optional(r.event_id),
+                    r.read_time.to_rfc3339(),
+                    r.metric,
+                    r.value,
+                    csv_field(&r.unit),
+                    optional(r.raw_value),
+                    optional(r.received_at.map(|t| t.to_rfc3339())),
+                )?;
+                count += 1;
+            }
+        }
+        Format::Jsonl => {
+            while let Some(row) = rows.next()? {
+                serde_json::to_writer(&mut out, &row).map_err(io::Error::from)?;
+                out.write_all(b"\n")?;
+                count += 1;
+            }
+        }
+        Format::Parquet => {
+            let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+            let props = Arc::new(WriterProperties::builder().build());
+            let mut writer = SerializedFileWriter::new(&mut out, schema, props)?;
+            let mut batch = Vec::with_capacity(ROW_GROUP_SIZE);
+            while let Some(row) = rows.next()? {
+                batch.push(row);
+                count += 1;
+                if batch.len() == ROW_GROUP_SIZE {
+                    write_row_group(&mut writer, &batch)?;
+                    batch.clear();
+                }
+            }
+            if !batch.is_empty() {
+                write_row_group(&mut writer, &batch)?;
+            }
+            writer.close()?;
+        }
+    }
+
+    out.flush()?;
+    Ok(count)
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+// Единицу неизвестной величины присылает плата, в ней может быть что угодно
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_row_group<W: Write + Send>(
+    writer: &mut SerializedFileWriter<W>,
+    batch: &[ExportRow],
+) -> Result<(), ExportError> {
+    let mut group = writer.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column) = group.next_column()? {
+        match index {
+            0 => write_i64(&mut column, batch.iter().map(|r| Some(r.device_id)), false)?,
+            1 => write_i64(&mut column, batch.iter().map(|r| r.event_id), true)?,
+            2 => write_i64(&mut column, batch.iter().map(|r| Some(r.read_time.timestamp_micros())), false)?,
+            3 => write_str(&mut column, batch.iter().map(|r| r.metric.as_str()))?,
+            4 => write_f64(&mut column, batch.iter().map(|r| Some(r.value)), false)?,
+            5 => write_str(&mut column, batch.iter().map(|r| r.unit.as_str()))?,
+            6 => write_f64(&mut column, batch.iter().map(|r| r.raw_value), true)?,
+            _ => write_i64(&mut column, batch.iter().map(|r| r.received_at.map(|t| t.timestamp_micros())), true)?,
+        }
+        column.close()?;
+        index += 1;
+    }
+    group.close()?;
+    Ok(())
+}
+
+// Для OPTIONAL-колонок значения пишутся без пропусков, а наличие - уровнями определения
+fn levels<T>(values: impl Iterator<Item = Option<T>>, optional: bool) -> (Vec<T>, Option<Vec<i16>>) {
+    let mut data = Vec::new();
+    let mut def_levels = Vec::new();
+    for value in values {
+        def_levels.push(value.is_some() as i16);
+        data.extend(value);
+    }
+    (data, optional.then_some(def_levels))
+}
+
+fn write_i64(
+    column: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+    values: impl Iterator<Item = Option<i64>>,
+    optional: bool,
+) -> Result<(), ExportError> {
+    let (data, def_levels) = levels(values, optional);
+    column.typed::<Int64Type>().write_batch(&data, def_levels.as_deref(), None)?;
+    Ok(())
+}
+
+fn write_f64(
+    column: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+    values: impl Iterator<Item = Option<f64>>,
+    optional: bool,
+) -> Result<(), ExportError> {
+    let (data, def_levels) = levels(values, optional);
+    column.typed::<DoubleType>().write_batch(&data, def_levels.as_deref(), None)?;
+    Ok(())
+}
+
+fn write_str<'a>(
+    column: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+    values: impl Iterator<Item = &'a str>,
+) -> Result<(), ExportError> {
+    let data: Vec<ByteArray> = values.map(ByteArray::from).collect();
+    column.typed::<ByteArrayType>().write_batch(&data, None, None)?;
+    Ok(())
+}
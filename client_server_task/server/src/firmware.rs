@@ -0,0 +1,147 @@
+use crate::data;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// Больше куска за раз не отдаём: кадр не должен упираться в MAX_FRAME_LEN
+pub const MAX_CHUNK_LEN: u32 = 64 * 1024;
+
+const DEFAULT_DIR: &str = "firmware";
+
+/// Образ прошивки, как он записан в таблице `firmware`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Firmware {
+    pub version: String,
+    pub size: u64,
+    /// SHA-256 образа в hex.
+    pub sha256: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// Образы прошивок в каталоге `FIRMWARE_DIR`, по файлу `<версия>.bin`
+/// на версию. Образ читается с диска при первом запросе и дальше
+/// отдаётся из памяти; `chunk` может читать диск, поэтому приём зовёт
+/// его через `spawn_blocking`. Образы общие для всех команд.
+pub struct FirmwareStore {
+    dir: PathBuf,
+    images: Mutex<HashMap<String, Arc<[u8]>>>,
+}
+
+impl FirmwareStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FirmwareStore {
+            dir: dir.into(),
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `FIRMWARE_DIR`, по умолчанию `firmware` в рабочем каталоге.
+    pub fn from_env() -> Self {
+        let dir = std::env::var("FIRMWARE_DIR")
+            .ok()
+            .filter(|d| !d.is_empty())
+            .unwrap_or_else(|| DEFAULT_DIR.to_string());
+        FirmwareStore::new(dir)
+    }
+
+    /// Сохраняет образ. Файл появляется целиком или не появляется вовсе.
+    pub fn add(&self, version: &str, image: &[u8]) -> Result<Firmware, String> {
+        validate_version(version)?;
+        let path = self.path(version);
+        if path.exists() {
+            return Err(format!("firmware {} already exists", version));
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| format!("{}: {}", self.dir.display(), e))?;
+        let partial = path.with_extension("bin.partial");
+        fs::write(&partial, image)
+            .and_then(|_| fs::rename(&partial, &path))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        Ok(Firmware {
+            version: version.to_string(),
+            size: image.len() as u64,
+            sha256: sha256_hex(image),
+            uploaded_at: Utc::now(),
+        })
+    }
+
+    /// Убирает образ, например если его не удалось записать в таблицу.
+    pub fn remove(&self, version: &str) -> io::Result<()> {
+        self.images.lock().unwrap().remove(version);
+        fs::remove_file(self.path(version))
+    }
+
+    /// Ответ на `FirmwareRequest`; ошибка уходит устройству в самом куске.
+    pub fn chunk(&self, request: &data::FirmwareRequest) -> data::FirmwareChunk {
+        let mut chunk = data::FirmwareChunk {
+            version: request.version.clone(),
+            offset: request.offset,
+            ..Default::default()
+        };
+        let image = validate_version(&request.version).and_then(|_| {
+            self.image(&request.version)
+                .map_err(|e| format!("firmware {}: {}", request.version, e))
+        });
+        let image = match image {
+            Ok(image) => image,
+            Err(e) => {
+                chunk.error = e;
+                return chunk;
+            }
+        };
+
+        chunk.total_size = image.len() as u64;
+        if request.offset > chunk.total_size {
+            chunk.error = format!("offset {} past the end of {} bytes", request.offset, chunk.total_size);
+            return chunk;
+        }
+        let max_len = match request.max_len {
+            0 => MAX_CHUNK_LEN,
+            len => len.min(MAX_CHUNK_LEN),
+        };
+        let start = request.offset as usize;
+        let end = image.len().min(start + max_len as usize);
+        chunk.data = image[start..end].to_vec();
+        chunk
+    }
+
+    fn image(&self, version: &str) -> io::Result<Arc<[u8]>> {
+        if let Some(image) = self.images.lock().unwrap().get(version) {
+            return Ok(Arc::clone(image));
+        }
+        let image: Arc<[u8]> = fs::read(self.path(version))?.into();
+        self.images
+            .lock()
+            .unwrap()
+            .insert(version.to_string(), Arc::clone(&image));
+        Ok(image)
+    }
+
+    fn path(&self, version: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", version))
+    }
+}
+
+/// Версия становится именем файла, поэтому без `/` и не с точки.
+pub fn validate_version(version: &str) -> Result<(), String> {
+    let valid = !version.is_empty()
+        && version.len() <= 64
+        && !version.starts_with('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid firmware version {:?}", version))
+    }
+}
+
+pub fn sha256_hex(image: &[u8]) -> String {
+    Sha256::digest(image).iter().map(|b| format!("{:02x}", b)).collect()
+}
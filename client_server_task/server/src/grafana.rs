@@ -0,0 +1,147 @@
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+// Совпадает с uid в grafana/dashboards/datasources/datasource.yaml
+pub const DEFAULT_DATASOURCE_UID: &str = "sensors-db";
+
+const DATASOURCE_TYPE: &str = "grafana-postgresql-datasource";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Переменная `$device` и панели, повторяемые для каждого выбранного устройства.
+    Templated,
+    /// Отдельная панель на каждое устройство из базы.
+    PerDevice,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "templated" => Ok(Layout::Templated),
+            "per-device" => Ok(Layout::PerDevice),
+            _ => Err(format!("unknown layout {:?}, expected templated or per-device", s)),
+        }
+    }
+}
+
+/// Дашборд для папки provisioning; `devices` нужен только для `Layout::PerDevice`.
+pub fn dashboard(layout: Layout, devices: &[u32], datasource_uid: &str) -> Value {
+    let datasource = json!({ "type": DATASOURCE_TYPE, "uid": datasource_uid });
+
+    // аннотации из sensor_anomalies поверх панелей
+    let anomaly_filter = match layout {
+        Layout::Templated => "device_id IN ($device) AND ",
+        Layout::PerDevice => "",
+    };
+    let annotations = json!({
+        "list": [{
+            "name": "Anomalies",
+            "datasource": datasource,
+            "enable": true,
+            "iconColor": "red",
+            "target": {
+                "refId": "Anno",
+                "editorMode": "code",
+                "format": "table",
+                "rawQuery": true,
+                "rawSql": format!(
+                    "SELECT\n  time,\n  'device ' || device_id || ': ' || message AS text,\n  kind || ',' || metric AS tags\nFROM sensor_anomalies\nWHERE {anomaly_filter}$__timeFilter(time)\nORDER BY time"
+                ),
+            },
+        }]
+    });
+
+    // величины берутся из данных, а не из кода: новый датчик появится на
+    // графиках без перегенерации дашборда
+    let metric = variable(
+        "metric",
+        "Metric",
+        "SELECT DISTINCT metric FROM measurements ORDER BY metric",
+        &datasource,
+    );
+    let (panels, templating) = match layout {
+        Layout::Templated => {
+            let mut panel = panel(1, "Device $device", "device_id = $device", &datasource, 0);
+            panel["repeat"] = json!("device");
+            panel["repeatDirection"] = json!("h");
+            let device = variable(
+                "device",
+                "Device",
+                "SELECT DISTINCT device_id FROM measurements ORDER BY device_id",
+                &datasource,
+            );
+            (vec![panel], vec![device, metric])
+        }
+        Layout::PerDevice => {
+            let panels = devices
+                .iter()
+                .enumerate()
+                .map(|(i, device_id)| {
+                    panel(
+                        i as u64 + 1,
+                        &format!("Device {}", device_id),
+                        &format!("device_id = {}", device_id),
+                        &datasource,
+                        i as u64,
+                    )
+                })
+                .collect();
+            (panels, vec![metric])
+        }
+    };
+
+    json!({
+        "title": "Sensors",
+        "uid": "sensors",
+        "editable": true,
+        "schemaVersion": 41,
+        "time": { "from": "now-1h", "to": "now" },
+        "refresh": "10s",
+        "timezone": "browser",
+        "tags": ["sensors"],
+        "annotations": annotations,
+        "panels": panels,
+        "templating": { "list": templating },
+    })
+}
+
+fn variable(name: &str, label: &str, query: &str, datasource: &Value) -> Value {
+    json!({
+        "name": name,
+        "label": label,
+        "type": "query",
+        "datasource": datasource,
+        "query": query,
+        "definition": query,
+        "refresh": 1,
+        "multi": true,
+        "includeAll": true,
+        "current": { "text": "All", "value": "$__all" },
+        "options": [],
+    })
+}
+
+// Ряд на каждую выбранную величину: колонка metric даёт имя ряда
+fn panel(id: u64, title: &str, filter: &str, datasource: &Value, index: u64) -> Value {
+    let targets = vec![json!({
+        "datasource": datasource,
+        "refId": "A",
+        "editorMode": "code",
+        "format": "time_series",
+        "rawQuery": true,
+        "rawSql": format!(
+            "SELECT\n  ts AS time,\n  metric,\n  value\nFROM measurements\nWHERE {filter} AND metric IN ($metric) AND $__timeFilter(ts)\nORDER BY ts"
+        ),
+    })];
+
+    json!({
+        "id": id,
+        "type": "timeseries",
+        "title": title,
+        "datasource": datasource,
+        "gridPos": { "h": 8, "w": 12, "x": (index % 2) * 12, "y": (index / 2) * 8 },
+        "targets": targets,
+    })
+}
@@ -0,0 +1,67 @@
+use crate::db::Database;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Дольше база не отвечает - считаем её недоступной
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Готов ли сервер принимать показания: для `/readyz` и HEALTHCHECK
+/// контейнера. Флаги выставляет `serve` по ходу запуска.
+#[derive(Default)]
+pub struct Readiness {
+    migrated: AtomicBool,
+    listening: AtomicBool,
+}
+
+impl Readiness {
+    pub fn shared() -> Arc<Readiness> {
+        Arc::new(Readiness::default())
+    }
+
+    pub fn set_migrated(&self) {
+        self.migrated.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_listening(&self) {
+        self.listening.store(true, Ordering::Relaxed);
+    }
+
+    /// Причина, по которой сервер не готов. Базу проверяет при каждом вызове.
+    pub fn check(&self) -> Result<(), String> {
+        if !self.migrated.load(Ordering::Relaxed) {
+            return Err("database migrations not applied yet".to_string());
+        }
+        if !self.listening.load(Ordering::Relaxed) {
+            return Err("sensor listener not bound yet".to_string());
+        }
+        Database::ping(PING_TIMEOUT)
+    }
+}
+
+/// `GET path` к HTTP API по `addr`; успех - только ответ 200. В образе
+/// `scratch` нет curl, поэтому HEALTHCHECK вызывает `server healthcheck`.
+pub fn probe(addr: &str, path: &str, timeout: Duration) -> Result<(), String> {
+    let socket = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("{}: {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("{}: no address", addr))?;
+    let err = |e: std::io::Error| format!("{}: {}", addr, e);
+    let mut stream = TcpStream::connect_timeout(&socket, timeout).map_err(err)?;
+    stream.set_read_timeout(Some(timeout)).map_err(err)?;
+    stream.set_write_timeout(Some(timeout)).map_err(err)?;
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr).map_err(err)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(err)?;
+    let status = response.lines().next().unwrap_or_default();
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body).trim();
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        Some(code) => Err(format!("{} returned {}: {}", path, code, body)),
+        None => Err(format!("{}: malformed response", path)),
+    }
+}
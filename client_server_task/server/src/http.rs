@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info_span, warn};
+
+// Служебный HTTP без зависимостей, по мотивам веб-сервера из главы 21 книги.
+
+const MAX_BODY: usize = 1024 * 1024;
+
+// Клиент, который не забирает ответ дольше этого, отключается
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Токен из заголовка `Authorization: Bearer <token>`.
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.headers
+            .get("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+    }
+}
+
+pub type StreamWriter = Box<dyn FnOnce(&mut TcpStream) -> io::Result<()> + Send>;
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    /// Тело пишется по мере готовности, пока функция не вернётся; длина заранее неизвестна.
+    Stream(StreamWriter),
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status,
+            content_type,
+            body: Body::Bytes(body.into()),
+        }
+    }
+
+    pub fn stream<F>(content_type: &'static str, writer: F) -> Self
+    where
+        F: FnOnce(&mut TcpStream) -> io::Result<()> + Send + 'static,
+    {
+        Response {
+            status: 200,
+            content_type,
+            body: Body::Stream(Box::new(writer)),
+        }
+    }
+
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body.into())
+    }
+
+    pub fn not_found() -> Self {
+        Self::text(404, "not found\n")
+    }
+}
+
+/// Запускает обработку запросов в отдельном потоке, по потоку на соединение.
+pub fn spawn<H>(listener: TcpListener, handler: H)
+where
+    H: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(error = %e, "HTTP connection error");
+                    continue;
+                }
+            };
+            let handler = Arc::clone(&handler);
+            let span = match stream.peer_addr() {
+                Ok(peer) => info_span!("http", %peer),
+                Err(_) => info_span!("http"),
+            };
+            thread::spawn(move || {
+                let _span = span.entered();
+                if let Err(e) = handle_connection(stream, handler.as_ref()) {
+                    warn!(error = %e, "HTTP error");
+                }
+            });
+        }
+    });
+}
+
+fn handle_connection<H>(mut stream: TcpStream, handler: &H) -> io::Result<()>
+where
+    H: Fn(&Request) -> Response,
+{
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let response = match read_request(&stream) {
+        Ok(request) => handler(&request),
+        Err(e) => Response::text(400, format!("{}\n", e)),
+    };
+    write_response(&mut stream, response)
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(invalid("malformed request line")),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query(query)),
+        None => (target.to_string(), HashMap::new()),
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length: usize = match headers.get("content-length") {
+        Some(value) => value.parse().map_err(|_| invalid("bad content-length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(invalid("body too large"));
+    }
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+fn write_response(stream: &mut TcpStream, response: Response) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+    );
+    match response.body {
+        Body::Bytes(body) => {
+            head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+            stream.write_all(head.as_bytes())?;
+            stream.write_all(&body)?;
+            stream.flush()
+        }
+        Body::Stream(writer) => {
+            head.push_str("Cache-Control: no-cache\r\n\r\n");
+            stream.write_all(head.as_bytes())?;
+            stream.flush()?;
+            writer(stream)
+        }
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (percent_decode(key), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|d| d as u8)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -0,0 +1,273 @@
+use protocol::Frame;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{TimeZone, Utc};
+use anomaly::{Anomaly, AnomalyConfig, AnomalyDetector};
+use calibration::{Calibration, Calibrations, Metric};
+use clock::ClockTracker;
+use db::Reading;
+use measurement::Measurement;
+use metrics::SharedMetrics;
+use sequence::{SequenceEvent, SequenceTracker};
+use store::Store;
+use tenant::Registry;
+use tracing::{debug, error, warn};
+
+pub mod anomaly;
+pub mod api;
+pub mod calibration;
+pub mod capture;
+pub mod clock;
+pub mod commands;
+pub mod db;
+pub mod export;
+pub mod firmware;
+pub mod grafana;
+pub mod health;
+pub mod http;
+pub mod live;
+pub mod logging;
+pub mod measurement;
+pub mod metrics;
+pub mod pipeline;
+pub mod pool;
+pub mod sequence;
+pub mod store;
+pub mod tenant;
+pub use protocol::data;
+
+// Версия прошивки длиннее - скорее мусор в кадре, чем версия
+const MAX_FIRMWARE_VERSION_LEN: usize = 64;
+
+// Как часто перечитывать калибровки и реестр устройств, заданные через API
+// или `server calibrate` / `server tenant`
+const REGISTRY_REFRESH: Duration = Duration::from_secs(30);
+
+/// Принятое показание и всё, что о нём нужно записать.
+pub struct Accepted {
+    pub reading: Reading,
+    pub gap: Option<SequenceEvent>,
+    pub anomalies: Vec<Anomaly>,
+    /// Состояние платы, если оно пришло с этим показанием.
+    pub health: Option<data::Health>,
+}
+
+/// Состояние приёма по устройствам: часы, нумерация, калибровки, владельцы, аномалии.
+/// Само ничего не записывает - это делает вызывающий, синхронно
+/// (`process_frame`) или через `pipeline`.
+pub struct Ingest {
+    clock: ClockTracker,
+    sequence: SequenceTracker,
+    calibrations: Calibrations,
+    registry: Registry,
+    refreshed: Option<Instant>,
+    anomalies: AnomalyDetector,
+    metrics: SharedMetrics,
+}
+
+impl Ingest {
+    pub fn new(metrics: SharedMetrics) -> Self {
+        Ingest {
+            clock: ClockTracker::from_env(),
+            sequence: SequenceTracker::default(),
+            calibrations: Calibrations::default(),
+            registry: Registry::default(),
+            refreshed: None,
+            anomalies: AnomalyDetector::new(AnomalyConfig::from_env()),
+            metrics,
+        }
+    }
+
+    /// Пора ли перечитать калибровки и реестр устройств. Перечитывание сразу
+    /// считается начатым, чтобы параллельные соединения не загружали их одновременно.
+    pub fn refresh_due(&mut self) -> bool {
+        if self.refreshed.is_some_and(|t| t.elapsed() < REGISTRY_REFRESH) {
+            return false;
+        }
+        self.refreshed = Some(Instant::now());
+        true
+    }
+
+    pub fn set_calibrations(&mut self, rows: Result<Vec<(u32, Calibration)>, String>) {
+        match rows {
+            Ok(rows) => self.calibrations = Calibrations::new(rows),
+            Err(e) => error!(error = %e, "Calibration load error"),
+        }
+    }
+
+    pub fn set_device_tenants(&mut self, rows: Result<Vec<(u32, String)>, String>) {
+        match rows {
+            Ok(rows) => self.registry = Registry::new(rows),
+            Err(e) => error!(error = %e, "Device registry load error"),
+        }
+    }
+
+    /// Синхронный путь (replay): разбор кадра и запись в `store`.
+    /// Возвращает device_id принятого показания.
+    pub fn process_frame(&mut self, store: &mut dyn Store, frame: &[u8], received: SystemTime) -> Result<u32, String> {
+        if self.refresh_due() {
+            self.set_calibrations(store.load_calibrations());
+            self.set_device_tenants(store.load_device_tenants());
+        }
+        let accepted = self.accept(frame, received)?;
+        let reading = &accepted.reading;
+        if let Some(gap) = &accepted.gap {
+            store.save_gap(reading, gap);
+        }
+        store.save(reading);
+        for anomaly in &accepted.anomalies {
+            store.save_anomaly(reading, anomaly);
+        }
+        if let Some(health) = &accepted.health {
+            store.save_health(reading, health);
+        }
+        Ok(reading.device_id)
+    }
+
+    /// Общий путь для живых и воспроизводимых кадров: декодирование, проверка,
+    /// обновление состояния. Отклонённые кадры попадают в метрики.
+    pub fn accept(&mut self, frame: &[u8], received: SystemTime) -> Result<Accepted, String> {
+        let data = self.decode(frame)?;
+        self.accept_data(data, received)
+    }
+
+    /// Декодирование отдельно - чтобы отличить запрос прошивки от показания.
+    pub fn decode(&mut self, frame: &[u8]) -> Result<data::Data, String> {
+        let result = data::Data::decode_frame(frame).map_err(|e| e.to_string());
+        self.count_rejected(result)
+    }
+
+    pub fn accept_data(&mut self, data: data::Data, received: SystemTime) -> Result<Accepted, String> {
+        let result = self.analyze(data, received);
+        self.count_rejected(result)
+    }
+
+    fn count_rejected<T>(&mut self, result: Result<T, String>) -> Result<T, String> {
+        if result.is_err() {
+            self.metrics.lock().unwrap().frames_rejected += 1;
+        }
+        result
+    }
+
+    fn analyze(&mut self, mut data: data::Data, received: SystemTime) -> Result<Accepted, String> {
+        if data.firmware_request.is_some() {
+            return Err(format!("firmware request from device {} is not a reading", data.device_id));
+        }
+        let ts = data
+            .read_time
+            .as_ref()
+            .ok_or(format!("missing read_time from device {}", data.device_id))?;
+        let read_time = u32::try_from(ts.nanos)
+            .ok()
+            .and_then(|nanos| Utc.timestamp_opt(ts.seconds, nanos).single())
+            .ok_or(format!("invalid read_time from device {}", data.device_id))?;
+        let mut measurements = self.measurements(&mut data)?;
+
+        let clock = self.clock.check(data.device_id, read_time, received.into());
+        if clock.skewed {
+            warn!(device_id = data.device_id, offset_ms = clock.offset_ms, "Clock skew");
+        }
+
+        let sequence = self.sequence.observe(data.device_id, data.event_id);
+        if let Some(event) = &sequence {
+            warn!(
+                device_id = data.device_id,
+                kind = %event.kind,
+                expected = event.expected,
+                received = event.received,
+                "Sequence break"
+            );
+        }
+        self.metrics
+            .lock()
+            .unwrap()
+            .record_reading(data.device_id, sequence.as_ref());
+
+        // сырые значения сохраняются рядом с откалиброванными
+        let id = data.device_id;
+        for m in &mut measurements {
+            if let Ok(metric) = m.metric.parse::<Metric>() {
+                m.value = self.calibrations.apply(id, metric, m.raw_value, read_time);
+            }
+        }
+        let reading = Reading {
+            device_id: id,
+            event_id: data.event_id,
+            measurements,
+            read_time,
+            clock,
+            tenant_id: self.registry.tenant(id).map(String::from),
+        };
+
+        debug!(
+            device_id = id,
+            event_id = reading.event_id,
+            measurements = ?reading.measurements,
+            health = ?data.health,
+            "Reading accepted"
+        );
+
+        // испорченное состояние платы не повод терять само показание
+        let health = data.health.take().filter(|health| match check_health(health) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(device_id = id, error = %e, "Ignoring health");
+                false
+            }
+        });
+        if let Some(health) = &health {
+            self.metrics.lock().unwrap().record_health(id, health);
+        }
+
+        let mut anomalies = Vec::new();
+        for m in &reading.measurements {
+            let Ok(metric) = m.metric.parse::<Metric>() else {
+                continue;
+            };
+            for anomaly in self.anomalies.observe(id, metric, m.value) {
+                warn!(device_id = id, "Anomaly: {}", anomaly.message);
+                anomalies.push(anomaly);
+            }
+        }
+        Ok(Accepted {
+            reading,
+            gap: sequence,
+            anomalies,
+            health,
+        })
+    }
+
+    // Величины кадра в единицах сервера. Негодные величины отбрасываются,
+    // кадр без единой годной отклоняется.
+    fn measurements(&self, data: &mut data::Data) -> Result<Vec<Measurement>, String> {
+        let id = data.device_id;
+        let sent = if data.measurements.is_empty() {
+            measurement::legacy(data)
+        } else {
+            std::mem::take(&mut data.measurements)
+        };
+        let mut measurements: Vec<Measurement> = Vec::with_capacity(sent.len());
+        for m in &sent {
+            match measurement::normalize(m) {
+                Ok(m) if measurements.iter().any(|seen| seen.metric == m.metric) => {
+                    warn!(device_id = id, metric = %m.metric, "Ignoring duplicate measurement")
+                }
+                Ok(m) => measurements.push(m),
+                Err(e) => warn!(device_id = id, error = %e, "Ignoring measurement"),
+            }
+        }
+        if measurements.is_empty() {
+            return Err(format!("no valid measurements from device {}", id));
+        }
+        Ok(measurements)
+    }
+}
+
+fn check_health(health: &data::Health) -> Result<(), String> {
+    if !health.battery_voltage.is_finite() || health.battery_voltage < 0.0 {
+        return Err(format!("invalid battery voltage {}", health.battery_voltage));
+    }
+    if health.firmware_version.len() > MAX_FIRMWARE_VERSION_LEN {
+        return Err(format!("firmware version longer than {} bytes", MAX_FIRMWARE_VERSION_LEN));
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+// Сколько событий может ждать одного подписчика
+const SUBSCRIBER_BUFFER: usize = 256;
+
+// Сколько событий подряд можно потерять, прежде чем отключить подписчика
+const MAX_DROPPED: usize = 1024;
+
+struct Subscriber {
+    tenant_id: Option<String>,
+    devices: Option<HashSet<u32>>,
+    sender: SyncSender<Arc<str>>,
+    dropped: usize,
+}
+
+/// Раздаёт принятые показания подписчикам (SSE).
+///
+/// Приём показаний никогда не ждёт подписчиков: у каждого свой
+/// ограниченный буфер, и если он полон, событие для этого подписчика
+/// выбрасывается. Подписчик, который долго ничего не забирает, отключается.
+#[derive(Default)]
+pub struct Broadcaster {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Broadcaster {
+    pub fn shared() -> Arc<Broadcaster> {
+        Arc::new(Broadcaster::default())
+    }
+
+    /// `tenant_id = None` - устройства всех команд, `devices = None` - все устройства.
+    pub fn subscribe(&self, tenant_id: Option<String>, devices: Option<HashSet<u32>>) -> Receiver<Arc<str>> {
+        let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().unwrap().push(Subscriber {
+            tenant_id,
+            devices,
+            sender,
+            dropped: 0,
+        });
+        receiver
+    }
+
+    pub fn publish(&self, device_id: u32, tenant_id: Option<&str>, event: &str) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let event: Arc<str> = Arc::from(event);
+        subscribers.retain_mut(|subscriber| {
+            if subscriber.tenant_id.as_deref().is_some_and(|t| Some(t) != tenant_id) {
+                return true;
+            }
+            if subscriber.devices.as_ref().is_some_and(|d| !d.contains(&device_id)) {
+                return true;
+            }
+            match subscriber.sender.try_send(Arc::clone(&event)) {
+                Ok(()) => {
+                    subscriber.dropped = 0;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    subscriber.dropped += 1;
+                    if subscriber.dropped >= MAX_DROPPED {
+                        tracing::warn!(dropped = subscriber.dropped, "Dropping slow live subscriber");
+                        return false;
+                    }
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+}
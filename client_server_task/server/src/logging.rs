@@ -0,0 +1,28 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_FILTER: &str = "info";
+
+/// Журнал сервера в stderr; stdout остаётся выводу команд (`server export`).
+///
+/// `LOG_LEVEL` - уровень и фильтры по модулям в синтаксисе `RUST_LOG`,
+/// например `info,server::pipeline=debug` (по умолчанию `info`).
+/// `LOG_FORMAT` - `text` (по умолчанию) или `json`, по объекту на строку.
+pub fn init() -> Result<(), String> {
+    let filter = env::var("LOG_LEVEL")
+        .ok()
+        .filter(|f| !f.is_empty())
+        .unwrap_or_else(|| DEFAULT_FILTER.to_string());
+    let filter = EnvFilter::builder()
+        .parse(&filter)
+        .map_err(|e| format!("Invalid LOG_LEVEL {:?}: {}", filter, e))?;
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(io::stderr);
+    match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => builder.json().flatten_event(true).with_current_span(true).with_span_list(false).init(),
+        Ok("text") | Ok("") | Err(_) => builder.with_ansi(io::stderr().is_terminal()).init(),
+        Ok(other) => return Err(format!("Invalid LOG_FORMAT {:?}: expected text or json", other)),
+    }
+    Ok(())
+}
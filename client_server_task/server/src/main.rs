@@ -1,96 +1,413 @@
-    use prost::Message;
-    use std::net::{TcpListener, TcpStream};
-    use std::io::{BufReader, Read};
-    use postgres::{Client, NoTls};
-    use chrono::{TimeZone, Utc};
-    use std::env;
-    mod data {
-        include!(concat!(env!("OUT_DIR"), "/_.rs"));
-    }
+    use std::net::TcpListener;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use std::{env, process, thread};
+    use chrono::{DateTime, Utc};
+    use server::api::Api;
+    use server::calibration::CalibrationRequest;
+    use server::capture::{CaptureReader, CaptureWriter};
+    use server::commands::CommandQueue;
+    use server::db::{Database, DbError, SharedDatabase};
+    use server::export::{self, ExportQuery};
+    use server::firmware::FirmwareStore;
+    use server::grafana::{self, Layout};
+    use server::health::{self, Readiness};
+    use server::http;
+    use server::live::Broadcaster;
+    use server::logging;
+    use server::metrics::Metrics;
+    use server::pipeline::Pipeline;
+    use server::pool::PgPool;
+    use server::tenant;
+    use server::Ingest;
+    use tracing::{info, info_span, warn};
+
+    // Пока база не поднялась, пробуем снова с такой паузой
+    const DATABASE_RETRY: Duration = Duration::from_secs(2);
 
-    struct Database(Client);
-
-    impl Database {
-        fn new() -> Self {
-            // let mut client = Client::connect("host=localhost user=postgres password=0330", NoTls).unwrap();
-
-            let database_url = env::var("DATABASE_URL")
-            .expect("nnn");
-            let mut client = Client::connect(&database_url, NoTls)
-                .unwrap();
-
-
-            
-            client.batch_execute(
-                "CREATE TABLE IF NOT EXISTS sensor_data (
-                    device_id BIGINT NOT NULL,
-                    event_id BIGINT NOT NULL,
-                    humidity REAL NOT NULL,
-                    temperature REAL NOT NULL,
-                    read_time TIMESTAMP NOT NULL
-                )"
-            ).unwrap();
-            Self(client)
-        }
-
-        fn save(&mut self, data: &data::Data) {
-
-            let read_time = data.read_time.as_ref().map(|ts| {
-                Utc.timestamp_opt(ts.seconds, ts.nanos as u32)
-                    .single()
-                    .unwrap()
-                    .naive_utc()
-            }).unwrap();
-
-            println!("DATA: {:?}", data);
-
-            self.0.execute(
-                "INSERT INTO sensor_data VALUES ($1, $2, $3, $4, $5)",
-                &[
-                    &(data.device_id as i64),
-                    &(data.event_id as i64),
-                    &data.humidity,
-                    &data.temperature,
-                    &read_time
-                ]
-            ).unwrap();
+    fn serve() -> Result<(), String> {
+        let metrics = Metrics::shared();
+        let live = Broadcaster::shared();
+        let readiness = Readiness::shared();
+        let ingest = Ingest::new(metrics.clone());
+        let capture = CaptureWriter::from_env().map_err(|e| format!("CAPTURE_DIR: {}", e))?;
+        let address = env::var("ADDRESS").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let port = env::var("PORT").unwrap_or_else(|_| "7878".to_string());
+        let listen_addr = format!("{}:{}", address, port);
+
+        let http_port = env::var("HTTP_PORT").unwrap_or_else(|_| "8080".to_string());
+        let http_addr = format!("{}:{}", address, http_port);
+        let commands = CommandQueue::shared();
+        let admin_token = env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+        if admin_token.is_none() {
+            warn!("ADMIN_TOKEN is not set, admin API routes are disabled");
+        }
+        let api = Api {
+            metrics,
+            commands: commands.clone(),
+            live: live.clone(),
+            readiness: readiness.clone(),
+            admin_token: admin_token.clone(),
+            db: SharedDatabase::default(),
+        };
+        http::spawn(TcpListener::bind(&http_addr).unwrap(), move |request| api.handle(request));
+        info!(addr = %http_addr, "HTTP API started");
+
+        // таблицы создаются синхронным клиентом, до запуска runtime;
+        // до тех пор /readyz отвечает 503
+        let mut db = loop {
+            match Database::open() {
+                Ok(db) => break db,
+                Err(e @ DbError::NotConfigured) => return Err(e.to_string()),
+                Err(e) => {
+                    warn!(error = %e, retry_in = ?DATABASE_RETRY, "Database error");
+                    thread::sleep(DATABASE_RETRY);
+                }
+            }
+        };
+        // команды без ADMIN_TOKEN - ошибка настройки, а не открытый режим
+        if admin_token.is_none() && !db.tenants().map_err(|e| e.to_string())?.is_empty() {
+            return Err("tenants are configured, set ADMIN_TOKEN to serve the HTTP API".to_string());
         }
+        drop(db);
+        readiness.set_migrated();
+
+        let firmware = FirmwareStore::from_env();
+        let pipeline = Arc::new(Pipeline::new(ingest, PgPool::from_env(), capture, commands, live, firmware));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind(&listen_addr).await.unwrap();
+            readiness.set_listening();
+            info!(addr = %listen_addr, "Server started");
+            // let mut db = Database::new();
+            // let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+            // println!("Server started on 127.0.0.1:7878");
+
+            pipeline.serve(listener).await
+        });
+        Ok(())
     }
 
-    fn handle_client(stream: TcpStream, db: &mut Database) {
-        let mut reader = BufReader::new(stream);
-        let mut len_buf = [0u8; 4];
+    // server replay <file> [--speed <factor>]
+    // speed 1 - исходный темп, 10 - в десять раз быстрее, 0 - без пауз
+    fn replay(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+        let path = args.next().ok_or("Usage: server replay <file> [--speed <factor>]")?;
+        let mut speed = 1.0;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--speed" => {
+                    let value = args.next().ok_or("Missing value after --speed")?;
+                    speed = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|s| s.is_finite() && *s >= 0.0)
+                        .ok_or(format!("Invalid speed: {}", value))?;
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
 
-        while reader.read_exact(&mut len_buf).is_ok() {
-            let len = u32::from_le_bytes(len_buf) as usize;
-            let mut proto_data = vec![0u8; len];
-            if reader.read_exact(&mut proto_data).is_err() {
-                break;
+        let reader = CaptureReader::open(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let _span = info_span!("replay", file = %path).entered();
+        let mut db = Database::new();
+        let mut ingest = Ingest::new(Metrics::shared());
+        let (mut saved, mut rejected) = (0u64, 0u64);
+        let mut previous: Option<SystemTime> = None;
+
+        for record in reader {
+            let record = record.map_err(|e| format!("{}: {}", path, e))?;
+            if let (Some(prev), true) = (previous, speed > 0.0) {
+                let gap = record.received.duration_since(prev).unwrap_or(Duration::ZERO);
+                thread::sleep(gap.div_f64(speed));
             }
+            previous = Some(record.received);
 
-            if let Ok(data) = data::Data::decode(&proto_data[..]) {
-                println!("Data from device {}", data.device_id);
-                db.save(&data);
+            match ingest.process_frame(&mut db, &record.frame, record.received) {
+                Ok(_) => saved += 1,
+                Err(e) => {
+                    warn!(error = %e, "Rejected frame");
+                    rejected += 1;
+                }
             }
         }
+
+        println!("Replay finished: {} saved, {} rejected", saved, rejected);
+        Ok(())
     }
 
-    fn main() {
+    // Флаги вида --name value
+    fn parse_flags(args: impl Iterator<Item = String>) -> Result<HashMap<String, String>, String> {
+        let mut flags = HashMap::new();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                return Err(format!("Expected argument starting with --, got {}", arg));
+            }
+            let value = args.next().ok_or(format!("Missing value after {}", arg))?;
+            flags.insert(arg, value);
+        }
+        Ok(flags)
+    }
+
+    fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| format!("Invalid time {}: {}", value, e))
+    }
+
+    // server calibrate --device <id> --metric temperature|humidity
+    //     (--reference <value> [--at <time>] | --offset <value>) [--gain <value>] [--from <time>]
+    fn calibrate(args: impl Iterator<Item = String>) -> Result<(), String> {
+        let mut flags = parse_flags(args)?;
+        let mut take = |name: &str| flags.remove(name);
+        let number = |name: &str, value: Option<String>| -> Result<Option<f64>, String> {
+            value
+                .map(|v| v.parse::<f64>().map_err(|_| format!("Invalid {}: {}", name, v)))
+                .transpose()
+        };
+
+        let device_id = take("--device")
+            .ok_or("Missing --device")?
+            .parse::<u32>()
+            .map_err(|e| format!("Invalid --device: {}", e))?;
+        let request = CalibrationRequest {
+            metric: take("--metric").ok_or("Missing --metric")?.parse()?,
+            reference: number("--reference", take("--reference"))?,
+            at: take("--at").as_deref().map(parse_time).transpose()?,
+            gain: number("--gain", take("--gain"))?,
+            offset: number("--offset", take("--offset"))?,
+            valid_from: take("--from").as_deref().map(parse_time).transpose()?,
+        };
+        if let Some(unknown) = flags.keys().next() {
+            return Err(format!("Unknown argument: {}", unknown));
+        }
+
         let mut db = Database::new();
-        let address = env::var("ADDRESS").unwrap_or_else(|_| "0.0.0.0".to_string());
-        let port = env::var("PORT").unwrap_or_else(|_| "7878".to_string());
-        let listen_addr = format!("{}:{}", address, port);
-        
-        let listener = TcpListener::bind(&listen_addr).unwrap();
-        println!("Server started on {}", listen_addr);
-        // let mut db = Database::new();
-        // let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-        // println!("Server started on 127.0.0.1:7878");
-
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => handle_client(stream, &mut db),
-                Err(e) => eprintln!("Connection error: {}", e),
+        let calibration = request.resolve(&mut db, device_id)?;
+        let calibration = db.insert_calibration(device_id, &calibration).map_err(|e| e.to_string())?;
+        print!(
+            "Device {} {}: gain {} offset {:+} from {}",
+            device_id, calibration.metric, calibration.gain, calibration.offset, calibration.valid_from
+        );
+        match calibration.valid_to {
+            Some(to) => println!(" to {}", to),
+            None => println!(),
+        }
+        Ok(())
+    }
+
+    // server grafana-export [--layout templated|per-device] [--datasource <uid>] [--tenant <id>] [--out <file>]
+    fn grafana_export(args: impl Iterator<Item = String>) -> Result<(), String> {
+        let mut flags = parse_flags(args)?;
+        let layout: Layout = flags.remove("--layout").as_deref().unwrap_or("templated").parse()?;
+        let datasource = flags
+            .remove("--datasource")
+            .unwrap_or_else(|| grafana::DEFAULT_DATASOURCE_UID.to_string());
+        let tenant_id = flags.remove("--tenant");
+        let out = flags.remove("--out");
+        if let Some(unknown) = flags.keys().next() {
+            return Err(format!("Unknown argument: {}", unknown));
+        }
+
+        // шаблонный вариант берёт список устройств запросом из самой Grafana
+        let devices = match layout {
+            Layout::Templated => Vec::new(),
+            Layout::PerDevice => Database::connect()
+                .map_err(|e| e.to_string())?
+                .devices(tenant_id.as_deref())
+                .map_err(|e| e.to_string())?,
+        };
+
+        let dashboard = grafana::dashboard(layout, &devices, &datasource);
+        let json = serde_json::to_string_pretty(&dashboard).map_err(|e| e.to_string())? + "\n";
+        match out {
+            Some(path) => {
+                std::fs::write(&path, json).map_err(|e| format!("{}: {}", path, e))?;
+                println!("Dashboard written to {}", path);
             }
+            None => print!("{}", json),
+        }
+        Ok(())
+    }
+
+    // server export [--tenant <id>] [--device <id>] [--from <time>] [--to <time>]
+    //     [--format csv|parquet|jsonl] [--out <file>]
+    fn export(args: impl Iterator<Item = String>) -> Result<(), String> {
+        let mut flags = parse_flags(args)?;
+        let out = flags.remove("--out");
+        let query = ExportQuery::from_params(|name| flags.remove(&format!("--{}", name)))?;
+        if let Some(unknown) = flags.keys().next() {
+            return Err(format!("Unknown argument: {}", unknown));
+        }
+
+        let mut db = Database::connect().map_err(|e| e.to_string())?;
+        let count = match &out {
+            Some(path) => {
+                let file = std::fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+                export::export(&mut db, &query, file)
+            }
+            None => export::export(&mut db, &query, std::io::stdout()),
+        }
+        .map_err(|e| e.to_string())?;
+        if let Some(path) = out {
+            println!("Exported {} rows to {}", count, path);
+        }
+        Ok(())
+    }
+
+    // server tenant add --id <id> [--name <name>]
+    // server tenant token --id <id>
+    // server tenant assign --device <id> --tenant <id>
+    // server tenant list
+    fn tenant(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+        let usage = "Usage: server tenant add|token|assign|list [flags]";
+        let command = args.next().ok_or(usage)?;
+        let mut flags = parse_flags(args)?;
+        let mut take = |name: &str| flags.remove(name);
+        let id = take("--id");
+        let name = take("--name");
+        let device = take("--device");
+        let tenant_id = take("--tenant");
+        if let Some(unknown) = flags.keys().next() {
+            return Err(format!("Unknown argument: {}", unknown));
+        }
+
+        match command.as_str() {
+            "add" => {
+                let id = id.ok_or("Missing --id")?;
+                tenant::validate_id(&id)?;
+                let name = name.unwrap_or_else(|| id.clone());
+                let token = tenant::generate_token();
+                Database::new()
+                    .add_tenant(&id, &name, &tenant::hash_token(&token))
+                    .map_err(|e| e.to_string())?;
+                println!("Tenant {} added, API token (shown once): {}", id, token);
+            }
+            "token" => {
+                let id = id.ok_or("Missing --id")?;
+                let token = tenant::generate_token();
+                let updated = Database::new()
+                    .set_tenant_token(&id, &tenant::hash_token(&token))
+                    .map_err(|e| e.to_string())?;
+                if !updated {
+                    return Err(format!("Unknown tenant: {}", id));
+                }
+                println!("New API token for {} (shown once, the old one no longer works): {}", id, token);
+            }
+            "assign" => {
+                let device_id = device
+                    .ok_or("Missing --device")?
+                    .parse::<u32>()
+                    .map_err(|e| format!("Invalid --device: {}", e))?;
+                let tenant_id = tenant_id.ok_or("Missing --tenant")?;
+                let claimed = Database::new()
+                    .assign_device(device_id, &tenant_id)
+                    .map_err(|e| e.to_string())?;
+                println!(
+                    "Device {} assigned to {}, {} earlier readings moved to it",
+                    device_id, tenant_id, claimed
+                );
+            }
+            "list" => {
+                for tenant in Database::new().tenants().map_err(|e| e.to_string())? {
+                    let devices: Vec<String> = tenant.devices.iter().map(|id| id.to_string()).collect();
+                    println!("{}\t{}\t{}", tenant.id, tenant.name, devices.join(","));
+                }
+            }
+            _ => return Err(usage.to_string()),
+        }
+        Ok(())
+    }
+
+    // server firmware add --version <version> --file <image>
+    // server firmware list
+    fn firmware(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+        let usage = "Usage: server firmware add|list [flags]";
+        let command = args.next().ok_or(usage)?;
+        let mut flags = parse_flags(args)?;
+        let version = flags.remove("--version");
+        let file = flags.remove("--file");
+        if let Some(unknown) = flags.keys().next() {
+            return Err(format!("Unknown argument: {}", unknown));
+        }
+
+        match command.as_str() {
+            "add" => {
+                let version = version.ok_or("Missing --version")?;
+                let file = file.ok_or("Missing --file")?;
+                let image = std::fs::read(&file).map_err(|e| format!("{}: {}", file, e))?;
+                let mut db = Database::new();
+                let store = FirmwareStore::from_env();
+                let firmware = store.add(&version, &image)?;
+                // без строки в таблице образ никто не запросит
+                if let Err(e) = db.add_firmware(&firmware) {
+                    let _ = store.remove(&version);
+                    return Err(e.to_string());
+                }
+                println!("Firmware {} added: {} bytes, sha256 {}", version, firmware.size, firmware.sha256);
+            }
+            "list" => {
+                for firmware in Database::new().firmwares().map_err(|e| e.to_string())? {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        firmware.version, firmware.size, firmware.sha256, firmware.uploaded_at
+                    );
+                }
+            }
+            _ => return Err(usage.to_string()),
+        }
+        Ok(())
+    }
+
+    // server healthcheck [--path /readyz|/healthz] [--timeout <secs>]
+    // Для HEALTHCHECK контейнера: запрос к HTTP API этого же сервера
+    // (ADDRESS и HTTP_PORT), код выхода 0 - ответ 200.
+    fn healthcheck(args: impl Iterator<Item = String>) -> Result<(), String> {
+        let mut flags = parse_flags(args)?;
+        let path = flags.remove("--path").unwrap_or_else(|| "/readyz".to_string());
+        let timeout = match flags.remove("--timeout") {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|t| t.is_finite() && *t > 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or(format!("Invalid timeout: {}", value))?,
+            None => Duration::from_secs(3),
+        };
+        if let Some(unknown) = flags.keys().next() {
+            return Err(format!("Unknown argument: {}", unknown));
+        }
+
+        let address = match env::var("ADDRESS").as_deref() {
+            Ok("") | Ok("0.0.0.0") | Err(_) => "127.0.0.1".to_string(),
+            Ok(address) => address.to_string(),
+        };
+        let http_port = env::var("HTTP_PORT").unwrap_or_else(|_| "8080".to_string());
+        health::probe(&format!("{}:{}", address, http_port), &path, timeout)
+    }
+
+    fn main() {
+        if let Err(e) = logging::init() {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+
+        let mut args = env::args().skip(1);
+        let result = match args.next().as_deref() {
+            None | Some("serve") => serve(),
+            Some("replay") => replay(args),
+            Some("calibrate") => calibrate(args),
+            Some("grafana-export") => grafana_export(args),
+            Some("export") => export(args),
+            Some("tenant") => tenant(args),
+            Some("firmware") => firmware(args),
+            Some("healthcheck") => healthcheck(args),
+            Some(command) => Err(format!("Unknown command: {}", command)),
+        };
+
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            process::exit(1);
         }
     }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use crate::data;
+use serde::Serialize;
+
+// Имя величины попадает в SQL-выборки и подписи графиков
+const MAX_METRIC_LEN: usize = 64;
+
+/// Величина показания в единицах сервера. `raw_value` - до калибровки.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Measurement {
+    pub metric: String,
+    pub value: f64,
+    #[serde(skip)]
+    pub raw_value: f64,
+    pub unit: String,
+}
+
+// Единица, которой может прислать плата, и перевод из неё в единицу сервера
+type Alias = (&'static str, fn(f64) -> f64);
+
+// Единица сервера для известной величины и чем её может прислать плата
+struct Conversion {
+    metric: &'static str,
+    unit: &'static str,
+    aliases: &'static [Alias],
+}
+
+const CONVERSIONS: &[Conversion] = &[
+    Conversion {
+        metric: "temperature",
+        unit: "°C",
+        aliases: &[
+            ("C", |v| v),
+            ("degC", |v| v),
+            ("celsius", |v| v),
+            ("°F", |v| (v - 32.0) * 5.0 / 9.0),
+            ("F", |v| (v - 32.0) * 5.0 / 9.0),
+            ("degF", |v| (v - 32.0) * 5.0 / 9.0),
+            ("fahrenheit", |v| (v - 32.0) * 5.0 / 9.0),
+            ("K", |v| v - 273.15),
+            ("kelvin", |v| v - 273.15),
+        ],
+    },
+    Conversion {
+        metric: "humidity",
+        unit: "%",
+        aliases: &[("%RH", |v| v), ("percent", |v| v), ("ratio", |v| v * 100.0)],
+    },
+    Conversion {
+        metric: "pressure",
+        unit: "hPa",
+        aliases: &[
+            ("mbar", |v| v),
+            ("Pa", |v| v / 100.0),
+            ("kPa", |v| v * 10.0),
+            ("mmHg", |v| v * 1.333_223_874),
+            ("inHg", |v| v * 33.863_886_67),
+        ],
+    },
+    Conversion {
+        metric: "co2",
+        unit: "ppm",
+        aliases: &[("ppb", |v| v / 1000.0), ("%", |v| v * 10_000.0)],
+    },
+];
+
+/// Проверяет величину и переводит её в единицу сервера. Пустая единица у
+/// известной величины означает единицу сервера; неизвестные величины
+/// сохраняются как есть, в присланной единице.
+pub fn normalize(measurement: &data::Measurement) -> Result<Measurement, String> {
+    let metric = measurement.metric.as_str();
+    let valid_name = !metric.is_empty()
+        && metric.len() <= MAX_METRIC_LEN
+        && metric
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid_name {
+        return Err(format!("invalid metric name {:?}", metric));
+    }
+    if !measurement.value.is_finite() {
+        return Err(format!("{}: value {} is not a number", metric, measurement.value));
+    }
+
+    let unit = measurement.unit.trim();
+    let (value, unit) = match CONVERSIONS.iter().find(|c| c.metric == metric) {
+        Some(conversion) if unit.is_empty() || unit == conversion.unit => (measurement.value, conversion.unit),
+        Some(conversion) => match conversion.aliases.iter().find(|(alias, _)| *alias == unit) {
+            Some((_, convert)) => (convert(measurement.value), conversion.unit),
+            None => return Err(format!("{}: unknown unit {:?}", metric, unit)),
+        },
+        None => (measurement.value, unit),
+    };
+    Ok(Measurement {
+        metric: metric.to_string(),
+        value,
+        raw_value: value,
+        unit: unit.to_string(),
+    })
+}
+
+/// Старый кадр без `measurements`: температура и влажность из фиксированных полей.
+pub fn legacy(data: &data::Data) -> Vec<data::Measurement> {
+    vec![
+        data::Measurement {
+            metric: "temperature".to_string(),
+            value: widen(data.temperature),
+            unit: "°C".to_string(),
+        },
+        data::Measurement {
+            metric: "humidity".to_string(),
+            value: widen(data.humidity),
+            unit: "%".to_string(),
+        },
+    ]
+}
+
+// f32 как есть, без хвоста из-за перевода в f64 (23.7, а не 23.700000762939453)
+pub(crate) fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
@@ -0,0 +1,90 @@
+use crate::data::Health;
+use crate::measurement::widen;
+use crate::sequence::{SequenceEvent, SequenceKind};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+type DeviceSeries = (&'static str, &'static str, fn(&DeviceMetrics) -> Option<f64>);
+
+#[derive(Default)]
+pub struct DeviceMetrics {
+    pub received: u64,
+    pub missing: u64,
+    pub duplicates: u64,
+    pub resets: u64,
+    /// Последнее состояние платы; `None`, пока плата его не прислала.
+    pub health: Option<Health>,
+}
+
+impl DeviceMetrics {
+    /// Доля потерянных показаний от всех ожидавшихся. Повторы в
+    /// ожидавшиеся не входят: они не новые показания.
+    pub fn loss_rate(&self) -> f64 {
+        let expected = self.received.saturating_sub(self.duplicates) + self.missing;
+        if expected == 0 {
+            0.0
+        } else {
+            self.missing as f64 / expected as f64
+        }
+    }
+}
+
+/// Счётчики сервера, которые отдаются в `/metrics` в текстовом формате Prometheus.
+#[derive(Default)]
+pub struct Metrics {
+    pub frames_rejected: u64,
+    pub devices: BTreeMap<u32, DeviceMetrics>,
+}
+
+impl Metrics {
+    pub fn shared() -> SharedMetrics {
+        Arc::new(Mutex::new(Metrics::default()))
+    }
+
+    pub fn record_reading(&mut self, device_id: u32, sequence: Option<&SequenceEvent>) {
+        let device = self.devices.entry(device_id).or_default();
+        device.received += 1;
+        if let Some(event) = sequence {
+            match event.kind {
+                SequenceKind::Gap => device.missing += event.missing,
+                SequenceKind::Duplicate => device.duplicates += 1,
+                SequenceKind::Reset => device.resets += 1,
+            }
+        }
+    }
+
+    pub fn record_health(&mut self, device_id: u32, health: &Health) {
+        self.devices.entry(device_id).or_default().health = Some(health.clone());
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# TYPE sensor_frames_rejected_total counter").unwrap();
+        writeln!(out, "sensor_frames_rejected_total {}", self.frames_rejected).unwrap();
+
+        let per_device: [DeviceSeries; 9] = [
+            ("sensor_readings_total", "counter", |d| Some(d.received as f64)),
+            ("sensor_missing_readings_total", "counter", |d| Some(d.missing as f64)),
+            ("sensor_duplicate_readings_total", "counter", |d| Some(d.duplicates as f64)),
+            ("sensor_device_resets_total", "counter", |d| Some(d.resets as f64)),
+            ("sensor_packet_loss_ratio", "gauge", |d| Some(d.loss_rate())),
+            ("sensor_battery_volts", "gauge", |d| d.health.as_ref().map(|h| widen(h.battery_voltage))),
+            ("sensor_wifi_rssi_dbm", "gauge", |d| d.health.as_ref().map(|h| h.rssi_dbm as f64)),
+            ("sensor_uptime_seconds", "gauge", |d| d.health.as_ref().map(|h| h.uptime_secs as f64)),
+            ("sensor_free_heap_bytes", "gauge", |d| d.health.as_ref().map(|h| h.free_heap_bytes as f64)),
+        ];
+        for (name, kind, value) in per_device {
+            writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+            for (device_id, device) in &self.devices {
+                if let Some(value) = value(device) {
+                    writeln!(out, "{}{{device_id=\"{}\"}} {}", name, device_id, value).unwrap();
+                }
+            }
+        }
+        out
+    }
+}
+
@@ -0,0 +1,215 @@
+use crate::capture::{CaptureQueue, CaptureWriter};
+use crate::commands::SharedCommands;
+use crate::data;
+use crate::firmware::FirmwareStore;
+use crate::live::Broadcaster;
+use crate::store::AsyncStore;
+use crate::{Accepted, Ingest};
+use futures_util::{SinkExt, StreamExt};
+use protocol::FrameCodec;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_util::codec::Framed;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+
+// Плата шлёт показание и сразу закрывает соединение; молчащее дольше
+// соединение держит сокет впустую
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Асинхронный приём показаний: задача на каждое соединение.
+///
+/// Состояние по устройствам (`Ingest`) защищено обычным мьютексом и
+/// держится только на время разбора кадра; запись в хранилище и ответ
+/// идут без блокировки, так что медленная БД или клиент не задерживают
+/// остальные соединения.
+pub struct Pipeline<S> {
+    ingest: Mutex<Ingest>,
+    store: S,
+    capture: Option<CaptureQueue>,
+    commands: SharedCommands,
+    live: Arc<Broadcaster>,
+    firmware: Arc<FirmwareStore>,
+    // номер соединения для журнала
+    connections: AtomicU64,
+}
+
+impl<S: AsyncStore> Pipeline<S> {
+    pub fn new(
+        ingest: Ingest,
+        store: S,
+        capture: Option<CaptureWriter>,
+        commands: SharedCommands,
+        live: Arc<Broadcaster>,
+        firmware: FirmwareStore,
+    ) -> Self {
+        Pipeline {
+            ingest: Mutex::new(ingest),
+            store,
+            capture: capture.map(CaptureWriter::spawn),
+            commands,
+            live,
+            firmware: Arc::new(firmware),
+            connections: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        // иначе первые кадры после запуска примутся без калибровок и владельцев
+        self.refresh_if_due().await;
+        loop {
+            match listener.accept().await {
+                Ok((socket, peer)) => {
+                    // device_id становится известен с первым кадром
+                    let id = self.connections.fetch_add(1, Ordering::Relaxed) + 1;
+                    let span = info_span!("connection", id, %peer, device_id = field::Empty);
+                    tokio::spawn(Arc::clone(&self).handle_connection(socket).instrument(span));
+                }
+                Err(e) => {
+                    // например, кончились дескрипторы: не крутимся вхолостую
+                    error!(error = %e, "Accept error");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(self: Arc<Self>, socket: TcpStream) {
+        let mut frames = Framed::new(socket, FrameCodec);
+        loop {
+            let body = match timeout(IDLE_TIMEOUT, frames.next()).await {
+                Ok(Some(Ok(body))) => body,
+                Ok(None) => break,
+                Ok(Some(Err(e))) => {
+                    warn!(error = %e, "Connection error");
+                    break;
+                }
+                Err(_) => {
+                    info!(idle = ?IDLE_TIMEOUT, "Connection idle, closing");
+                    break;
+                }
+            };
+
+            let received = SystemTime::now();
+            if let Some(capture) = &self.capture {
+                capture.record(received, &body);
+            }
+
+            let data = match self.ingest.lock().unwrap().decode(&body) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!(error = %e, "Rejected frame");
+                    continue;
+                }
+            };
+            Span::current().record("device_id", data.device_id);
+            if let Some(request) = data.firmware_request {
+                let reply = data::Reply {
+                    commands: Vec::new(),
+                    firmware_chunk: Some(self.firmware_chunk(data.device_id, request).await),
+                };
+                if let Err(e) = frames.send(reply).await {
+                    warn!(error = %e, "Reply error");
+                    break;
+                }
+                continue;
+            }
+
+            let accepted = match self.accept(data, received).await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "Rejected frame");
+                    continue;
+                }
+            };
+            // ответ подтверждает запись, поэтому без записи его нет
+            if let Err(e) = self.persist(&accepted).await {
+                error!(error = %e, "Store error");
+                break;
+            }
+            let reading = &accepted.reading;
+            self.live
+                .publish(reading.device_id, reading.tenant_id.as_deref(), &reading.to_json().to_string());
+
+            if let Err(e) = self.send_reply(&mut frames, reading.device_id).await {
+                warn!(error = %e, "Reply error");
+                break;
+            }
+        }
+    }
+
+    async fn accept(&self, data: data::Data, received: SystemTime) -> Result<Accepted, String> {
+        self.refresh_if_due().await;
+        self.ingest.lock().unwrap().accept_data(data, received)
+    }
+
+    async fn refresh_if_due(&self) {
+        let due = self.ingest.lock().unwrap().refresh_due();
+        if due {
+            let calibrations = self.store.load_calibrations().await;
+            let tenants = self.store.load_device_tenants().await;
+            let mut ingest = self.ingest.lock().unwrap();
+            ingest.set_calibrations(calibrations);
+            ingest.set_device_tenants(tenants);
+        }
+    }
+
+    async fn persist(&self, accepted: &Accepted) -> Result<(), String> {
+        let reading = &accepted.reading;
+        if let Some(gap) = &accepted.gap {
+            self.store.save_gap(reading, gap).await?;
+        }
+        self.store.save(reading).await?;
+        for anomaly in &accepted.anomalies {
+            self.store.save_anomaly(reading, anomaly).await?;
+        }
+        if let Some(health) = &accepted.health {
+            self.store.save_health(reading, health).await?;
+        }
+        Ok(())
+    }
+
+    // Образы общие для всех команд, но устройство получает только версию,
+    // которую ему велели поставить. Файл читается вне рабочих потоков tokio.
+    async fn firmware_chunk(&self, device_id: u32, request: data::FirmwareRequest) -> data::FirmwareChunk {
+        let (version, offset) = (request.version.clone(), request.offset);
+        let refused = |error: String| data::FirmwareChunk {
+            version: version.clone(),
+            offset,
+            error,
+            ..Default::default()
+        };
+        if !self.commands.lock().unwrap().offered(device_id, &version) {
+            warn!(%version, "Firmware was not offered to the device");
+            return refused(format!("firmware {} was not offered to device {}", version, device_id));
+        }
+        let firmware = Arc::clone(&self.firmware);
+        tokio::task::spawn_blocking(move || firmware.chunk(&request))
+            .await
+            .unwrap_or_else(|e| refused(format!("firmware {}: {}", version, e)))
+    }
+
+    // Ответ на показание: ожидающие команды устройства
+    async fn send_reply(&self, frames: &mut Framed<TcpStream, FrameCodec>, device_id: u32) -> io::Result<()> {
+        let pending = self.commands.lock().unwrap().take(device_id);
+        let reply = data::Reply {
+            commands: pending.iter().map(|c| c.to_proto()).collect(),
+            firmware_chunk: None,
+        };
+        match frames.send(reply).await {
+            Ok(()) => {
+                if !pending.is_empty() {
+                    info!(count = pending.len(), "Sent commands");
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.commands.lock().unwrap().restore(device_id, pending);
+                Err(e.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::anomaly::Anomaly;
+use crate::calibration::Calibration;
+use crate::data::Health;
+use crate::db::{self, Reading};
+use crate::sequence::SequenceEvent;
+use crate::store::AsyncStore;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, NoTls};
+
+const DEFAULT_POOL_SIZE: usize = 8;
+
+/// Несколько соединений tokio-postgres, которые раздаются по кругу.
+///
+/// Одно соединение tokio-postgres само конвейеризует запросы из разных
+/// задач, так что очереди на соединение нет; пул нужен, чтобы запись
+/// не упиралась в одно соединение и один процесс на стороне Postgres.
+/// Разорванное соединение переоткрывается при следующем обращении к нему.
+pub struct PgPool {
+    url: String,
+    slots: Vec<Mutex<Option<Arc<Client>>>>,
+    next: AtomicUsize,
+}
+
+impl PgPool {
+    pub fn new(url: impl Into<String>, size: usize) -> Self {
+        PgPool {
+            url: url.into(),
+            slots: (0..size.max(1)).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// `DATABASE_URL` и `DB_POOL_SIZE` (по умолчанию 8).
+    pub fn from_env() -> Self {
+        let url = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let size = env::var("DB_POOL_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_POOL_SIZE);
+        PgPool::new(url, size)
+    }
+
+    pub async fn get(&self) -> Result<Arc<Client>, tokio_postgres::Error> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        let mut slot = self.slots[index].lock().await;
+        if let Some(client) = slot.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(Arc::clone(client));
+        }
+
+        let (client, connection) = tokio_postgres::connect(&self.url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::error!(error = %e, "Database connection error");
+            }
+        });
+        let client = Arc::new(client);
+        *slot = Some(Arc::clone(&client));
+        Ok(client)
+    }
+}
+
+impl AsyncStore for PgPool {
+    async fn save(&self, reading: &Reading) -> Result<(), String> {
+        let row = reading.to_row();
+        let client = self.get().await.map_err(|e| e.to_string())?;
+        client
+            .execute(db::INSERT_READING, &row.params())
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn save_gap(&self, reading: &Reading, event: &SequenceEvent) -> Result<(), String> {
+        let client = self.get().await.map_err(|e| e.to_string())?;
+        client
+            .execute(
+                db::INSERT_GAP,
+                &[
+                    &(reading.device_id as i64),
+                    &event.kind.to_string(),
+                    &(event.expected as i64),
+                    &(event.received as i64),
+                    &(event.missing as i64),
+                    &reading.clock.received_at.naive_utc(),
+                    &reading.tenant_id,
+                ],
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn save_anomaly(&self, reading: &Reading, anomaly: &Anomaly) -> Result<(), String> {
+        let client = self.get().await.map_err(|e| e.to_string())?;
+        client
+            .execute(
+                db::INSERT_ANOMALY,
+                &[
+                    &(reading.device_id as i64),
+                    &anomaly.metric.as_str(),
+                    &anomaly.kind.to_string(),
+                    &anomaly.value,
+                    &anomaly.score,
+                    &anomaly.message,
+                    &reading.read_time.naive_utc(),
+                    &reading.tenant_id,
+                ],
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn save_health(&self, reading: &Reading, health: &Health) -> Result<(), String> {
+        let client = self.get().await.map_err(|e| e.to_string())?;
+        client
+            .execute(
+                db::INSERT_HEALTH,
+                &[
+                    &(reading.device_id as i64),
+                    &health.battery_voltage,
+                    &health.rssi_dbm,
+                    &(health.uptime_secs as i64),
+                    &(health.free_heap_bytes as i64),
+                    &health.firmware_version,
+                    &reading.read_time.naive_utc(),
+                    &reading.clock.received_at.naive_utc(),
+                    &reading.tenant_id,
+                ],
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn load_calibrations(&self) -> Result<Vec<(u32, Calibration)>, String> {
+        let client = self.get().await.map_err(|e| e.to_string())?;
+        let rows = client
+            .query(db::SELECT_CALIBRATIONS, &[&None::<i64>])
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(rows.iter().filter_map(db::calibration_from_row).collect())
+    }
+
+    async fn load_device_tenants(&self) -> Result<Vec<(u32, String)>, String> {
+        let client = self.get().await.map_err(|e| e.to_string())?;
+        let rows = client
+            .query(db::SELECT_DEVICE_TENANTS, &[])
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(rows.iter().map(db::device_tenant_from_row).collect())
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// Насколько номер может отстать от ожидаемого, чтобы считаться опоздавшим
+// кадром; дальше назад - перезагрузка, даже если её кадр 0 потерялся
+const REORDER_WINDOW: u64 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceKind {
+    /// Пропущены одно или несколько показаний.
+    Gap,
+    /// Номер уже был получен (повтор или опоздавший кадр).
+    Duplicate,
+    /// Счётчик начался заново - устройство перезагрузилось.
+    Reset,
+}
+
+impl fmt::Display for SequenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SequenceKind::Gap => "gap",
+            SequenceKind::Duplicate => "duplicate",
+            SequenceKind::Reset => "reset",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Нарушение последовательности `event_id` одного устройства.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceEvent {
+    pub kind: SequenceKind,
+    pub expected: u64,
+    pub received: u64,
+    /// Сколько показаний потеряно (только для `Gap`).
+    pub missing: u64,
+}
+
+/// Помнит последний `event_id` каждого устройства. Клиент увеличивает
+/// номер на единицу на каждое показание и начинает с нуля после старта.
+/// Номер меньше ожидаемого больше чем на `REORDER_WINDOW` - тоже перезапуск.
+#[derive(Default)]
+pub struct SequenceTracker {
+    last: HashMap<u32, u64>,
+}
+
+impl SequenceTracker {
+    pub fn observe(&mut self, device_id: u32, event_id: u64) -> Option<SequenceEvent> {
+        let last = self.last.insert(device_id, event_id)?;
+        let expected = last.wrapping_add(1);
+
+        let (kind, missing) = if event_id == expected {
+            return None;
+        } else if event_id > expected {
+            (SequenceKind::Gap, event_id - expected)
+        } else if event_id == 0 || expected - event_id > REORDER_WINDOW {
+            (SequenceKind::Reset, 0)
+        } else {
+            // повтор не сдвигает последовательность
+            self.last.insert(device_id, last);
+            (SequenceKind::Duplicate, 0)
+        };
+
+        Some(SequenceEvent {
+            kind,
+            expected,
+            received: event_id,
+            missing,
+        })
+    }
+}
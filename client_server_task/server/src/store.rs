@@ -0,0 +1,154 @@
+use crate::anomaly::Anomaly;
+use crate::calibration::Calibration;
+use crate::data::Health;
+use crate::db::Reading;
+use crate::sequence::SequenceEvent;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Куда приём складывает показания синхронно (`server replay`):
+/// Postgres (`Database`) или `MemoryStore`.
+pub trait Store: Send {
+    fn save(&mut self, reading: &Reading);
+    fn save_gap(&mut self, reading: &Reading, event: &SequenceEvent);
+    fn save_anomaly(&mut self, reading: &Reading, anomaly: &Anomaly);
+    fn save_health(&mut self, reading: &Reading, health: &Health);
+    /// Калибровки всех устройств.
+    fn load_calibrations(&mut self) -> Result<Vec<(u32, Calibration)>, String>;
+    /// Реестр устройств: за какой командой закреплено каждое.
+    fn load_device_tenants(&mut self) -> Result<Vec<(u32, String)>, String>;
+}
+
+/// То же для асинхронного приёма (`pipeline`): пул соединений
+/// с Postgres (`PgPool`) или `MemoryStore`.
+pub trait AsyncStore: Send + Sync + 'static {
+    fn save(&self, reading: &Reading) -> impl Future<Output = Result<(), String>> + Send;
+    fn save_gap(&self, reading: &Reading, event: &SequenceEvent) -> impl Future<Output = Result<(), String>> + Send;
+    fn save_anomaly(&self, reading: &Reading, anomaly: &Anomaly) -> impl Future<Output = Result<(), String>> + Send;
+    fn save_health(&self, reading: &Reading, health: &Health) -> impl Future<Output = Result<(), String>> + Send;
+    fn load_calibrations(&self) -> impl Future<Output = Result<Vec<(u32, Calibration)>, String>> + Send;
+    fn load_device_tenants(&self) -> impl Future<Output = Result<Vec<(u32, String)>, String>> + Send;
+}
+
+#[derive(Default)]
+struct MemoryData {
+    readings: Vec<Reading>,
+    gaps: Vec<(u32, SequenceEvent)>,
+    anomalies: Vec<(u32, Anomaly)>,
+    health: Vec<(u32, Health)>,
+    calibrations: Vec<(u32, Calibration)>,
+    device_tenants: Vec<(u32, String)>,
+}
+
+/// Хранилище в памяти. Клоны разделяют одни и те же данные,
+/// так что тест может читать то, что записал сервер.
+#[derive(Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<MemoryData>>);
+
+impl MemoryStore {
+    pub fn readings(&self) -> Vec<Reading> {
+        self.0.lock().unwrap().readings.clone()
+    }
+
+    pub fn gaps(&self) -> Vec<(u32, SequenceEvent)> {
+        self.0.lock().unwrap().gaps.clone()
+    }
+
+    pub fn anomalies(&self) -> Vec<(u32, Anomaly)> {
+        self.0.lock().unwrap().anomalies.clone()
+    }
+
+    pub fn health(&self) -> Vec<(u32, Health)> {
+        self.0.lock().unwrap().health.clone()
+    }
+
+    pub fn add_calibration(&self, device_id: u32, calibration: Calibration) {
+        self.0.lock().unwrap().calibrations.push((device_id, calibration));
+    }
+
+    pub fn assign_device(&self, device_id: u32, tenant_id: &str) {
+        self.0.lock().unwrap().device_tenants.push((device_id, tenant_id.to_string()));
+    }
+}
+
+impl MemoryStore {
+    fn push_reading(&self, reading: &Reading) {
+        self.0.lock().unwrap().readings.push(reading.clone());
+    }
+
+    fn push_gap(&self, device_id: u32, event: &SequenceEvent) {
+        self.0.lock().unwrap().gaps.push((device_id, event.clone()));
+    }
+
+    fn push_anomaly(&self, device_id: u32, anomaly: &Anomaly) {
+        self.0.lock().unwrap().anomalies.push((device_id, anomaly.clone()));
+    }
+
+    fn push_health(&self, device_id: u32, health: &Health) {
+        self.0.lock().unwrap().health.push((device_id, health.clone()));
+    }
+
+    fn calibrations(&self) -> Vec<(u32, Calibration)> {
+        self.0.lock().unwrap().calibrations.clone()
+    }
+
+    fn device_tenants(&self) -> Vec<(u32, String)> {
+        self.0.lock().unwrap().device_tenants.clone()
+    }
+}
+
+impl Store for MemoryStore {
+    fn save(&mut self, reading: &Reading) {
+        self.push_reading(reading);
+    }
+
+    fn save_gap(&mut self, reading: &Reading, event: &SequenceEvent) {
+        self.push_gap(reading.device_id, event);
+    }
+
+    fn save_anomaly(&mut self, reading: &Reading, anomaly: &Anomaly) {
+        self.push_anomaly(reading.device_id, anomaly);
+    }
+
+    fn save_health(&mut self, reading: &Reading, health: &Health) {
+        self.push_health(reading.device_id, health);
+    }
+
+    fn load_calibrations(&mut self) -> Result<Vec<(u32, Calibration)>, String> {
+        Ok(self.calibrations())
+    }
+
+    fn load_device_tenants(&mut self) -> Result<Vec<(u32, String)>, String> {
+        Ok(self.device_tenants())
+    }
+}
+
+impl AsyncStore for MemoryStore {
+    async fn save(&self, reading: &Reading) -> Result<(), String> {
+        self.push_reading(reading);
+        Ok(())
+    }
+
+    async fn save_gap(&self, reading: &Reading, event: &SequenceEvent) -> Result<(), String> {
+        self.push_gap(reading.device_id, event);
+        Ok(())
+    }
+
+    async fn save_anomaly(&self, reading: &Reading, anomaly: &Anomaly) -> Result<(), String> {
+        self.push_anomaly(reading.device_id, anomaly);
+        Ok(())
+    }
+
+    async fn save_health(&self, reading: &Reading, health: &Health) -> Result<(), String> {
+        self.push_health(reading.device_id, health);
+        Ok(())
+    }
+
+    async fn load_calibrations(&self) -> Result<Vec<(u32, Calibration)>, String> {
+        Ok(self.calibrations())
+    }
+
+    async fn load_device_tenants(&self) -> Result<Vec<(u32, String)>, String> {
+        Ok(self.device_tenants())
+    }
+}
@@ -0,0 +1,61 @@
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Команда, которой принадлежат устройства. Видит через API только свои
+/// устройства и показания, записанные, пока устройство было за ней.
+pub struct Tenant {
+    pub id: String,
+    pub name: String,
+    pub devices: Vec<u32>,
+}
+
+/// Чьё устройство: таблица `devices`, в памяти у приёма.
+#[derive(Default)]
+pub struct Registry(HashMap<u32, String>);
+
+impl Registry {
+    pub fn new(rows: Vec<(u32, String)>) -> Self {
+        Registry(rows.into_iter().collect())
+    }
+
+    /// `None` - устройство не закреплено ни за кем, его данные видит только админ.
+    pub fn tenant(&self, device_id: u32) -> Option<&str> {
+        self.0.get(&device_id).map(String::as_str)
+    }
+}
+
+/// Латиница в нижнем регистре, цифры, `-` и `_`: идентификатор
+/// попадает в URL и в запросы Grafana.
+pub fn validate_id(id: &str) -> Result<(), String> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid tenant id {:?}: use a-z, 0-9, - and _", id))
+    }
+}
+
+/// Новый токен API: 32 случайных байта в hex. Показывается один раз,
+/// в базе хранится только `hash_token`.
+pub fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    hex(&bytes)
+}
+
+/// Подходит ли строка под формат `generate_token`.
+pub fn is_token(token: &str) -> bool {
+    token.len() == 64 && token.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,95 @@
+// Детекторы аномалий: выброс по z-оценке, медленный дрейф по EWMA и
+// зависание датчика; о длящемся состоянии сообщается один раз.
+
+use server::anomaly::{AnomalyConfig, AnomalyDetector, AnomalyKind};
+use server::calibration::Metric;
+
+fn detector() -> AnomalyDetector {
+    AnomalyDetector::new(AnomalyConfig {
+        window: 10,
+        flat_count: 5,
+        ..AnomalyConfig::default()
+    })
+}
+
+// Виды аномалий по каждому значению
+fn feed(detector: &mut AnomalyDetector, metric: Metric, values: &[f64]) -> Vec<Vec<AnomalyKind>> {
+    values
+        .iter()
+        .map(|&value| detector.observe(1, metric, value).into_iter().map(|a| a.kind).collect())
+        .collect()
+}
+
+// Шум около 20 без повторов подряд
+const NOISE: [f64; 10] = [20.0, 20.4, 19.6, 20.2, 19.8, 20.3, 19.7, 20.1, 19.9, 20.0];
+
+#[test]
+fn steady_noise_is_not_an_anomaly() {
+    let mut detector = detector();
+    let kinds = feed(&mut detector, Metric::Temperature, &[NOISE, NOISE].concat());
+    assert!(kinds.iter().all(Vec::is_empty), "{:?}", kinds);
+}
+
+#[test]
+fn outlier_is_reported_by_zscore() {
+    let mut detector = detector();
+    feed(&mut detector, Metric::Temperature, &NOISE);
+    let anomalies = detector.observe(1, Metric::Temperature, 35.0);
+    let zscore = anomalies.iter().find(|a| a.kind == AnomalyKind::ZScore).expect("z-score anomaly");
+    assert!(zscore.score > 3.0);
+    assert_eq!(zscore.value, 35.0);
+    assert_eq!(zscore.metric, Metric::Temperature);
+}
+
+#[test]
+fn no_checks_before_warmup() {
+    let mut detector = detector();
+    // окно 10: проверки начинаются с пятой точки
+    let kinds = feed(&mut detector, Metric::Temperature, &[20.0, 20.5, 19.5, 100.0]);
+    assert!(kinds.iter().all(Vec::is_empty), "{:?}", kinds);
+}
+
+#[test]
+fn drift_is_reported_once_by_ewma() {
+    // длинное окно, чтобы среднее не успевало уйти вслед за дрейфом
+    let mut detector = AnomalyDetector::new(AnomalyConfig {
+        window: 40,
+        ..AnomalyConfig::default()
+    });
+    feed(&mut detector, Metric::Temperature, &[NOISE, NOISE, NOISE, NOISE].concat());
+    // сдвиг меньше трёх сигм: z-оценка молчит, EWMA замечает
+    let drift: Vec<f64> = (0..8).map(|i| 20.5 + 0.01 * i as f64).collect();
+    let kinds = feed(&mut detector, Metric::Temperature, &drift);
+    let ewma = kinds.iter().filter(|k| k.contains(&AnomalyKind::Ewma)).count();
+    assert_eq!(ewma, 1, "{:?}", kinds);
+    assert!(!kinds.iter().flatten().any(|k| *k == AnomalyKind::ZScore), "{:?}", kinds);
+}
+
+#[test]
+fn flat_line_is_reported_once() {
+    let mut detector = detector();
+    let kinds = feed(&mut detector, Metric::Humidity, &[45.0; 8]);
+    let flat: Vec<usize> = kinds
+        .iter()
+        .enumerate()
+        .filter(|(_, k)| k.contains(&AnomalyKind::FlatLine))
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(flat, vec![4]);
+}
+
+#[test]
+fn change_ends_the_flat_run() {
+    let mut detector = detector();
+    let kinds = feed(&mut detector, Metric::Humidity, &[45.0, 45.0, 45.0, 45.0, 46.0, 46.0, 46.0, 46.0]);
+    assert!(!kinds.iter().flatten().any(|k| *k == AnomalyKind::FlatLine), "{:?}", kinds);
+}
+
+#[test]
+fn metrics_and_devices_are_independent() {
+    let mut detector = detector();
+    feed(&mut detector, Metric::Temperature, &NOISE);
+    // у влажности и у другого устройства окно ещё пустое
+    assert!(detector.observe(1, Metric::Humidity, 99.0).is_empty());
+    assert!(detector.observe(2, Metric::Temperature, 99.0).is_empty());
+}
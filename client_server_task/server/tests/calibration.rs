@@ -0,0 +1,81 @@
+// История калибровок: новая калибровка занимает свой интервал, прежние
+// обрезаются или делятся, открытой остаётся не больше одной.
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use server::calibration::{self, Calibration, Calibrations, Metric};
+
+fn day(d: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 1, d, 0, 0, 0).unwrap()
+}
+
+fn offset(offset: f64, from: u32, to: Option<u32>) -> Calibration {
+    Calibration {
+        metric: Metric::Temperature,
+        gain: 1.0,
+        offset,
+        valid_from: day(from),
+        valid_to: to.map(day),
+    }
+}
+
+// (offset, начало, конец) по порядку
+fn intervals(history: &[Calibration]) -> Vec<(f64, u32, Option<u32>)> {
+    history
+        .iter()
+        .map(|c| (c.offset, c.valid_from.day(), c.valid_to.map(|t| t.day())))
+        .collect()
+}
+
+#[test]
+fn later_calibration_closes_the_open_one() {
+    let history = calibration::insert(vec![offset(1.0, 1, None)], offset(2.0, 10, None));
+    assert_eq!(intervals(&history), vec![(1.0, 1, Some(10)), (2.0, 10, None)]);
+}
+
+#[test]
+fn back_dated_calibration_ends_at_the_next_one() {
+    let history = calibration::insert(vec![offset(1.0, 10, None)], offset(2.0, 5, None));
+    assert_eq!(intervals(&history), vec![(2.0, 5, Some(10)), (1.0, 10, None)]);
+}
+
+#[test]
+fn back_dated_calibration_splits_the_running_interval() {
+    let history = vec![offset(1.0, 1, Some(10)), offset(3.0, 10, None)];
+    let history = calibration::insert(history, offset(2.0, 5, None));
+    assert_eq!(
+        intervals(&history),
+        vec![(1.0, 1, Some(5)), (2.0, 5, Some(10)), (3.0, 10, None)]
+    );
+}
+
+#[test]
+fn closed_interval_inside_an_open_one_splits_it() {
+    let history = calibration::insert(vec![offset(1.0, 1, None)], offset(2.0, 5, Some(8)));
+    assert_eq!(
+        intervals(&history),
+        vec![(1.0, 1, Some(5)), (2.0, 5, Some(8)), (1.0, 8, None)]
+    );
+}
+
+#[test]
+fn equal_start_replaces_the_calibration() {
+    let history = vec![offset(1.0, 1, Some(5)), offset(2.0, 5, None)];
+    let history = calibration::insert(history, offset(3.0, 5, None));
+    assert_eq!(intervals(&history), vec![(1.0, 1, Some(5)), (3.0, 5, None)]);
+}
+
+#[test]
+fn at_most_one_calibration_applies() {
+    let mut history = Vec::new();
+    for (value, from) in [(1.0, 10), (2.0, 5), (3.0, 20), (4.0, 5), (5.0, 1)] {
+        history = calibration::insert(history, offset(value, from, None));
+        assert_eq!(history.iter().filter(|c| c.valid_to.is_none()).count(), 1);
+        for pair in history.windows(2) {
+            assert_eq!(pair[0].valid_to, Some(pair[1].valid_from));
+        }
+    }
+
+    let calibrations = Calibrations::new(history.into_iter().map(|c| (1, c)).collect());
+    let apply = |d| calibrations.apply(1, Metric::Temperature, 0.0, day(d));
+    assert_eq!([apply(2), apply(7), apply(12), apply(25)], [5.0, 4.0, 1.0, 3.0]);
+}
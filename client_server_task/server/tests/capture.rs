@@ -0,0 +1,71 @@
+// Файлы захвата читаются так же, как пишутся (напрямую и через очередь
+// к потоку записи), а испорченная длина записи не превращается в огромную
+// аллокацию.
+
+use server::capture::{CaptureReader, CaptureWriter};
+use std::io::{self, Cursor};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use std::{fs, process, thread};
+
+fn capture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("capture-test-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn recorded_frames_are_read_back() {
+    let dir = capture_dir("roundtrip");
+    let received = UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+    {
+        let mut writer = CaptureWriter::open(&dir, 1024 * 1024, 4).unwrap();
+        writer.record(received, b"first").unwrap();
+        writer.record(received, b"second").unwrap();
+    }
+    let file = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+
+    let frames: Vec<_> = CaptureReader::open(&file).unwrap().collect::<io::Result<_>>().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].frame, b"first");
+    assert_eq!(frames[1].frame, b"second");
+    assert_eq!(frames[0].received, received);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn queued_frames_are_written_by_the_capture_thread() {
+    let dir = capture_dir("queue");
+    let queue = CaptureWriter::open(&dir, 1024 * 1024, 4).unwrap().spawn();
+    for i in 0..3u8 {
+        queue.record(UNIX_EPOCH, &[i]);
+    }
+    drop(queue);
+    let file = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+
+    // поток дописывает очередь после того, как её отпустили
+    let mut frames = Vec::new();
+    for _ in 0..100 {
+        frames = CaptureReader::open(&file).unwrap().collect::<io::Result<Vec<_>>>().unwrap();
+        if frames.len() == 3 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let frames: Vec<Vec<u8>> = frames.into_iter().map(|f| f.frame).collect();
+    assert_eq!(frames, vec![vec![0], vec![1], vec![2]]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn corrupt_record_length_is_an_error() {
+    let mut file = b"SCAPv1\0\0".to_vec();
+    file.extend_from_slice(&0u64.to_le_bytes());
+    file.extend_from_slice(&u32::MAX.to_le_bytes());
+    file.extend_from_slice(b"short");
+
+    let mut reader = CaptureReader::new(Cursor::new(file)).unwrap();
+    let err = reader.next().unwrap().err().expect("oversized record must fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("corrupt capture"), "{}", err);
+}
@@ -0,0 +1,61 @@
+// Оценка смещения часов: первый замер, сглаживание, сброс на скачке,
+// флаг и исправление времени вне допуска.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use server::clock::ClockTracker;
+
+fn at(ms: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(1_700_000_000_000 + ms).unwrap()
+}
+
+#[test]
+fn first_reading_sets_the_offset() {
+    let mut clock = ClockTracker::new(5_000, false);
+    let check = clock.check(1, at(0), at(120));
+    assert_eq!(check.offset_ms, 120);
+    assert!(!check.skewed);
+    assert_eq!(check.corrected_time, None);
+    assert_eq!(check.received_at, at(120));
+}
+
+#[test]
+fn offset_is_smoothed_within_tolerance() {
+    let mut clock = ClockTracker::new(5_000, false);
+    clock.check(1, at(0), at(100));
+    // новый замер 1100 мс: оценка сдвигается на десятую часть разницы
+    let check = clock.check(1, at(1_000), at(2_100));
+    assert_eq!(check.offset_ms, 200);
+}
+
+#[test]
+fn jump_beyond_tolerance_resets_the_offset() {
+    let mut clock = ClockTracker::new(5_000, false);
+    clock.check(1, at(0), at(100));
+    let check = clock.check(1, at(1_000), at(61_000));
+    assert_eq!(check.offset_ms, 60_000);
+    assert!(check.skewed);
+}
+
+#[test]
+fn skewed_time_is_corrected_only_when_enabled() {
+    let read = at(0);
+    let received = at(30_000);
+
+    let flagged = ClockTracker::new(5_000, false).check(1, read, received);
+    assert!(flagged.skewed);
+    assert_eq!(flagged.corrected_time, None);
+
+    let corrected = ClockTracker::new(5_000, true).check(1, read, received);
+    assert!(corrected.skewed);
+    assert_eq!(corrected.corrected_time, Some(read + Duration::milliseconds(30_000)));
+}
+
+#[test]
+fn devices_are_tracked_independently() {
+    let mut clock = ClockTracker::new(5_000, true);
+    clock.check(1, at(0), at(30_000));
+    let check = clock.check(2, at(0), at(50));
+    assert_eq!(check.offset_ms, 50);
+    assert!(!check.skewed);
+    assert_eq!(check.corrected_time, None);
+}
@@ -0,0 +1,559 @@
+// Асинхронный приём поднимается в том же процессе на свободном порту
+// с хранилищем в памяти, показания шлёт настоящий клиент. Postgres не нужен.
+
+use client::config::Config;
+use client::connection::Connector;
+use client::trace::Trace;
+use client::SERVER;
+use protocol::data::{Data, FirmwareRequest, Measurement, Reply};
+use protocol::Frame;
+use server::commands::{CommandQueue, CommandRequest, SharedCommands};
+use server::api::Api;
+use server::db::SharedDatabase;
+use server::firmware::FirmwareStore;
+use server::health::{self, Readiness};
+use server::http;
+use server::live::Broadcaster;
+use server::metrics::{Metrics, SharedMetrics};
+use server::pipeline::Pipeline;
+use server::store::MemoryStore;
+use server::Ingest;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, process, thread};
+
+struct TestServer {
+    port: u16,
+    store: MemoryStore,
+    metrics: SharedMetrics,
+    commands: SharedCommands,
+    firmware: PathBuf,
+}
+
+fn start_server() -> TestServer {
+    start_with(MemoryStore::default())
+}
+
+fn start_with(store: MemoryStore) -> TestServer {
+    start_on(TcpListener::bind("127.0.0.1:0").unwrap(), store)
+}
+
+fn start_on(listener: TcpListener, store: MemoryStore) -> TestServer {
+    let port = listener.local_addr().unwrap().port();
+    let metrics = Metrics::shared();
+    let commands = CommandQueue::shared();
+    let firmware = std::env::temp_dir().join(format!("sensor-firmware-{}-{}", process::id(), port));
+    let pipeline = Arc::new(Pipeline::new(
+        Ingest::new(metrics.clone()),
+        store.clone(),
+        None,
+        commands.clone(),
+        Broadcaster::shared(),
+        FirmwareStore::new(&firmware),
+    ));
+    listener.set_nonblocking(true).unwrap();
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            pipeline.serve(listener).await
+        })
+    });
+    TestServer {
+        port,
+        store,
+        metrics,
+        commands,
+        firmware,
+    }
+}
+
+// Порт, на котором заведомо никто не слушает (пока его не займёт тест)
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn sensor(port: u16, device_id: u32, extra: &[&str]) -> SERVER {
+    let mut args = vec![
+        "client".to_string(),
+        "--address".to_string(),
+        "127.0.0.1".to_string(),
+        "--port".to_string(),
+        port.to_string(),
+        "--device-id".to_string(),
+        device_id.to_string(),
+    ];
+    args.extend(extra.iter().map(|a| a.to_string()));
+    let config = Config::build(args.into_iter()).unwrap();
+    let connector = Connector::new(&config).unwrap();
+    let trace = Trace::load(&config).unwrap();
+    let mut sensor = SERVER::new(config, connector);
+    if let Some(trace) = trace {
+        sensor.play(trace);
+    }
+    sensor
+}
+
+fn queue_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sensor-queue-{}-{}", process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn readings_are_stored() {
+    let server = start_server();
+    let mut sensor = sensor(server.port, 7, &[]);
+    for _ in 0..3 {
+        sensor.step().unwrap();
+    }
+
+    let readings = server.store.readings();
+    assert_eq!(readings.len(), 3);
+    for (i, reading) in readings.iter().enumerate() {
+        assert_eq!(reading.device_id, 7);
+        assert_eq!(reading.event_id, i as u64);
+        // DHT11 по умолчанию, калибровок нет
+        let temperature = reading.measurement("temperature").unwrap();
+        let humidity = reading.measurement("humidity").unwrap();
+        assert!((0.0..=50.0).contains(&temperature.value));
+        assert!((20.0..=90.0).contains(&humidity.value));
+        assert_eq!(temperature.value, temperature.raw_value);
+        assert_eq!(reading.measurements.len(), 2);
+        assert!(!reading.clock.skewed);
+    }
+    assert!(server.store.gaps().is_empty());
+    assert_eq!(server.metrics.lock().unwrap().frames_rejected, 0);
+}
+
+#[test]
+fn health_is_stored_with_every_nth_reading() {
+    let server = start_server();
+    let mut sensor = sensor(server.port, 8, &["--set", "health.every=2"]);
+    for _ in 0..5 {
+        sensor.step().unwrap();
+    }
+
+    let health = server.store.health();
+    assert_eq!(health.len(), 3);
+    for (device_id, health) in &health {
+        assert_eq!(*device_id, 8);
+        assert!((3.0..=4.3).contains(&health.battery_voltage));
+        assert!((-95..=-35).contains(&health.rssi_dbm));
+        assert_eq!(health.firmware_version, client::board::FIRMWARE_VERSION);
+    }
+    let metrics = server.metrics.lock().unwrap().render();
+    assert!(metrics.contains("sensor_battery_volts{device_id=\"8\"}"));
+}
+
+#[test]
+fn firmware_update_is_downloaded_verified_and_installed() {
+    let server = start_server();
+    let image: Vec<u8> = (0..10_000u32).map(|i| (i * 7) as u8).collect();
+    let firmware = FirmwareStore::new(&server.firmware).add("2.0.0", &image).unwrap();
+    FirmwareStore::new(&server.firmware).add("2.0.1", &image).unwrap();
+    let update = |version: &str, sha256: &str| CommandRequest::UpdateFirmware {
+        version: version.to_string(),
+        size: firmware.size,
+        sha256: sha256.to_string(),
+    };
+    let mut sensor = sensor(server.port, 14, &["--set", "health.every=1", "--set", "ota.chunk_bytes=3000"]);
+
+    // образ не сходится с контрольной суммой из команды: не ставится
+    server.commands.lock().unwrap().push(14, update("2.0.1", &"0".repeat(64)));
+    sensor.step().unwrap();
+    server.commands.lock().unwrap().push(14, update("2.0.0", &firmware.sha256));
+    sensor.step().unwrap();
+    sensor.step().unwrap();
+
+    let versions: Vec<String> = server.store.health().into_iter().map(|(_, h)| h.firmware_version).collect();
+    assert_eq!(versions[..2], [client::board::FIRMWARE_VERSION, client::board::FIRMWARE_VERSION]);
+    assert_eq!(versions[2], "2.0.0");
+    // после перезагрузки счёт показаний начинается заново
+    let events: Vec<u64> = server.store.readings().iter().map(|r| r.event_id).collect();
+    assert_eq!(events, vec![0, 1, 0]);
+    assert_eq!(server.metrics.lock().unwrap().frames_rejected, 0);
+    let _ = fs::remove_dir_all(&server.firmware);
+}
+
+#[test]
+fn trace_is_played_back_in_order() {
+    let server = start_server();
+    let path = std::env::temp_dir().join(format!("sensor-trace-{}.csv", process::id()));
+    fs::write(
+        &path,
+        "time,temperature,humidity,pressure (Pa)\n\
+         2024-03-01T10:00:00Z,21.5,40,101325\n\
+         2024-03-01T10:00:10Z,,,\n\
+         2024-03-01T10:00:20Z,21.7,41,\n",
+    )
+    .unwrap();
+    let mut sensor = sensor(
+        server.port,
+        15,
+        &["--trace", path.to_str().unwrap(), "--set", "trace.speed=0", "--set", "trace.loop=true"],
+    );
+    fs::remove_file(&path).unwrap();
+    // строка без величин пропускается, после последней - снова первая
+    for _ in 0..3 {
+        sensor.step().unwrap();
+    }
+
+    let readings = server.store.readings();
+    let values: Vec<Vec<(&str, f64)>> = readings
+        .iter()
+        .map(|r| r.measurements.iter().map(|m| (m.metric.as_str(), m.value)).collect())
+        .collect();
+    let first = vec![("temperature", 21.5), ("humidity", 40.0), ("pressure", 1013.25)];
+    assert_eq!(values, [first.clone(), vec![("temperature", 21.7), ("humidity", 41.0)], first]);
+}
+
+#[test]
+fn bme280_pressure_is_stored_in_hpa() {
+    let server = start_server();
+    sensor(server.port, 10, &["--model", "bme280"]).step().unwrap();
+
+    let readings = server.store.readings();
+    let pressure = readings[0].measurement("pressure").unwrap();
+    assert_eq!(pressure.unit, "hPa");
+    assert!((300.0..=1100.0).contains(&pressure.value), "{}", pressure.value);
+}
+
+#[test]
+fn measurements_are_normalised_and_invalid_ones_dropped() {
+    let server = start_server();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let measurement = |metric: &str, value: f64, unit: &str| Measurement {
+        metric: metric.to_string(),
+        value,
+        unit: unit.to_string(),
+    };
+    let data = Data {
+        device_id: 13,
+        read_time: Some(prost_types::Timestamp {
+            seconds: now.as_secs() as i64,
+            nanos: 0,
+        }),
+        measurements: vec![
+            measurement("temperature", 212.0, "°F"),
+            measurement("co2", 0.05, "%"),
+            measurement("lux", 300.0, "lx"),
+            measurement("pressure", 1.0, "furlong"),
+            measurement("Bad Name", 1.0, ""),
+            measurement("co2", 420.0, "ppm"),
+        ],
+        ..Data::default()
+    };
+
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    stream.write_all(&data.encode_frame()).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert!(Reply::read_frame(&mut stream).unwrap().is_some());
+
+    let readings = server.store.readings();
+    let stored: Vec<_> = readings[0]
+        .measurements
+        .iter()
+        .map(|m| (m.metric.as_str(), (m.value * 1000.0).round() / 1000.0, m.unit.as_str()))
+        .collect();
+    assert_eq!(stored, [("temperature", 100.0, "°C"), ("co2", 500.0, "ppm"), ("lux", 300.0, "lx")]);
+}
+
+#[test]
+fn readings_are_stored_under_device_tenant() {
+    let store = MemoryStore::default();
+    store.assign_device(11, "team-a");
+    let server = start_with(store);
+    sensor(server.port, 11, &[]).step().unwrap();
+    sensor(server.port, 12, &[]).step().unwrap();
+
+    let readings = server.store.readings();
+    assert_eq!(readings.len(), 2);
+    let tenant = |device_id| {
+        let reading = readings.iter().find(|r| r.device_id == device_id).unwrap();
+        reading.tenant_id.clone()
+    };
+    assert_eq!(tenant(11).as_deref(), Some("team-a"));
+    assert_eq!(tenant(12), None);
+}
+
+#[test]
+fn firmware_is_served_only_after_the_update_command() {
+    let server = start_server();
+    let image = vec![7u8; 100];
+    let firmware = FirmwareStore::new(&server.firmware).add("3.0.0", &image).unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let request = Data {
+        device_id: 15,
+        event_id: 0,
+        humidity: 0.0,
+        temperature: 0.0,
+        read_time: Some(prost_types::Timestamp {
+            seconds: now.as_secs() as i64,
+            nanos: 0,
+        }),
+        health: None,
+        measurements: Vec::new(),
+        firmware_request: Some(FirmwareRequest {
+            version: "3.0.0".to_string(),
+            offset: 0,
+            max_len: 0,
+        }),
+    };
+    let download = || {
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        stream.write_all(&request.encode_frame()).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        Reply::read_frame(&mut stream).unwrap().unwrap().firmware_chunk.unwrap()
+    };
+
+    let refused = download();
+    assert!(refused.error.contains("not offered"), "{}", refused.error);
+    assert!(refused.data.is_empty());
+
+    server.commands.lock().unwrap().push(
+        15,
+        CommandRequest::UpdateFirmware {
+            version: "3.0.0".to_string(),
+            size: firmware.size,
+            sha256: firmware.sha256,
+        },
+    );
+    server.commands.lock().unwrap().take(15);
+    let chunk = download();
+    assert_eq!(chunk.error, "");
+    assert_eq!(chunk.data, image);
+    let _ = fs::remove_dir_all(&server.firmware);
+}
+
+#[test]
+fn malformed_frames_are_rejected() {
+    let server = start_server();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let valid = Data {
+        device_id: 9,
+        event_id: 0,
+        humidity: 40.0,
+        temperature: 21.5,
+        read_time: Some(prost_types::Timestamp {
+            seconds: now.as_secs() as i64,
+            nanos: 0,
+        }),
+        health: None,
+        measurements: Vec::new(),
+        firmware_request: None,
+    };
+    let no_time = Data {
+        read_time: None,
+        ..valid.clone()
+    };
+
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    stream.write_all(&[3, 0, 0, 0, 0xff, 0xff, 0xff]).unwrap();
+    stream.write_all(&no_time.encode_frame()).unwrap();
+    stream.write_all(&valid.encode_frame()).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    // ответ приходит только на принятое показание
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let reply = Reply::read_frame(&mut stream).unwrap().unwrap();
+    assert!(reply.commands.is_empty());
+    assert!(Reply::read_frame(&mut stream).unwrap().is_none());
+
+    let readings = server.store.readings();
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0].device_id, 9);
+    assert_eq!(readings[0].measurement("temperature").unwrap().value, 21.5);
+    assert_eq!(server.metrics.lock().unwrap().frames_rejected, 2);
+}
+
+#[test]
+fn injected_faults_do_not_break_the_server() {
+    let server = start_server();
+    for (device_id, fault) in [(41, "truncated"), (42, "oversized"), (43, "garbage"), (44, "half_open")] {
+        let mut sensor = sensor(server.port, device_id, &["--set", &format!("faults.{}=1", fault)]);
+        sensor.step().unwrap();
+    }
+    let mut sensor = sensor(server.port, 45, &["--set", "faults.out_of_order=1"]);
+    sensor.step().unwrap();
+    sensor.step().unwrap();
+
+    let readings = server.store.readings();
+    assert!(readings.iter().all(|r| r.device_id == 45));
+    assert_eq!(readings.len(), 2);
+    // испорченный protobuf - единственный кадр, который сервер смог прочитать и отклонить
+    assert_eq!(server.metrics.lock().unwrap().frames_rejected, 1);
+}
+
+// HTTP API без приёма показаний; адрес для запросов
+fn start_api(readiness: Arc<Readiness>, admin_token: Option<&str>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let api = Api {
+        metrics: Metrics::shared(),
+        commands: CommandQueue::shared(),
+        live: Broadcaster::shared(),
+        readiness,
+        admin_token: admin_token.map(String::from),
+        db: SharedDatabase::default(),
+    };
+    http::spawn(listener, move |request| api.handle(request));
+    addr
+}
+
+// Код ответа на GET, с токеном в Authorization или без
+fn status(addr: &str, path: &str, token: Option<&str>) -> u16 {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let auth = token.map(|t| format!("Authorization: Bearer {}\r\n", t)).unwrap_or_default();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n{}Connection: close\r\n\r\n", path, addr, auth).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split_whitespace().nth(1).unwrap().parse().unwrap()
+}
+
+#[test]
+fn readyz_fails_until_migrations_are_applied() {
+    let readiness = Readiness::shared();
+    let addr = start_api(readiness.clone(), None);
+
+    let timeout = Duration::from_secs(5);
+    assert_eq!(health::probe(&addr, "/healthz", timeout), Ok(()));
+    let err = health::probe(&addr, "/readyz", timeout).unwrap_err();
+    assert!(err.contains("503") && err.contains("migrations"), "{}", err);
+    readiness.set_migrated();
+    let err = health::probe(&addr, "/readyz", timeout).unwrap_err();
+    assert!(err.contains("listener"), "{}", err);
+    // базы в тестах нет: ответ 503 с причиной, а не оборванное соединение
+    readiness.set_listening();
+    let err = health::probe(&addr, "/readyz", timeout).unwrap_err();
+    assert!(err.contains("503") && err.to_lowercase().contains("database"), "{}", err);
+}
+
+#[test]
+fn api_without_token_is_unauthorized() {
+    // без ADMIN_TOKEN отсутствие токена не делает админом
+    for admin_token in [None, Some("secret")] {
+        let addr = start_api(Readiness::shared(), admin_token);
+        for path in ["/metrics", "/live", "/export?device=1", "/devices/1/commands", "/devices/1/calibration"] {
+            assert_eq!(status(&addr, path, None), 401, "{} with ADMIN_TOKEN {:?}", path, admin_token);
+        }
+    }
+}
+
+#[test]
+fn api_with_bad_token_is_unauthorized() {
+    for admin_token in [None, Some("secret")] {
+        let addr = start_api(Readiness::shared(), admin_token);
+        for token in ["wrong", "secret2", ""] {
+            assert_eq!(status(&addr, "/devices/1/commands", Some(token)), 401, "token {:?}", token);
+            assert_eq!(status(&addr, "/metrics", Some(token)), 401, "token {:?}", token);
+        }
+    }
+}
+
+#[test]
+fn tenant_token_without_database_is_an_error_not_a_panic() {
+    let addr = start_api(Readiness::shared(), Some("secret"));
+    let token = "ab".repeat(32);
+    // в тестах DATABASE_URL нет: токен команды проверить не по чему
+    for _ in 0..2 {
+        assert_eq!(status(&addr, "/devices/1/commands", Some(&token)), 500);
+    }
+    assert_eq!(status(&addr, "/metrics", Some("secret")), 200);
+}
+
+#[test]
+fn admin_token_opens_metrics_and_commands() {
+    let addr = start_api(Readiness::shared(), Some("secret"));
+    assert_eq!(status(&addr, "/metrics", Some("secret")), 200);
+    // админ видит любое устройство, база для этого не нужна
+    assert_eq!(status(&addr, "/devices/1/commands", Some("secret")), 200);
+}
+
+#[test]
+fn truncated_frame_does_not_break_next_connection() {
+    let server = start_server();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    stream.write_all(&[100, 0, 0, 0, 1, 2, 3]).unwrap();
+    drop(stream);
+
+    let mut sensor = sensor(server.port, 11, &[]);
+    sensor.step().unwrap();
+    assert_eq!(server.store.readings().len(), 1);
+}
+
+#[test]
+fn queued_readings_are_delivered_after_reconnect() {
+    let port = free_port();
+    let queue = queue_path("reconnect");
+    let mut sensor = sensor(port, 21, &["--queue", queue.to_str().unwrap()]);
+
+    // сервера ещё нет: показания копятся в очереди
+    sensor.step().unwrap();
+    sensor.step().unwrap();
+    assert!(queue.exists());
+
+    let server = start_on(TcpListener::bind(("127.0.0.1", port)).unwrap(), MemoryStore::default());
+    sensor.step().unwrap();
+
+    let events: Vec<u64> = server.store.readings().iter().map(|r| r.event_id).collect();
+    assert_eq!(events, vec![0, 1, 2]);
+    assert!(server.store.gaps().is_empty());
+    assert!(!queue.exists());
+}
+
+#[test]
+fn client_retries_until_server_is_up() {
+    let port = free_port();
+    let mut sensor = sensor(
+        port,
+        31,
+        &["--set", "retry.max_attempts=20", "--set", "retry.backoff_ms=50", "--set", "retry.max_backoff_ms=50"],
+    );
+
+    let starter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        start_on(TcpListener::bind(("127.0.0.1", port)).unwrap(), MemoryStore::default())
+    });
+    sensor.step().unwrap();
+    let server = starter.join().unwrap();
+
+    let readings = server.store.readings();
+    assert_eq!(readings.len(), 1);
+    assert_eq!(readings[0].device_id, 31);
+}
+
+#[test]
+fn concurrent_devices_are_all_stored() {
+    let server = start_server();
+    let devices: Vec<_> = (0..50)
+        .map(|device_id| {
+            let port = server.port;
+            thread::spawn(move || {
+                let mut sensor = sensor(port, 1000 + device_id, &[]);
+                for _ in 0..3 {
+                    sensor.step().unwrap();
+                }
+            })
+        })
+        .collect();
+    for device in devices {
+        device.join().unwrap();
+    }
+
+    let readings = server.store.readings();
+    assert_eq!(readings.len(), 150);
+    for device_id in 1000..1050 {
+        let events: Vec<u64> = readings
+            .iter()
+            .filter(|r| r.device_id == device_id)
+            .map(|r| r.event_id)
+            .collect();
+        assert_eq!(events, vec![0, 1, 2], "device {}", device_id);
+    }
+    assert!(server.store.gaps().is_empty());
+}
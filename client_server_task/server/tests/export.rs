@@ -0,0 +1,77 @@
+// Выгрузка `server export` проигрывается клиентом как запись платы:
+// строки одного показания собираются обратно в одно показание.
+
+use chrono::{DateTime, TimeZone, Utc};
+use client::trace::{self, Point};
+use postgres::fallible_iterator;
+use server::export::{self, ExportRow, Format};
+
+fn at(second: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, second).unwrap()
+}
+
+fn row(device_id: i64, event_id: Option<i64>, second: u32, metric: &str, value: f64, unit: &str) -> ExportRow {
+    ExportRow {
+        device_id,
+        event_id,
+        read_time: at(second),
+        metric: metric.to_string(),
+        value,
+        unit: unit.to_string(),
+        raw_value: Some(value - 0.5),
+        received_at: Some(at(second + 1)),
+    }
+}
+
+// В порядке `Database::readings`: устройство, время, величина
+fn rows() -> Vec<ExportRow> {
+    vec![
+        row(1, Some(0), 0, "humidity", 40.0, "%"),
+        row(1, Some(0), 0, "temperature", 21.5, "°C"),
+        row(1, Some(1), 10, "temperature", 21.7, "°C"),
+        row(1, None, 20, "co2", 600.0, "ppm, NDIR"),
+        row(2, Some(0), 20, "temperature", 19.0, "°C"),
+    ]
+}
+
+fn exported(format: Format) -> String {
+    let mut out = Vec::new();
+    let count = export::write(fallible_iterator::convert(rows().into_iter().map(Ok)), format, &mut out).unwrap();
+    assert_eq!(count, 5);
+    String::from_utf8(out).unwrap()
+}
+
+// Секунды от начала записи и величины показания: (metric, value, unit)
+type Played = Vec<(i64, Vec<(String, f64, String)>)>;
+
+fn points(points: Vec<Point>) -> Played {
+    points
+        .into_iter()
+        .map(|p| {
+            let values = p.measurements.into_iter().map(|m| (m.metric, m.value, m.unit)).collect();
+            (p.time as i64 - at(0).timestamp(), values)
+        })
+        .collect()
+}
+
+fn expected() -> Played {
+    let value = |metric: &str, value: f64, unit: &str| (metric.to_string(), value, unit.to_string());
+    vec![
+        (0, vec![value("humidity", 40.0, "%"), value("temperature", 21.5, "°C")]),
+        (10, vec![value("temperature", 21.7, "°C")]),
+        (20, vec![value("co2", 600.0, "ppm, NDIR")]),
+        (20, vec![value("temperature", 19.0, "°C")]),
+    ]
+}
+
+#[test]
+fn csv_export_plays_back_as_a_trace() {
+    let points = trace::parse_csv(&exported(Format::Csv)).unwrap();
+    assert_eq!(self::points(points), expected());
+}
+
+#[test]
+fn jsonl_export_plays_back_as_a_trace() {
+    let points = trace::parse_jsonl(&exported(Format::Jsonl)).unwrap();
+    assert_eq!(self::points(points), expected());
+}
@@ -0,0 +1,103 @@
+// Разбор нумерации event_id: пропуски, повторы, перезапуски счётчика
+// и доля потерь в метриках.
+
+use server::metrics::Metrics;
+use server::sequence::{SequenceEvent, SequenceKind, SequenceTracker};
+
+fn event(kind: SequenceKind, expected: u64, received: u64, missing: u64) -> Option<SequenceEvent> {
+    Some(SequenceEvent {
+        kind,
+        expected,
+        received,
+        missing,
+    })
+}
+
+#[test]
+fn consecutive_ids_are_not_reported() {
+    let mut sequence = SequenceTracker::default();
+    assert_eq!(sequence.observe(1, 5), None);
+    assert_eq!(sequence.observe(1, 6), None);
+    assert_eq!(sequence.observe(1, 7), None);
+}
+
+#[test]
+fn gap_counts_missing_readings() {
+    let mut sequence = SequenceTracker::default();
+    sequence.observe(1, 1);
+    assert_eq!(sequence.observe(1, 5), event(SequenceKind::Gap, 2, 5, 3));
+    // после пропуска ждём следующий за полученным
+    assert_eq!(sequence.observe(1, 6), None);
+}
+
+#[test]
+fn duplicate_does_not_move_the_sequence() {
+    let mut sequence = SequenceTracker::default();
+    sequence.observe(1, 10);
+    assert_eq!(sequence.observe(1, 10), event(SequenceKind::Duplicate, 11, 10, 0));
+    assert_eq!(sequence.observe(1, 4), event(SequenceKind::Duplicate, 11, 4, 0));
+    assert_eq!(sequence.observe(1, 11), None);
+}
+
+#[test]
+fn restart_from_zero_is_a_reset() {
+    let mut sequence = SequenceTracker::default();
+    sequence.observe(1, 42);
+    assert_eq!(sequence.observe(1, 0), event(SequenceKind::Reset, 43, 0, 0));
+    assert_eq!(sequence.observe(1, 1), None);
+}
+
+#[test]
+fn restart_with_a_lost_first_frame_is_a_reset() {
+    let mut metrics = Metrics::default();
+    let mut sequence = SequenceTracker::default();
+    for id in 0..=100 {
+        assert_eq!(sequence.observe(1, id), None);
+        metrics.record_reading(1, None);
+    }
+    // кадр 0 после перезагрузки потерялся
+    let reset = sequence.observe(1, 1);
+    assert_eq!(reset, event(SequenceKind::Reset, 101, 1, 0));
+    metrics.record_reading(1, reset.as_ref());
+    for id in 2..=10 {
+        let event = sequence.observe(1, id);
+        assert_eq!(event, None);
+        metrics.record_reading(1, event.as_ref());
+    }
+    let device = &metrics.devices[&1];
+    assert_eq!((device.duplicates, device.resets), (0, 1));
+    assert_eq!(device.loss_rate(), 0.0);
+}
+
+#[test]
+fn devices_are_tracked_independently() {
+    let mut sequence = SequenceTracker::default();
+    sequence.observe(1, 100);
+    assert_eq!(sequence.observe(2, 0), None);
+    assert_eq!(sequence.observe(2, 1), None);
+    assert_eq!(sequence.observe(1, 101), None);
+}
+
+#[test]
+fn loss_rate_ignores_duplicates() {
+    let mut metrics = Metrics::default();
+    let mut sequence = SequenceTracker::default();
+    // 0, 1, (2 и 3 потеряны), 4, повтор 4, повтор 4
+    for id in [0, 1, 4, 4, 4] {
+        let event = sequence.observe(7, id);
+        metrics.record_reading(7, event.as_ref());
+    }
+    let device = &metrics.devices[&7];
+    assert_eq!(device.received, 5);
+    assert_eq!(device.duplicates, 2);
+    assert_eq!(device.missing, 2);
+    // ожидалось 5 показаний (0..=4), потеряно 2
+    assert!((device.loss_rate() - 0.4).abs() < 1e-9, "{}", device.loss_rate());
+}
+
+#[test]
+fn loss_rate_without_gaps_is_zero() {
+    let mut metrics = Metrics::default();
+    metrics.record_reading(1, None);
+    assert_eq!(metrics.devices[&1].loss_rate(), 0.0);
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+
+use crate::pb;
+use crate::pb::contact::Kind;
+use crate::{str_to_department, str_to_phone_type};
+
+// Правки одного контакта. Ошибка - без имени контакта, его добавляет
+// вызывающий; время обновления тоже ставит он, только если правка удалась.
+
+// Для человека `kind` - тип телефона (home, mobile, work), для компании - отдел (hr, cs)
+pub fn add_phone(contact: &mut pb::Contact, number: &str, kind: &str) -> Result<(), String> {
+    match contact.kind.as_mut() {
+        Some(Kind::Person(person)) => {
+            if person.phones.iter().any(|p| p.number == number) {
+                return Err(format!("phone {} already exists", number));
+            }
+            person.phones.push(pb::person::PhoneNumber {
+                number: number.to_string(),
+                r#type: str_to_phone_type(kind),
+            });
+        }
+        Some(Kind::Company(company)) => {
+            if company.phones.iter().any(|p| p.number == number) {
+                return Err(format!("phone {} already exists", number));
+            }
+            company.phones.push(pb::company::PhoneNumber {
+                number: number.to_string(),
+                department: str_to_department(kind),
+            });
+        }
+        None => return Err(NO_KIND.to_string()),
+    }
+    Ok(())
+}
+
+pub fn remove_phone(contact: &mut pb::Contact, number: &str) -> Result<(), String> {
+    let removed = match contact.kind.as_mut() {
+        Some(Kind::Person(person)) => remove_where(&mut person.phones, |p| p.number == number),
+        Some(Kind::Company(company)) => remove_where(&mut company.phones, |p| p.number == number),
+        None => return Err(NO_KIND.to_string()),
+    };
+    if !removed {
+        return Err(format!("no phone {}", number));
+    }
+    Ok(())
+}
+
+// У человека один адрес: добавить можно, только если его ещё нет
+pub fn add_email(contact: &mut pb::Contact, email: &str, department: Option<&str>) -> Result<(), String> {
+    match contact.kind.as_mut() {
+        Some(Kind::Person(person)) => {
+            if department.is_some() {
+                return Err(PERSON_DEPARTMENT.to_string());
+            }
+            if person.email == email {
+                return Err(format!("email {} already exists", email));
+            }
+            if !person.email.is_empty() {
+                return Err(format!(
+                    "a person has only one email and it is already {}; use update to change it",
+                    person.email
+                ));
+            }
+            person.email = email.to_string();
+        }
+        Some(Kind::Company(company)) => {
+            if company.emails.iter().any(|e| e.email == email) {
+                return Err(format!("email {} already exists", email));
+            }
+            company.emails.push(pb::company::EmailAddress {
+                email: email.to_string(),
+                department: str_to_department(department.unwrap_or_default()),
+            });
+        }
+        None => return Err(NO_KIND.to_string()),
+    }
+    Ok(())
+}
+
+pub fn remove_email(contact: &mut pb::Contact, email: &str) -> Result<(), String> {
+    let removed = match contact.kind.as_mut() {
+        Some(Kind::Person(person)) if !email.is_empty() && person.email == email => {
+            person.email.clear();
+            true
+        }
+        Some(Kind::Person(_)) => false,
+        Some(Kind::Company(company)) => remove_where(&mut company.emails, |e| e.email == email),
+        None => return Err(NO_KIND.to_string()),
+    };
+    if !removed {
+        return Err(format!("no email {}", email));
+    }
+    Ok(())
+}
+
+/// `update`: меняет существующий телефон (`--phone` с `--new-phone`
+/// и/или `--type`) и/или адрес (`--email` с `--new-email` и/или `--dep`).
+pub fn update(contact: &mut pb::Contact, params: &HashMap<String, String>) -> Result<(), String> {
+    let param = |key: &str| params.get(key).map(String::as_str);
+    if param("--phone").is_none() && param("--email").is_none() {
+        return Err("nothing to update, expected --phone or --email".to_string());
+    }
+    if let Some(number) = param("--phone") {
+        update_phone(contact, number, param("--new-phone"), param("--type"))?;
+    }
+    if let Some(email) = param("--email") {
+        update_email(contact, email, param("--new-email"), param("--dep"))?;
+    }
+    Ok(())
+}
+
+fn update_phone(
+    contact: &mut pb::Contact,
+    number: &str,
+    new_number: Option<&str>,
+    kind: Option<&str>,
+) -> Result<(), String> {
+    if new_number.is_none() && kind.is_none() {
+        return Err(format!("nothing to update for phone {}, expected --new-phone or --type", number));
+    }
+    let missing = || format!("no phone {}", number);
+    let duplicate = |new: &str| format!("phone {} already exists", new);
+    match contact.kind.as_mut() {
+        Some(Kind::Person(person)) => {
+            if let Some(new) = new_number.filter(|&new| new != number) {
+                if person.phones.iter().any(|p| p.number == new) {
+                    return Err(duplicate(new));
+                }
+            }
+            let phone = person.phones.iter_mut().find(|p| p.number == number).ok_or_else(missing)?;
+            if let Some(new) = new_number {
+                phone.number = new.to_string();
+            }
+            if let Some(kind) = kind {
+                phone.r#type = str_to_phone_type(kind);
+            }
+        }
+        Some(Kind::Company(company)) => {
+            if let Some(new) = new_number.filter(|&new| new != number) {
+                if company.phones.iter().any(|p| p.number == new) {
+                    return Err(duplicate(new));
+                }
+            }
+            let phone = company.phones.iter_mut().find(|p| p.number == number).ok_or_else(missing)?;
+            if let Some(new) = new_number {
+                phone.number = new.to_string();
+            }
+            if let Some(department) = kind {
+                phone.department = str_to_department(department);
+            }
+        }
+        None => return Err(NO_KIND.to_string()),
+    }
+    Ok(())
+}
+
+fn update_email(
+    contact: &mut pb::Contact,
+    email: &str,
+    new_email: Option<&str>,
+    department: Option<&str>,
+) -> Result<(), String> {
+    if new_email.is_none() && department.is_none() {
+        return Err(format!("nothing to update for email {}, expected --new-email or --dep", email));
+    }
+    match contact.kind.as_mut() {
+        Some(Kind::Person(person)) => {
+            if department.is_some() {
+                return Err(PERSON_DEPARTMENT.to_string());
+            }
+            if person.email != email {
+                return Err(format!("no email {}", email));
+            }
+            person.email = new_email.unwrap_or_default().to_string();
+        }
+        Some(Kind::Company(company)) => {
+            if let Some(new) = new_email.filter(|&new| new != email) {
+                if company.emails.iter().any(|e| e.email == new) {
+                    return Err(format!("email {} already exists", new));
+                }
+            }
+            let address = company
+                .emails
+                .iter_mut()
+                .find(|e| e.email == email)
+                .ok_or_else(|| format!("no email {}", email))?;
+            if let Some(new) = new_email {
+                address.email = new.to_string();
+            }
+            if let Some(department) = department {
+                address.department = str_to_department(department);
+            }
+        }
+        None => return Err(NO_KIND.to_string()),
+    }
+    Ok(())
+}
+
+const NO_KIND: &str = "contact is neither a person nor a company";
+const PERSON_DEPARTMENT: &str = "a person's email has no department";
+
+fn remove_where<T>(items: &mut Vec<T>, matches: impl Fn(&T) -> bool) -> bool {
+    let before = items.len();
+    items.retain(|item| !matches(item));
+    items.len() != before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(email: &str, phones: &[&str]) -> pb::Contact {
+        let phones = phones
+            .iter()
+            .map(|number| pb::person::PhoneNumber {
+                number: number.to_string(),
+                r#type: 1,
+            })
+            .collect();
+        pb::Contact {
+            last_updated: None,
+            kind: Some(Kind::Person(pb::Person {
+                email: email.to_string(),
+                phones,
+            })),
+        }
+    }
+
+    fn company(emails: &[&str], phones: &[&str]) -> pb::Contact {
+        pb::Contact {
+            last_updated: None,
+            kind: Some(Kind::Company(pb::Company {
+                emails: emails
+                    .iter()
+                    .map(|email| pb::company::EmailAddress {
+                        email: email.to_string(),
+                        department: 1,
+                    })
+                    .collect(),
+                phones: phones
+                    .iter()
+                    .map(|number| pb::company::PhoneNumber {
+                        number: number.to_string(),
+                        department: 2,
+                    })
+                    .collect(),
+            })),
+        }
+    }
+
+    fn kindless() -> pb::Contact {
+        pb::Contact::default()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn add_phone_rejects_duplicates() {
+        let mut contact = person("", &["+1"]);
+        assert_eq!(add_phone(&mut contact, "+1", "home"), Err("phone +1 already exists".to_string()));
+        let mut contact = company(&[], &["+2"]);
+        assert_eq!(add_phone(&mut contact, "+2", "hr"), Err("phone +2 already exists".to_string()));
+    }
+
+    #[test]
+    fn add_phone_needs_a_kind() {
+        assert_eq!(add_phone(&mut kindless(), "+1", "home"), Err(NO_KIND.to_string()));
+    }
+
+    #[test]
+    fn add_phone_keeps_the_type() {
+        let mut contact = person("", &[]);
+        add_phone(&mut contact, "+1", "work").unwrap();
+        let Some(Kind::Person(person)) = contact.kind else { unreachable!() };
+        assert_eq!(person.phones[0].r#type, 3);
+    }
+
+    #[test]
+    fn update_without_targets_fails() {
+        let mut contact = person("a@x.org", &["+1"]);
+        let err = update(&mut contact, &params(&[("--new-phone", "+2")])).unwrap_err();
+        assert_eq!(err, "nothing to update, expected --phone or --email");
+        let err = update(&mut contact, &params(&[("--phone", "+1")])).unwrap_err();
+        assert!(err.starts_with("nothing to update for phone +1"), "{}", err);
+        let err = update(&mut contact, &params(&[("--email", "a@x.org")])).unwrap_err();
+        assert!(err.starts_with("nothing to update for email a@x.org"), "{}", err);
+    }
+
+    #[test]
+    fn update_of_a_missing_phone_or_email_fails() {
+        let mut contact = person("a@x.org", &["+1"]);
+        let err = update(&mut contact, &params(&[("--phone", "+9"), ("--type", "home")])).unwrap_err();
+        assert_eq!(err, "no phone +9");
+        let err = update(&mut contact, &params(&[("--email", "b@x.org"), ("--new-email", "c@x.org")])).unwrap_err();
+        assert_eq!(err, "no email b@x.org");
+
+        let mut contact = company(&["a@x.org"], &["+1"]);
+        let err = update(&mut contact, &params(&[("--email", "b@x.org"), ("--dep", "hr")])).unwrap_err();
+        assert_eq!(err, "no email b@x.org");
+    }
+
+    #[test]
+    fn update_to_an_existing_value_fails() {
+        let mut contact = person("", &["+1", "+2"]);
+        let err = update(&mut contact, &params(&[("--phone", "+1"), ("--new-phone", "+2")])).unwrap_err();
+        assert_eq!(err, "phone +2 already exists");
+        let mut contact = company(&["a@x.org", "b@x.org"], &[]);
+        let err = update(&mut contact, &params(&[("--email", "a@x.org"), ("--new-email", "b@x.org")])).unwrap_err();
+        assert_eq!(err, "email b@x.org already exists");
+    }
+
+    #[test]
+    fn update_of_a_person_email_department_fails() {
+        let mut contact = person("a@x.org", &[]);
+        let err = update(&mut contact, &params(&[("--email", "a@x.org"), ("--dep", "hr")])).unwrap_err();
+        assert_eq!(err, PERSON_DEPARTMENT);
+    }
+
+    #[test]
+    fn update_of_a_kindless_contact_fails() {
+        let err = update(&mut kindless(), &params(&[("--phone", "+1"), ("--type", "home")])).unwrap_err();
+        assert_eq!(err, NO_KIND);
+    }
+
+    #[test]
+    fn update_changes_phone_and_email() {
+        let mut contact = company(&["a@x.org"], &["+1"]);
+        let changes = params(&[("--phone", "+1"), ("--new-phone", "+2"), ("--email", "a@x.org"), ("--dep", "cs")]);
+        update(&mut contact, &changes).unwrap();
+        let Some(Kind::Company(company)) = contact.kind else { unreachable!() };
+        assert_eq!(company.phones[0].number, "+2");
+        assert_eq!(company.emails[0].department, 2);
+    }
+
+    #[test]
+    fn remove_email_of_a_missing_address_fails() {
+        let mut contact = person("a@x.org", &[]);
+        assert_eq!(remove_email(&mut contact, "b@x.org"), Err("no email b@x.org".to_string()));
+        // пустой адрес человека - это отсутствие адреса, а не адрес ""
+        let mut contact = person("", &[]);
+        assert_eq!(remove_email(&mut contact, ""), Err("no email ".to_string()));
+        let mut contact = company(&["a@x.org"], &[]);
+        assert_eq!(remove_email(&mut contact, "b@x.org"), Err("no email b@x.org".to_string()));
+        assert_eq!(remove_email(&mut kindless(), "a@x.org"), Err(NO_KIND.to_string()));
+    }
+
+    #[test]
+    fn remove_email_clears_a_person_address() {
+        let mut contact = person("a@x.org", &[]);
+        remove_email(&mut contact, "a@x.org").unwrap();
+        assert_eq!(contact, person("", &[]));
+    }
+}
@@ -0,0 +1,245 @@
+use chrono::DateTime;
+use serde_json::{json, Map, Value};
+
+use super::{format_time, from_datetime};
+use crate::pb;
+use crate::pb::contact::Kind;
+use crate::pb::person::phone_number::Type;
+
+// Каноническое JSON-представление protobuf (proto3 JSON mapping):
+// поля в lowerCamelCase, перечисления - именами из .proto, Timestamp -
+// строкой RFC 3339, значения по умолчанию опускаются. При чтении годятся
+// и исходные имена полей, и числа вместо имён перечислений.
+
+pub fn write(book: &pb::AddressBook) -> String {
+    let contacts: Map<String, Value> = book
+        .contacts
+        .iter()
+        .map(|(name, contact)| (name.clone(), contact_to_json(contact)))
+        .collect();
+    let mut text = serde_json::to_string_pretty(&json!({ "contacts": contacts })).unwrap();
+    text.push('\n');
+    text
+}
+
+fn contact_to_json(contact: &pb::Contact) -> Value {
+    let mut object = Map::new();
+    if let Some(time) = contact.last_updated.as_ref().and_then(format_time) {
+        object.insert("lastUpdated".to_string(), json!(time));
+    }
+    match &contact.kind {
+        Some(Kind::Person(person)) => {
+            let mut value = Map::new();
+            if !person.email.is_empty() {
+                value.insert("email".to_string(), json!(person.email));
+            }
+            if !person.phones.is_empty() {
+                let phones = person.phones.iter().map(|p| number(&p.number, "type", type_name(p.r#type)));
+                value.insert("phones".to_string(), Value::Array(phones.collect()));
+            }
+            object.insert("person".to_string(), Value::Object(value));
+        }
+        Some(Kind::Company(company)) => {
+            let mut value = Map::new();
+            if !company.emails.is_empty() {
+                let emails = company.emails.iter().map(|e| {
+                    let mut email = Map::new();
+                    if !e.email.is_empty() {
+                        email.insert("email".to_string(), json!(e.email));
+                    }
+                    if let Some(department) = department_name(e.department) {
+                        email.insert("department".to_string(), department);
+                    }
+                    Value::Object(email)
+                });
+                value.insert("emails".to_string(), Value::Array(emails.collect()));
+            }
+            if !company.phones.is_empty() {
+                let phones = company.phones.iter().map(|p| number(&p.number, "department", department_name(p.department)));
+                value.insert("phones".to_string(), Value::Array(phones.collect()));
+            }
+            object.insert("company".to_string(), Value::Object(value));
+        }
+        None => {}
+    }
+    Value::Object(object)
+}
+
+fn number(number: &str, enum_field: &str, enum_value: Option<Value>) -> Value {
+    let mut phone = Map::new();
+    if !number.is_empty() {
+        phone.insert("number".to_string(), json!(number));
+    }
+    if let Some(value) = enum_value {
+        phone.insert(enum_field.to_string(), value);
+    }
+    Value::Object(phone)
+}
+
+// Нулевое значение опускается; неизвестное число пишется числом
+fn type_name(value: i32) -> Option<Value> {
+    match Type::try_from(value) {
+        Ok(Type::Unspecified) => None,
+        Ok(t) => Some(json!(t.as_str_name())),
+        Err(_) => Some(json!(value)),
+    }
+}
+
+fn department_name(value: i32) -> Option<Value> {
+    match pb::company::Department::try_from(value) {
+        Ok(pb::company::Department::Unspecified) => None,
+        Ok(d) => Some(json!(d.as_str_name())),
+        Err(_) => Some(json!(value)),
+    }
+}
+
+pub fn read(text: &str) -> Result<Vec<(String, pb::Contact)>, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let root = object(&root, "AddressBook")?;
+    let mut entries = Vec::new();
+    for (key, value) in root {
+        match key.as_str() {
+            "contacts" => {
+                if value.is_null() {
+                    continue;
+                }
+                let contacts = object(value, "contacts")?;
+                for (name, contact) in contacts {
+                    let contact = contact_from_json(contact).map_err(|e| format!("contact {}: {}", name, e))?;
+                    entries.push((name.clone(), contact));
+                }
+            }
+            other => return Err(unknown("AddressBook", other)),
+        }
+    }
+    Ok(entries)
+}
+
+fn contact_from_json(value: &Value) -> Result<pb::Contact, String> {
+    let mut contact = pb::Contact::default();
+    for (key, value) in object(value, "Contact")? {
+        if value.is_null() {
+            continue;
+        }
+        match key.as_str() {
+            "lastUpdated" | "last_updated" => {
+                let text = value.as_str().ok_or("lastUpdated must be an RFC 3339 string")?;
+                let time = DateTime::parse_from_rfc3339(text)
+                    .map_err(|e| format!("invalid lastUpdated {:?}: {}", text, e))?;
+                contact.last_updated = Some(from_datetime(time.to_utc()));
+            }
+            "person" | "company" if contact.kind.is_some() => {
+                return Err("both person and company are set".to_string());
+            }
+            "person" => contact.kind = Some(Kind::Person(person_from_json(value)?)),
+            "company" => contact.kind = Some(Kind::Company(company_from_json(value)?)),
+            other => return Err(unknown("Contact", other)),
+        }
+    }
+    if contact.kind.is_none() {
+        return Err("neither person nor company is set".to_string());
+    }
+    Ok(contact)
+}
+
+fn person_from_json(value: &Value) -> Result<pb::Person, String> {
+    let mut person = pb::Person::default();
+    for (key, value) in object(value, "Person")? {
+        match key.as_str() {
+            "email" => person.email = string(value, "email")?,
+            "phones" => {
+                for phone in array(value, "phones")? {
+                    let mut number = pb::person::PhoneNumber::default();
+                    for (key, value) in object(phone, "PhoneNumber")? {
+                        match key.as_str() {
+                            "number" => number.number = string(value, "number")?,
+                            "type" => number.r#type = enumeration(value, "type", |s| Type::from_str_name(s).map(|t| t as i32))?,
+                            other => return Err(unknown("PhoneNumber", other)),
+                        }
+                    }
+                    person.phones.push(number);
+                }
+            }
+            other => return Err(unknown("Person", other)),
+        }
+    }
+    Ok(person)
+}
+
+fn company_from_json(value: &Value) -> Result<pb::Company, String> {
+    let department = |value: &Value| {
+        enumeration(value, "department", |s| {
+            pb::company::Department::from_str_name(s).map(|d| d as i32)
+        })
+    };
+    let mut company = pb::Company::default();
+    for (key, value) in object(value, "Company")? {
+        match key.as_str() {
+            "emails" => {
+                for email in array(value, "emails")? {
+                    let mut address = pb::company::EmailAddress::default();
+                    for (key, value) in object(email, "EmailAddress")? {
+                        match key.as_str() {
+                            "email" => address.email = string(value, "email")?,
+                            "department" => address.department = department(value)?,
+                            other => return Err(unknown("EmailAddress", other)),
+                        }
+                    }
+                    company.emails.push(address);
+                }
+            }
+            "phones" => {
+                for phone in array(value, "phones")? {
+                    let mut number = pb::company::PhoneNumber::default();
+                    for (key, value) in object(phone, "PhoneNumber")? {
+                        match key.as_str() {
+                            "number" => number.number = string(value, "number")?,
+                            "department" => number.department = department(value)?,
+                            other => return Err(unknown("PhoneNumber", other)),
+                        }
+                    }
+                    company.phones.push(number);
+                }
+            }
+            other => return Err(unknown("Company", other)),
+        }
+    }
+    Ok(company)
+}
+
+fn object<'a>(value: &'a Value, what: &str) -> Result<&'a Map<String, Value>, String> {
+    value.as_object().ok_or_else(|| format!("{} must be an object", what))
+}
+
+// null в повторяющемся поле - пустой список
+fn array<'a>(value: &'a Value, what: &str) -> Result<&'a [Value], String> {
+    match value {
+        Value::Null => Ok(&[]),
+        Value::Array(items) => Ok(items),
+        _ => Err(format!("{} must be an array", what)),
+    }
+}
+
+fn string(value: &Value, what: &str) -> Result<String, String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(format!("{} must be a string", what)),
+    }
+}
+
+fn enumeration(value: &Value, what: &str, from_name: impl Fn(&str) -> Option<i32>) -> Result<i32, String> {
+    match value {
+        Value::Null => Ok(0),
+        Value::String(name) => from_name(name).ok_or_else(|| format!("unknown {} {:?}", what, name)),
+        Value::Number(n) => n
+            .as_i64()
+            .and_then(|n| i32::try_from(n).ok())
+            .ok_or_else(|| format!("invalid {} {}", what, n)),
+        _ => Err(format!("{} must be a name or a number", what)),
+    }
+}
+
+fn unknown(message: &str, field: &str) -> String {
+    format!("unknown field {:?} in {}", field, message)
+}
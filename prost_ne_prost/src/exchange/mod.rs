@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use prost_types::Timestamp;
+
+use crate::pb;
+use crate::pb::contact::Kind;
+
+mod json;
+mod table;
+mod vcard;
+
+/// Формат файла `import`/`export`: `--format`, иначе по расширению `--file`.
+#[derive(Clone, Copy)]
+pub enum Format {
+    VCard,
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Format, String> {
+        if let Some(format) = params.get("--format") {
+            return match format.as_str() {
+                "vcard" | "vcf" => Ok(Format::VCard),
+                "csv" => Ok(Format::Csv),
+                "json" => Ok(Format::Json),
+                other => Err(format!("Invalid --format {}, expected vcard, csv or json", other)),
+            };
+        }
+        let extension = params
+            .get("--file")
+            .and_then(|file| Path::new(file).extension())
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("vcf" | "vcard") => Ok(Format::VCard),
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            _ => Err("Missing --format (vcard, csv or json)".to_string()),
+        }
+    }
+}
+
+/// Книга в выбранном формате. vCard - версии 3.0 (по умолчанию) или
+/// `--vcard-version 4`.
+pub fn export(book: &pb::AddressBook, format: Format, params: &HashMap<String, String>) -> Result<String, String> {
+    match format {
+        Format::VCard => {
+            let version = match params.get("--vcard-version").map(String::as_str) {
+                None | Some("3" | "3.0") => vcard::Version::V3,
+                Some("4" | "4.0") => vcard::Version::V4,
+                Some(other) => return Err(format!("Invalid --vcard-version {}, expected 3 or 4", other)),
+            };
+            Ok(vcard::write(book, version))
+        }
+        Format::Csv => table::write(book),
+        Format::Json => Ok(json::write(book)),
+    }
+}
+
+/// Контакты из файла по порядку; одно имя может встретиться несколько раз.
+/// То, что пришлось отбросить при переводе, попадает в `warnings`.
+pub fn parse(
+    text: &str,
+    format: Format,
+    params: &HashMap<String, String>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<(String, pb::Contact)>, String> {
+    let entries = match format {
+        Format::VCard => vcard::read(text, warnings)?,
+        Format::Csv => table::read(text, params.get("--map").map(String::as_str), warnings)?,
+        Format::Json => json::read(text)?,
+    };
+    if entries.iter().any(|(name, _)| name.is_empty()) {
+        return Err("contact with an empty name".to_string());
+    }
+    Ok(entries)
+}
+
+/// Что делать, если контакт с таким именем уже есть и отличается.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Ничего не импортировать и перечислить совпавшие имена.
+    Fail,
+    Skip,
+    Replace,
+    /// Добавить к существующему контакту недостающие телефоны и адреса.
+    Merge,
+    /// Оставить контакт с более поздним `last_updated`.
+    Newer,
+    /// Импортировать под именем "Имя (2)".
+    Rename,
+}
+
+impl Strategy {
+    pub fn from_param(value: Option<&str>) -> Result<Strategy, String> {
+        match value {
+            None | Some("fail") => Ok(Strategy::Fail),
+            Some("skip") => Ok(Strategy::Skip),
+            Some("replace") => Ok(Strategy::Replace),
+            Some("merge") => Ok(Strategy::Merge),
+            Some("newer") => Ok(Strategy::Newer),
+            Some("rename") => Ok(Strategy::Rename),
+            Some(other) => Err(format!(
+                "Invalid --on-conflict {}, expected fail, skip, replace, merge, newer or rename",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Added,
+    Updated,
+    Unchanged,
+    Skipped,
+}
+
+/// Итог импорта одного контакта; `old` и `new` - для `--dry-run`.
+pub struct Change {
+    pub name: String,
+    // имя в файле, если контакт импортирован под другим (`rename`)
+    pub renamed_from: Option<String>,
+    pub outcome: Outcome,
+    pub old: Option<pb::Contact>,
+    pub new: Option<pb::Contact>,
+}
+
+/// Вносит контакты в книгу. С `Strategy::Fail` любое совпадение - ошибка,
+/// и книгу записывать нельзя. Контакт без `last_updated` получает `now`.
+/// То, что отброшено при слиянии, попадает в `warnings`.
+pub fn apply(
+    book: &mut pb::AddressBook,
+    entries: Vec<(String, pb::Contact)>,
+    strategy: Strategy,
+    now: Timestamp,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<Change>, String> {
+    let mut changes = Vec::new();
+    let mut collisions = Vec::new();
+    // время ставится после решения: для `newer` контакт без времени старше любого
+    let stamped = |mut contact: pb::Contact| {
+        contact.last_updated.get_or_insert(now);
+        contact
+    };
+    for (name, imported) in entries {
+        let Some(existing) = book.contacts.get(&name).cloned() else {
+            let imported = stamped(imported);
+            book.contacts.insert(name.clone(), imported.clone());
+            changes.push(change(name, Outcome::Added, None, Some(imported)));
+            continue;
+        };
+        if same_details(&existing, &imported) {
+            changes.push(change(name, Outcome::Unchanged, Some(existing), None));
+            continue;
+        }
+
+        let updated = match strategy {
+            Strategy::Fail => {
+                collisions.push(name);
+                continue;
+            }
+            Strategy::Skip => None,
+            Strategy::Replace => Some(stamped(imported)),
+            Strategy::Newer => Some(imported).filter(|imported| key(imported) > key(&existing)).map(stamped),
+            Strategy::Merge => {
+                let mut merged = existing.clone();
+                merge(&name, &mut merged, imported, warnings).map_err(|e| format!("Cannot merge {}: {}", name, e))?;
+                merged.last_updated = Some(now);
+                Some(merged).filter(|merged| !same_details(merged, &existing))
+            }
+            Strategy::Rename => {
+                let free = (2..)
+                    .map(|n| format!("{} ({})", name, n))
+                    .find(|candidate| !book.contacts.contains_key(candidate))
+                    .unwrap();
+                let imported = stamped(imported);
+                book.contacts.insert(free.clone(), imported.clone());
+                changes.push(Change {
+                    renamed_from: Some(name),
+                    ..change(free, Outcome::Added, None, Some(imported))
+                });
+                continue;
+            }
+        };
+        match updated {
+            Some(contact) => {
+                book.contacts.insert(name.clone(), contact.clone());
+                changes.push(change(name, Outcome::Updated, Some(existing), Some(contact)));
+            }
+            None => {
+                let outcome = if strategy == Strategy::Merge { Outcome::Unchanged } else { Outcome::Skipped };
+                changes.push(change(name, outcome, Some(existing), None));
+            }
+        }
+    }
+
+    if !collisions.is_empty() {
+        collisions.sort();
+        collisions.dedup();
+        return Err(format!(
+            "{} contact(s) already exist with different details: {}; choose --on-conflict skip, replace, merge, newer or rename",
+            collisions.len(),
+            collisions.join(", ")
+        ));
+    }
+    Ok(changes)
+}
+
+fn change(name: String, outcome: Outcome, old: Option<pb::Contact>, new: Option<pb::Contact>) -> Change {
+    Change {
+        name,
+        renamed_from: None,
+        outcome,
+        old,
+        new,
+    }
+}
+
+// Совпадают ли контакты без учёта времени обновления
+fn same_details(a: &pb::Contact, b: &pb::Contact) -> bool {
+    a.kind == b.kind
+}
+
+fn key(contact: &pb::Contact) -> (i64, i32) {
+    contact.last_updated.map_or((i64::MIN, 0), |t| (t.seconds, t.nanos))
+}
+
+fn merge(name: &str, existing: &mut pb::Contact, imported: pb::Contact, warnings: &mut Vec<String>) -> Result<(), String> {
+    match (existing.kind.as_mut(), imported.kind) {
+        (Some(Kind::Person(person)), Some(Kind::Person(new))) => {
+            // у человека один адрес: пустой заполняется, заполненный не трогаем
+            if person.email.is_empty() {
+                person.email = new.email;
+            } else if !new.email.is_empty() && new.email != person.email {
+                warnings.push(format!(
+                    "{}: a person has one email, kept {} and dropped {}",
+                    name, person.email, new.email
+                ));
+            }
+            for phone in new.phones {
+                if !person.phones.iter().any(|p| p.number == phone.number) {
+                    person.phones.push(phone);
+                }
+            }
+        }
+        (Some(Kind::Company(company)), Some(Kind::Company(new))) => {
+            for email in new.emails {
+                if !company.emails.iter().any(|e| e.email == email.email) {
+                    company.emails.push(email);
+                }
+            }
+            for phone in new.phones {
+                if !company.phones.iter().any(|p| p.number == phone.number) {
+                    company.phones.push(phone);
+                }
+            }
+        }
+        (_, None) => {}
+        (None, kind) => existing.kind = kind,
+        (Some(Kind::Person(_)), Some(Kind::Company(_))) => return Err("a company into a person".to_string()),
+        (Some(Kind::Company(_)), Some(Kind::Person(_))) => return Err("a person into a company".to_string()),
+    }
+    Ok(())
+}
+
+/// Строки отчёта `--dry-run` об одном контакте: `+` новый, `~` изменённый,
+/// `!` пропущенный; под ними - добавленные и убранные телефоны и адреса.
+pub fn describe(change: &Change) -> Vec<String> {
+    let title = match (&change.outcome, &change.renamed_from) {
+        (Outcome::Added, Some(from)) => format!("+ {} ({}, renamed from {})", change.name, kind_name(&change.new), from),
+        (Outcome::Added, None) => format!("+ {} ({})", change.name, kind_name(&change.new)),
+        (Outcome::Updated, _) => format!("~ {}", change.name),
+        (Outcome::Skipped, _) => format!("! {} (already exists, skipped)", change.name),
+        (Outcome::Unchanged, _) => return Vec::new(),
+    };
+    let old = change.old.as_ref().filter(|_| change.new.is_some()).map(details).unwrap_or_default();
+    let new = change.new.as_ref().map(details).unwrap_or_default();
+    let mut lines = vec![title];
+    if change.outcome == Outcome::Updated && kind_name(&change.old) != kind_name(&change.new) {
+        lines.push(format!("    kind {} -> {}", kind_name(&change.old), kind_name(&change.new)));
+    }
+    lines.extend(old.iter().filter(|item| !new.contains(item)).map(|item| format!("    - {}", item)));
+    lines.extend(new.iter().filter(|item| !old.contains(item)).map(|item| format!("    + {}", item)));
+    lines
+}
+
+fn kind_name(contact: &Option<pb::Contact>) -> &'static str {
+    match contact.as_ref().and_then(|c| c.kind.as_ref()) {
+        Some(Kind::Person(_)) => "person",
+        Some(Kind::Company(_)) => "company",
+        None => "empty",
+    }
+}
+
+fn details(contact: &pb::Contact) -> Vec<String> {
+    let tagged = |what: &str, value: &str, tag: Option<&str>| match tag {
+        Some(tag) => format!("{} {} ({})", what, value, tag),
+        None => format!("{} {}", what, value),
+    };
+    match &contact.kind {
+        Some(Kind::Person(person)) => {
+            let email = Some(&person.email).filter(|e| !e.is_empty()).map(|e| tagged("email", e, None));
+            let phones = person.phones.iter().map(|p| tagged("phone", &p.number, phone_type_name(p.r#type)));
+            email.into_iter().chain(phones).collect()
+        }
+        Some(Kind::Company(company)) => {
+            let emails = company.emails.iter().map(|e| tagged("email", &e.email, department_name(e.department)));
+            let phones = company.phones.iter().map(|p| tagged("phone", &p.number, department_name(p.department)));
+            emails.chain(phones).collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+// Имена типов и отделов те же, что у `add`: mobile, home, work, hr, cs
+fn phone_type_name(value: i32) -> Option<&'static str> {
+    match pb::person::phone_number::Type::try_from(value) {
+        Ok(pb::person::phone_number::Type::Mobile) => Some("mobile"),
+        Ok(pb::person::phone_number::Type::Home) => Some("home"),
+        Ok(pb::person::phone_number::Type::Work) => Some("work"),
+        _ => None,
+    }
+}
+
+fn department_name(value: i32) -> Option<&'static str> {
+    match pb::company::Department::try_from(value) {
+        Ok(pb::company::Department::Hr) => Some("hr"),
+        Ok(pb::company::Department::CustomerService) => Some("cs"),
+        _ => None,
+    }
+}
+
+fn to_datetime(time: &Timestamp) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(time.seconds, time.nanos.try_into().ok()?)
+}
+
+fn from_datetime(time: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+// RFC 3339 в UTC, доли секунды - только если есть: 2024-05-01T10:00:00Z
+fn format_time(time: &Timestamp) -> Option<String> {
+    to_datetime(time).map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-05-01T10:00:00Z; vCard хранит время с точностью до секунды
+    const MAY: i64 = 1_714_557_600;
+
+    fn at(seconds: i64) -> Option<Timestamp> {
+        Some(Timestamp { seconds, nanos: 0 })
+    }
+
+    fn person(email: &str, phones: &[(&str, i32)], updated: Option<Timestamp>) -> pb::Contact {
+        pb::Contact {
+            last_updated: updated,
+            kind: Some(Kind::Person(pb::Person {
+                email: email.to_string(),
+                phones: phones
+                    .iter()
+                    .map(|&(number, r#type)| pb::person::PhoneNumber {
+                        number: number.to_string(),
+                        r#type,
+                    })
+                    .collect(),
+            })),
+        }
+    }
+
+    fn company(emails: &[(&str, i32)], phones: &[(&str, i32)], updated: Option<Timestamp>) -> pb::Contact {
+        pb::Contact {
+            last_updated: updated,
+            kind: Some(Kind::Company(pb::Company {
+                emails: emails
+                    .iter()
+                    .map(|&(email, department)| pb::company::EmailAddress {
+                        email: email.to_string(),
+                        department,
+                    })
+                    .collect(),
+                phones: phones
+                    .iter()
+                    .map(|&(number, department)| pb::company::PhoneNumber {
+                        number: number.to_string(),
+                        department,
+                    })
+                    .collect(),
+            })),
+        }
+    }
+
+    // Разделители и кавычки в значениях, несколько значений одного столбца.
+    // CSV группирует значения по столбцам, поэтому они идут в порядке COLUMNS.
+    fn book() -> pb::AddressBook {
+        let contacts = HashMap::from([
+            (
+                "Ivan \"Vanya\" Petrov, Jr.".to_string(),
+                person("ivan@example.com", &[("12-34", 0), ("+7 495; ext 1", 1), ("+7 916", 1), ("+7 499", 2)], at(MAY)),
+            ),
+            ("Olga; Smirnova".to_string(), person("", &[("+7 812", 3)], at(MAY + 1))),
+            (
+                "Acme, Inc.".to_string(),
+                company(
+                    &[("info;sales@acme.org", 0), ("hr@acme.org", 1), ("help@acme.org", 2)],
+                    &[("+1 212", 1), ("+1 213", 1), ("+1 800", 2)],
+                    at(MAY + 2),
+                ),
+            ),
+        ]);
+        pb::AddressBook { contacts }
+    }
+
+    fn round_trip(format: Format, params: &[(&str, &str)]) -> pb::AddressBook {
+        let params = params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let text = export(&book(), format, &params).unwrap();
+        let mut warnings = Vec::new();
+        let entries = parse(&text, format, &params, &mut warnings).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(entries.len(), book().contacts.len());
+        pb::AddressBook {
+            contacts: entries.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn vcard_3_round_trip() {
+        assert_eq!(round_trip(Format::VCard, &[]), book());
+    }
+
+    #[test]
+    fn vcard_4_round_trip() {
+        assert_eq!(round_trip(Format::VCard, &[("--vcard-version", "4")]), book());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        assert_eq!(round_trip(Format::Csv, &[]), book());
+    }
+
+    #[test]
+    fn csv_repeats_columns_instead_of_joining_values() {
+        let text = export(&book(), Format::Csv, &HashMap::new()).unwrap();
+        let header = text.lines().next().unwrap();
+        assert!(header.contains("phone.mobile,phone.mobile,"), "{}", header);
+        assert!(header.contains("phone.hr,phone.hr,"), "{}", header);
+        assert_eq!(header.matches("email,").count(), 1, "{}", header);
+        assert!(text.contains(",12-34,+7 495; ext 1,+7 916,+7 499,"), "{}", text);
+    }
+
+    #[test]
+    fn csv_map_reads_every_column_with_the_header() {
+        let text = "Full Name,Mobile,Mobile\nIvan,+1,+2\n";
+        let params = HashMap::from([("--map".to_string(), "name=Full Name,phone.mobile=Mobile".to_string())]);
+        let entries = parse(text, Format::Csv, &params, &mut Vec::new()).unwrap();
+        assert_eq!(entries, vec![("Ivan".to_string(), person("", &[("+1", 1), ("+2", 1)], None))]);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_eq!(round_trip(Format::Json, &[]), book());
+    }
+
+    #[test]
+    fn json_contact_needs_a_kind() {
+        let text = r#"{"contacts": {"Ivan": {"lastUpdated": "2024-05-01T10:00:00Z"}}}"#;
+        let err = parse(text, Format::Json, &HashMap::new(), &mut Vec::new()).unwrap_err();
+        assert_eq!(err, "contact Ivan: neither person nor company is set");
+    }
+
+    // Книга с одним Ivan от MAY и импорт другого Ivan со временем `updated`
+    fn import(strategy: Strategy, updated: Option<Timestamp>) -> (pb::AddressBook, Result<Vec<Change>, String>) {
+        let mut book = pb::AddressBook {
+            contacts: HashMap::from([("Ivan".to_string(), person("", &[("+1", 1)], at(MAY)))]),
+        };
+        let entries = vec![("Ivan".to_string(), person("", &[("+2", 2)], updated))];
+        let result = apply(&mut book, entries, strategy, at(MAY + 100).unwrap(), &mut Vec::new());
+        (book, result)
+    }
+
+    fn outcomes(result: Result<Vec<Change>, String>) -> Vec<(String, Outcome)> {
+        result.unwrap().into_iter().map(|c| (c.name, c.outcome)).collect()
+    }
+
+    #[test]
+    fn new_contact_is_added_with_the_import_time() {
+        let mut book = pb::AddressBook::default();
+        let entries = vec![("Olga".to_string(), person("", &[], None))];
+        let changes = apply(&mut book, entries, Strategy::Fail, at(MAY).unwrap(), &mut Vec::new()).unwrap();
+        assert!(changes[0].outcome == Outcome::Added);
+        assert_eq!(book.contacts["Olga"].last_updated, at(MAY));
+    }
+
+    #[test]
+    fn same_details_are_unchanged_for_any_strategy() {
+        let mut book = pb::AddressBook {
+            contacts: HashMap::from([("Ivan".to_string(), person("", &[("+1", 1)], at(MAY)))]),
+        };
+        let entries = vec![("Ivan".to_string(), person("", &[("+1", 1)], at(MAY + 1)))];
+        let changes = apply(&mut book, entries, Strategy::Fail, at(MAY + 100).unwrap(), &mut Vec::new());
+        assert!(outcomes(changes) == vec![("Ivan".to_string(), Outcome::Unchanged)]);
+        assert_eq!(book.contacts["Ivan"].last_updated, at(MAY));
+    }
+
+    #[test]
+    fn fail_lists_collisions_and_keeps_the_contact() {
+        let (book, result) = import(Strategy::Fail, None);
+        let err = result.err().unwrap();
+        assert!(err.starts_with("1 contact(s) already exist with different details: Ivan;"), "{}", err);
+        assert_eq!(book.contacts["Ivan"], person("", &[("+1", 1)], at(MAY)));
+    }
+
+    #[test]
+    fn skip_keeps_the_contact() {
+        let (book, result) = import(Strategy::Skip, at(MAY + 1));
+        assert!(outcomes(result) == vec![("Ivan".to_string(), Outcome::Skipped)]);
+        assert_eq!(book.contacts["Ivan"], person("", &[("+1", 1)], at(MAY)));
+    }
+
+    #[test]
+    fn replace_takes_the_imported_contact() {
+        let (book, result) = import(Strategy::Replace, None);
+        assert!(outcomes(result) == vec![("Ivan".to_string(), Outcome::Updated)]);
+        assert_eq!(book.contacts["Ivan"], person("", &[("+2", 2)], at(MAY + 100)));
+    }
+
+    #[test]
+    fn merge_adds_missing_phones() {
+        let (book, result) = import(Strategy::Merge, at(MAY - 1));
+        assert!(outcomes(result) == vec![("Ivan".to_string(), Outcome::Updated)]);
+        assert_eq!(book.contacts["Ivan"], person("", &[("+1", 1), ("+2", 2)], at(MAY + 100)));
+
+        let mut book = book;
+        let entries = vec![("Ivan".to_string(), company(&[], &[], None))];
+        let err = apply(&mut book, entries, Strategy::Merge, at(MAY).unwrap(), &mut Vec::new()).err().unwrap();
+        assert_eq!(err, "Cannot merge Ivan: a company into a person");
+    }
+
+    #[test]
+    fn merge_warns_about_a_dropped_person_email() {
+        let mut book = pb::AddressBook {
+            contacts: HashMap::from([("Ivan".to_string(), person("ivan@example.com", &[], at(MAY)))]),
+        };
+        let entries = vec![("Ivan".to_string(), person("vanya@example.com", &[("+1", 1)], None))];
+        let mut warnings = Vec::new();
+        apply(&mut book, entries, Strategy::Merge, at(MAY + 100).unwrap(), &mut warnings).unwrap();
+        assert_eq!(book.contacts["Ivan"], person("ivan@example.com", &[("+1", 1)], at(MAY + 100)));
+        assert_eq!(
+            warnings,
+            ["Ivan: a person has one email, kept ivan@example.com and dropped vanya@example.com"]
+        );
+    }
+
+    #[test]
+    fn newer_keeps_the_later_contact() {
+        let (book, result) = import(Strategy::Newer, at(MAY + 1));
+        assert!(outcomes(result) == vec![("Ivan".to_string(), Outcome::Updated)]);
+        assert_eq!(book.contacts["Ivan"], person("", &[("+2", 2)], at(MAY + 1)));
+
+        for older in [at(MAY - 1), at(MAY)] {
+            let (book, result) = import(Strategy::Newer, older);
+            assert!(outcomes(result) == vec![("Ivan".to_string(), Outcome::Skipped)]);
+            assert_eq!(book.contacts["Ivan"].last_updated, at(MAY));
+        }
+    }
+
+    #[test]
+    fn newer_treats_a_missing_time_as_older() {
+        let (book, result) = import(Strategy::Newer, None);
+        assert!(outcomes(result) == vec![("Ivan".to_string(), Outcome::Skipped)]);
+        assert_eq!(book.contacts["Ivan"], person("", &[("+1", 1)], at(MAY)));
+
+        // у существующего времени нет: любое время импорта новее
+        let mut book = pb::AddressBook {
+            contacts: HashMap::from([("Ivan".to_string(), person("", &[("+1", 1)], None))]),
+        };
+        let entries = vec![("Ivan".to_string(), person("", &[("+2", 2)], at(MAY)))];
+        apply(&mut book, entries, Strategy::Newer, at(MAY + 100).unwrap(), &mut Vec::new()).unwrap();
+        assert_eq!(book.contacts["Ivan"], person("", &[("+2", 2)], at(MAY)));
+    }
+
+    #[test]
+    fn rename_imports_under_a_free_name() {
+        let mut book = pb::AddressBook {
+            contacts: HashMap::from([
+                ("Ivan".to_string(), person("", &[("+1", 1)], at(MAY))),
+                ("Ivan (2)".to_string(), person("", &[("+3", 3)], at(MAY))),
+            ]),
+        };
+        let entries = vec![("Ivan".to_string(), person("", &[("+2", 2)], None))];
+        let changes = apply(&mut book, entries, Strategy::Rename, at(MAY + 100).unwrap(), &mut Vec::new()).unwrap();
+        assert_eq!(changes[0].renamed_from.as_deref(), Some("Ivan"));
+        assert!(outcomes(Ok(changes)) == vec![("Ivan (3)".to_string(), Outcome::Added)]);
+        assert_eq!(book.contacts["Ivan (3)"], person("", &[("+2", 2)], at(MAY + 100)));
+        assert_eq!(book.contacts["Ivan"], person("", &[("+1", 1)], at(MAY)));
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::DateTime;
+
+use super::{department_name, format_time, from_datetime, phone_type_name};
+use crate::pb;
+use crate::pb::contact::Kind;
+use crate::{str_to_department, str_to_phone_type};
+
+// Столбцы `export`; они же - цели `--map` при импорте. Уточнение после
+// точки - тип телефона человека (mobile, home, work) или отдел компании
+// (hr, cs). Если значений несколько, столбец повторяется: по значению в
+// ячейке, без разделителей внутри неё.
+const COLUMNS: &[&str] = &[
+    "name",
+    "kind",
+    "last_updated",
+    "email",
+    "email.hr",
+    "email.cs",
+    "phone",
+    "phone.mobile",
+    "phone.home",
+    "phone.work",
+    "phone.hr",
+    "phone.cs",
+];
+
+const TAGS: &[Option<&str>] = &[None, Some("mobile"), Some("home"), Some("work"), Some("hr"), Some("cs")];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Name,
+    Kind,
+    LastUpdated,
+    // уточнение: тип телефона или отдел, как в `add`
+    Email(Option<&'static str>),
+    Phone(Option<&'static str>),
+}
+
+fn target(column: &str) -> Option<Target> {
+    let column = column.trim().to_lowercase();
+    let (field, tag) = match column.split_once('.') {
+        Some((field, tag)) => (field, Some(tag)),
+        None => (column.as_str(), None),
+    };
+    let tag = match tag {
+        None => None,
+        Some(tag) => Some(*TAGS.iter().flatten().find(|t| **t == tag)?),
+    };
+    match (field, tag) {
+        ("name", None) => Some(Target::Name),
+        ("kind", None) => Some(Target::Kind),
+        ("last_updated", None) => Some(Target::LastUpdated),
+        ("email", tag) => Some(Target::Email(tag)),
+        ("phone", tag) => Some(Target::Phone(tag)),
+        _ => None,
+    }
+}
+
+pub fn write(book: &pb::AddressBook) -> Result<String, String> {
+    let mut names: Vec<&String> = book.contacts.keys().collect();
+    names.sort();
+    // значения каждого контакта по столбцам COLUMNS
+    let rows: Vec<Vec<Vec<String>>> = names.iter().map(|name| values(name, &book.contacts[*name])).collect();
+    // столбец повторяется столько раз, сколько значений у самого богатого контакта
+    let widths: Vec<usize> = (0..COLUMNS.len())
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or_default().max(1))
+        .collect();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header = COLUMNS.iter().zip(&widths).flat_map(|(column, &width)| std::iter::repeat_n(*column, width));
+    writer.write_record(header).map_err(|e| e.to_string())?;
+    for row in rows {
+        let record = row.iter().zip(&widths).flat_map(|(cell, &width)| {
+            cell.iter().map(String::as_str).chain(std::iter::repeat("")).take(width)
+        });
+        writer.write_record(record).map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+fn values(name: &str, contact: &pb::Contact) -> Vec<Vec<String>> {
+    let mut row = vec![Vec::new(); COLUMNS.len()];
+    let mut push = |field: &str, tag: Option<&str>, value: &str| {
+        let column = match tag {
+            Some(tag) => format!("{}.{}", field, tag),
+            None => field.to_string(),
+        };
+        row[COLUMNS.iter().position(|c| *c == column).unwrap()].push(value.to_string());
+    };
+    push("name", None, name);
+    if let Some(time) = contact.last_updated.as_ref().and_then(format_time) {
+        push("last_updated", None, &time);
+    }
+    match &contact.kind {
+        Some(Kind::Person(person)) => {
+            push("kind", None, "person");
+            if !person.email.is_empty() {
+                push("email", None, &person.email);
+            }
+            for phone in &person.phones {
+                push("phone", phone_type_name(phone.r#type), &phone.number);
+            }
+        }
+        Some(Kind::Company(company)) => {
+            push("kind", None, "company");
+            for email in &company.emails {
+                push("email", department_name(email.department), &email.email);
+            }
+            for phone in &company.phones {
+                push("phone", department_name(phone.department), &phone.number);
+            }
+        }
+        None => {}
+    }
+    row
+}
+
+/// Без `map` заголовок должен состоять из столбцов `export`. `map` -
+/// `цель=Столбец,...`, например `name=Full Name,phone.mobile=Mobile Phone`;
+/// одну цель можно взять из нескольких столбцов, прочие столбцы не читаются.
+pub fn read(text: &str, map: Option<&str>, warnings: &mut Vec<String>) -> Result<Vec<(String, pb::Contact)>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|h| h.trim_start_matches('\u{feff}').to_string())
+        .collect();
+
+    // номер столбца и что в нём
+    let columns: Vec<(usize, Target)> = match map {
+        None => headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                target(header)
+                    .map(|t| (i, t))
+                    .ok_or_else(|| format!("unknown column {:?}, use --map target=column to map it", header))
+            })
+            .collect::<Result<_, _>>()?,
+        Some(map) => parse_map(map, &headers)?,
+    };
+    if !columns.iter().any(|(_, t)| *t == Target::Name) {
+        return Err("no name column".to_string());
+    }
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let line = record.position().map_or(0, |p| p.line());
+        let cell = |i: usize| record.get(i).unwrap_or_default();
+        // цель может прийти из нескольких столбцов: по значению из каждого
+        let values = |target: Target| {
+            columns
+                .iter()
+                .filter(move |(_, t)| *t == target)
+                .map(move |(i, _)| cell(*i).trim())
+                .filter(|v| !v.is_empty())
+        };
+
+        let Some(name) = values(Target::Name).next().map(str::to_string) else {
+            warnings.push(format!("line {}: no name, skipped", line));
+            continue;
+        };
+        let err = |e: String| format!("line {}: {}", line, e);
+        let mut contact = pb::Contact::default();
+        if let Some(time) = values(Target::LastUpdated).next() {
+            let time = DateTime::parse_from_rfc3339(time).map_err(|e| err(format!("invalid last_updated {:?}: {}", time, e)))?;
+            contact.last_updated = Some(from_datetime(time.to_utc()));
+        }
+
+        // значения со своими уточнениями, по порядку TAGS
+        let emails = || TAGS.iter().flat_map(|&tag| values(Target::Email(tag)).map(move |v| (tag, v)));
+        let phones = || TAGS.iter().flat_map(|&tag| values(Target::Phone(tag)).map(move |v| (tag, v)));
+
+        let kind = values(Target::Kind).next().unwrap_or("person").to_lowercase();
+        contact.kind = match kind.as_str() {
+            "person" | "per" => {
+                let mut emails = emails().map(|(_, email)| email);
+                let email = emails.next().unwrap_or_default().to_string();
+                let extra = emails.count();
+                if extra > 0 {
+                    warnings.push(format!("{}: a person has one email, kept {} and dropped {} more", name, email, extra));
+                }
+                Some(Kind::Person(pb::Person {
+                    email,
+                    phones: phones()
+                        .map(|(tag, number)| pb::person::PhoneNumber {
+                            number: number.to_string(),
+                            r#type: str_to_phone_type(tag.unwrap_or_default()),
+                        })
+                        .collect(),
+                }))
+            }
+            "company" | "cie" | "org" => Some(Kind::Company(pb::Company {
+                emails: emails()
+                    .map(|(tag, email)| pb::company::EmailAddress {
+                        email: email.to_string(),
+                        department: str_to_department(tag.unwrap_or_default()),
+                    })
+                    .collect(),
+                phones: phones()
+                    .map(|(tag, number)| pb::company::PhoneNumber {
+                        number: number.to_string(),
+                        department: str_to_department(tag.unwrap_or_default()),
+                    })
+                    .collect(),
+            })),
+            other => return Err(err(format!("invalid kind {:?}, expected person or company", other))),
+        };
+        entries.push((name, contact));
+    }
+    Ok(entries)
+}
+
+fn parse_map(map: &str, headers: &[String]) -> Result<Vec<(usize, Target)>, String> {
+    let mut columns = Vec::new();
+    for pair in map.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (target_name, header) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("invalid --map entry {:?}, expected target=column", pair))?;
+        let target = target(target_name).ok_or_else(|| {
+            format!("invalid --map target {:?}, expected one of {}", target_name.trim(), COLUMNS.join(", "))
+        })?;
+        // повторённый заголовок - несколько столбцов с одной целью
+        let before = columns.len();
+        columns.extend(
+            headers
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| h.trim().eq_ignore_ascii_case(header.trim()))
+                .map(|(i, _)| (i, target)),
+        );
+        if columns.len() == before {
+            return Err(format!("column {:?} from --map is not in the header", header.trim()));
+        }
+    }
+    Ok(columns)
+}
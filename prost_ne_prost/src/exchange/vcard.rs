@@ -0,0 +1,371 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use super::{department_name, from_datetime, phone_type_name, to_datetime};
+use crate::pb;
+use crate::pb::contact::Kind;
+use crate::{str_to_department, str_to_phone_type};
+
+// vCard 3.0 (RFC 2426) и 4.0 (RFC 6350). Отдел компании у EMAIL и TEL
+// пишется нестандартным параметром X-DEPARTMENT=hr|cs.
+
+// Длина строки в октетах, дальше - перенос с пробелом в начале следующей
+const LINE_LIMIT: usize = 75;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Version {
+    V3,
+    V4,
+}
+
+pub fn write(book: &pb::AddressBook, version: Version) -> String {
+    let mut names: Vec<&String> = book.contacts.keys().collect();
+    names.sort();
+    let mut out = String::new();
+    for name in names {
+        write_card(&mut out, name, &book.contacts[name], version);
+    }
+    out
+}
+
+fn write_card(out: &mut String, name: &str, contact: &pb::Contact, version: Version) {
+    let v3 = version == Version::V3;
+    // в 3.0 значения TYPE принято писать заглавными, в 4.0 - строчными
+    let type_param = |value: &str| {
+        let value = if v3 { value.to_uppercase() } else { value.to_string() };
+        format!(";TYPE={}", value)
+    };
+
+    push_line(out, "BEGIN:VCARD");
+    push_line(out, if v3 { "VERSION:3.0" } else { "VERSION:4.0" });
+    push_line(out, &format!("FN:{}", escape(name)));
+    match &contact.kind {
+        Some(Kind::Person(person)) => {
+            // в 3.0 N обязателен; как делить имя на части, неизвестно
+            if v3 {
+                push_line(out, &format!("N:;{};;;", escape(name)));
+            } else {
+                push_line(out, "KIND:individual");
+            }
+            if !person.email.is_empty() {
+                let params = if v3 { type_param("internet") } else { String::new() };
+                push_line(out, &format!("EMAIL{}:{}", params, escape(&person.email)));
+            }
+            for phone in &person.phones {
+                let params = match phone_type_name(phone.r#type) {
+                    Some("mobile") => type_param("cell"),
+                    Some(kind) => type_param(kind),
+                    None => String::new(),
+                };
+                push_line(out, &format!("TEL{}:{}", params, escape(&phone.number)));
+            }
+        }
+        Some(Kind::Company(company)) => {
+            if v3 {
+                push_line(out, "N:;;;;");
+            } else {
+                push_line(out, "KIND:org");
+            }
+            push_line(out, &format!("ORG:{}", escape(name)));
+            let department = |value: i32| {
+                department_name(value).map_or(String::new(), |d| format!(";X-DEPARTMENT={}", d))
+            };
+            for email in &company.emails {
+                let params = if v3 { type_param("internet") } else { String::new() };
+                push_line(out, &format!("EMAIL{}{}:{}", params, department(email.department), escape(&email.email)));
+            }
+            for phone in &company.phones {
+                let params = type_param("work");
+                push_line(out, &format!("TEL{}{}:{}", params, department(phone.department), escape(&phone.number)));
+            }
+        }
+        None => {}
+    }
+    if let Some(time) = contact.last_updated.as_ref().and_then(to_datetime) {
+        push_line(out, &format!("REV:{}", time.format("%Y%m%dT%H%M%SZ")));
+    }
+    push_line(out, "END:VCARD");
+}
+
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > LINE_LIMIT {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Снимает экранирование и делит составное значение (N, ORG) по неэкранированной `;`
+fn unescape_parts(value: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => parts.last_mut().unwrap().push('\n'),
+                Some(other) => parts.last_mut().unwrap().push(other),
+                None => {}
+            },
+            ';' => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+fn unescape(value: &str) -> String {
+    unescape_parts(value).join(";")
+}
+
+struct Property {
+    name: String,
+    // имена параметров заглавными, значения строчными
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn has(&self, param: &str, value: &str) -> bool {
+        self.params.iter().any(|(p, v)| p == param && v == value)
+    }
+
+    fn param(&self, param: &str) -> Option<&str> {
+        self.params.iter().find(|(p, _)| p == param).map(|(_, v)| v.as_str())
+    }
+}
+
+// group.NAME;PARAM=a,b;BARE:value
+fn parse_property(line: &str) -> Option<Property> {
+    let colon = split_unquoted(line, ':').first()?.len();
+    if colon == line.len() {
+        return None;
+    }
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = split_unquoted(head, ';').into_iter();
+    let name = parts.next()?;
+    let name = name.rsplit('.').next().unwrap_or(name).trim().to_uppercase();
+
+    let mut params = Vec::new();
+    for part in parts {
+        match part.split_once('=') {
+            Some((key, values)) => {
+                // TYPE="cell,voice" - тот же список, что и TYPE=cell,voice
+                for value in values.trim().trim_matches('"').split(',') {
+                    params.push((key.trim().to_uppercase(), value.trim().to_lowercase()));
+                }
+            }
+            // vCard 2.1 пишет тип без имени параметра: TEL;CELL:...
+            None => params.push(("TYPE".to_string(), part.trim().to_lowercase())),
+        }
+    }
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// Склеивает перенесённые строки; номер строки - где началось свойство
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            _ => lines.push((i + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+pub fn read(text: &str, warnings: &mut Vec<String>) -> Result<Vec<(String, pb::Contact)>, String> {
+    let mut entries = Vec::new();
+    let mut card: Option<(usize, Vec<Property>)> = None;
+    for (line, text) in unfold(text) {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let property = parse_property(&text).ok_or_else(|| format!("line {}: not a vCard property", line))?;
+        match (property.name.as_str(), card.as_mut()) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VCARD") => card = Some((line, Vec::new())),
+            ("BEGIN", Some(_)) => return Err(format!("line {}: BEGIN inside a card", line)),
+            ("END", Some(_)) => {
+                let (start, properties) = card.take().unwrap();
+                let entry = to_contact(&properties, warnings).map_err(|e| format!("card at line {}: {}", start, e))?;
+                entries.push(entry);
+            }
+            (_, Some((_, properties))) => properties.push(property),
+            (_, None) => return Err(format!("line {}: {} outside BEGIN:VCARD", line, property.name)),
+        }
+    }
+    if let Some((start, _)) = card {
+        return Err(format!("card at line {}: missing END:VCARD", start));
+    }
+    Ok(entries)
+}
+
+fn to_contact(properties: &[Property], warnings: &mut Vec<String>) -> Result<(String, pb::Contact), String> {
+    let first = |name: &str| properties.iter().find(|p| p.name == name);
+    let all = |name: &'static str| properties.iter().filter(move |p| p.name == name);
+
+    if let Some(version) = first("VERSION").map(|p| p.value.trim()) {
+        if version != "3.0" && version != "4.0" {
+            return Err(format!("unsupported vCard version {}, expected 3.0 or 4.0", version));
+        }
+    }
+
+    let org = first("ORG").map(|p| unescape_parts(&p.value).remove(0)).filter(|o| !o.is_empty());
+    // N: фамилия;имя;отчество;приставка;суффикс
+    let structured = first("N").map(|p| {
+        let parts = unescape_parts(&p.value);
+        [3, 1, 2, 0, 4]
+            .iter()
+            .filter_map(|&i| parts.get(i).map(|s| s.trim()).filter(|s| !s.is_empty()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    let structured = structured.filter(|n| !n.is_empty());
+    let name = first("FN")
+        .map(|p| unescape(&p.value).trim().to_string())
+        .filter(|n| !n.is_empty())
+        .or(structured.clone())
+        .or(org.clone())
+        .ok_or("no FN, N or ORG")?;
+
+    // 4.0 говорит прямо, 3.0 - компания, если в карточке только её название
+    let company = match first("KIND").map(|p| p.value.trim().to_lowercase()) {
+        Some(kind) => kind == "org",
+        None => {
+            first("X-ABSHOWAS").is_some_and(|p| p.value.eq_ignore_ascii_case("company"))
+                || (structured.is_none() && org.as_deref() == Some(name.as_str()))
+        }
+    };
+
+    let emails: Vec<(&Property, String)> = all("EMAIL")
+        .map(|p| (p, unescape(&p.value).trim().to_string()))
+        .filter(|(_, e)| !e.is_empty())
+        .collect();
+    let phones: Vec<(&Property, String)> = all("TEL")
+        .map(|p| {
+            let value = unescape(&p.value);
+            let value = value.trim();
+            (p, value.strip_prefix("tel:").unwrap_or(value).to_string())
+        })
+        .filter(|(_, n)| !n.is_empty())
+        .collect();
+    let department = |p: &Property| str_to_department(p.param("X-DEPARTMENT").unwrap_or_default());
+
+    let kind = if company {
+        Kind::Company(pb::Company {
+            emails: emails
+                .into_iter()
+                .map(|(p, email)| pb::company::EmailAddress {
+                    email,
+                    department: department(p),
+                })
+                .collect(),
+            phones: phones
+                .into_iter()
+                .map(|(p, number)| pb::company::PhoneNumber {
+                    number,
+                    department: department(p),
+                })
+                .collect(),
+        })
+    } else {
+        // предпочтительный адрес (PREF) - первым
+        let mut emails = emails;
+        emails.sort_by_key(|(p, _)| !(p.has("TYPE", "pref") || p.param("PREF").is_some()));
+        if emails.len() > 1 {
+            warnings.push(format!(
+                "{}: a person has one email, kept {} and dropped {} more",
+                name,
+                emails[0].1,
+                emails.len() - 1
+            ));
+        }
+        Kind::Person(pb::Person {
+            email: emails.into_iter().next().map(|(_, e)| e).unwrap_or_default(),
+            phones: phones
+                .into_iter()
+                .map(|(p, number)| {
+                    let kind = ["cell", "mobile", "home", "work"]
+                        .into_iter()
+                        .find(|t| p.has("TYPE", t))
+                        .map(|t| if t == "cell" { "mobile" } else { t });
+                    pb::person::PhoneNumber {
+                        number,
+                        r#type: str_to_phone_type(kind.unwrap_or_default()),
+                    }
+                })
+                .collect(),
+        })
+    };
+
+    let mut contact = pb::Contact {
+        last_updated: None,
+        kind: Some(kind),
+    };
+    if let Some(rev) = first("REV").map(|p| p.value.trim()) {
+        match parse_rev(rev) {
+            Some(time) => contact.last_updated = Some(time),
+            None => warnings.push(format!("{}: invalid REV {:?}, using the import time", name, rev)),
+        }
+    }
+    Ok((name, contact))
+}
+
+// 20240501T100000Z, 2024-05-01T10:00:00Z или только дата
+fn parse_rev(value: &str) -> Option<prost_types::Timestamp> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(from_datetime(time.to_utc()));
+    }
+    let value = value.trim_end_matches('Z');
+    for format in ["%Y%m%dT%H%M%S", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(from_datetime(time.and_utc()));
+        }
+    }
+    for format in ["%Y%m%d", "%Y-%m-%d"] {
+        if let Ok(date) = NaiveDate::parse_from_str(value, format) {
+            return Some(from_datetime(date.and_hms_opt(0, 0, 0)?.and_utc()));
+        }
+    }
+    None
+}
@@ -1,17 +1,24 @@
-use std::fs;
 use std::collections::HashMap;
 use std::error::Error;
-use std::io::{BufReader, Read, Write, BufWriter};
-use prost::Message;
+use std::fs;
+use std::io::{self, Read};
 use prost_types::Timestamp;
 use std::time;
 use std::{env, process};
 
+use storage::{Db, StorageError};
+
+mod contacts;
+mod exchange;
+mod search;
+mod storage;
+
 mod pb {
     include!(concat!(env!("OUT_DIR"), "/addressbook.rs"));
 }
 
 const DB_FILE_PATH: &str = "addressbook.db";
+const FLAGS: &[&str] = &["--redact", "--dry-run"];
 
 struct Config {
     command: String,
@@ -28,13 +35,12 @@ impl Config {
         let command = args.next().ok_or("Command not found")?;
 
         let mut params: HashMap<String, String> = HashMap::new();
-        let mut redact = false;
 
         while let Some(arg) = args.next() {
             if arg.starts_with("--") {
-                // установка флага редактирования
-                if arg == "--redact" {
-                    redact = true; // Устанавливаем флаг редактирования
+                // флаги (--redact, --dry-run) идут без значения
+                if FLAGS.contains(&arg.as_str()) {
+                    params.insert(arg, "true".to_string());
                 } else {
                     let param = args.next().ok_or("Missing parameter after --arg")?;
                     params.insert(arg, param);
@@ -44,39 +50,10 @@ impl Config {
             }
         }
 
-        // Добавляем флаг в параметры
-        if redact {
-            params.insert("--redact".to_string(), "true".to_string());
-        }
-        //
         Ok(Config { command, params })
     }
 }
 
-// открытие дб
-fn open_db_file(file_path: &str) -> fs::File {
-    fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(file_path)
-        .unwrap()
-}
-
-fn read_from_db(f: &mut fs::File) -> pb::AddressBook {
-    let mut buf_reader = BufReader::new(f); // Создаем буферизованный читател
-    let mut contents = Vec::new();
-    buf_reader.read_to_end(&mut contents).unwrap();
-    pb::AddressBook::decode(contents.as_slice()).unwrap() // Декодируем содержимое в AddressBook
-}
-
-fn write_to_db(f: &mut fs::File, book: pb::AddressBook) {
-    let mut buf_writer = BufWriter::new(f);
-    let contents = book.encode_to_vec(); // Кодируем AddressBook в вектор байтов
-    buf_writer.write_all(&contents).unwrap(); // запись
-    buf_writer.flush().unwrap(); // сброс буфера
-}
-
 fn str_to_phone_type(s: &str) -> i32 {
     match s {
         "home" => 2,
@@ -94,56 +71,64 @@ fn str_to_department(s: &str) -> i32 {
     }
 }
 
-fn add_person(f: &mut fs::File, name: &str, email: &str, phone: &str, phone_type: &str) {
-    let mut book = read_from_db(f);
-    let mut person = pb::Person::default(); // создание экземпляра по умолчанию
+// Временная метка текущего момента
+fn now() -> Timestamp {
+    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
+    Timestamp {
+        seconds: duration.as_secs() as i64,
+        nanos: duration.subsec_nanos() as i32,
+    }
+}
+
+fn add_person(db: &Db, name: &str, email: &str, phone: &str, phone_type: &str) -> Result<(), StorageError> {
+    let mut book = db.read()?;
 
     // установка аргументов
-    person.email = email.to_string();
-    let mut nb = pb::person::PhoneNumber::default();
-    nb.number = phone.to_string();
-    nb.r#type = str_to_phone_type(phone_type);
-    person.phones.push(nb);
+    let person = pb::Person {
+        email: email.to_string(),
+        phones: vec![pb::person::PhoneNumber {
+            number: phone.to_string(),
+            r#type: str_to_phone_type(phone_type),
+        }],
+    };
 
     // Создаем новый контакт
-    let mut contact = pb::Contact::default();
-    let mut update_ts = Timestamp::default(); // Создаем временную метку
-    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
-    update_ts.seconds = duration.as_secs() as i64;
-    update_ts.nanos = duration.subsec_nanos() as i32;
-
-    contact.last_updated = Some(update_ts); // установка времени обновление
-    contact.kind = Some(pb::contact::Kind::Person(person)); // Тип
+    let contact = pb::Contact {
+        last_updated: Some(now()), // установка времени обновление
+        kind: Some(pb::contact::Kind::Person(person)), // Тип
+    };
     book.contacts.insert(name.to_string(), contact); // Добавление в книгу
 
-    write_to_db(f, book);
+    db.write(&book)
 }
 
-fn add_company(f: &mut fs::File, name: &str, email: &str, email_dep: &str, phone: &str, phone_dep: &str) {
-    let mut book = read_from_db(f);
-    let mut company = pb::Company::default();
-
-    let mut addr = pb::company::EmailAddress::default();
-    addr.email = email.to_string();
-    addr.department = str_to_department(email_dep);
-    company.emails.push(addr);
-
-    let mut nb = pb::company::PhoneNumber::default();
-    nb.number = phone.to_string();
-    nb.department = str_to_department(phone_dep);
-    company.phones.push(nb);
-
-    let mut contact = pb::Contact::default();
-    let mut update_ts = Timestamp::default();
-    let duration = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
-    update_ts.seconds = duration.as_secs() as i64;
-    update_ts.nanos = duration.subsec_nanos() as i32;
-
-    contact.last_updated = Some(update_ts);
-    contact.kind = Some(pb::contact::Kind::Company(company));
+fn add_company(
+    db: &Db,
+    name: &str,
+    email: &str,
+    email_dep: &str,
+    phone: &str,
+    phone_dep: &str,
+) -> Result<(), StorageError> {
+    let mut book = db.read()?;
+    let company = pb::Company {
+        emails: vec![pb::company::EmailAddress {
+            email: email.to_string(),
+            department: str_to_department(email_dep),
+        }],
+        phones: vec![pb::company::PhoneNumber {
+            number: phone.to_string(),
+            department: str_to_department(phone_dep),
+        }],
+    };
+
+    let contact = pb::Contact {
+        last_updated: Some(now()),
+        kind: Some(pb::contact::Kind::Company(company)),
+    };
     book.contacts.insert(name.to_string(), contact);
 
-    write_to_db(f, book);
+    db.write(&book)
 }
 
 fn redact_private_info(contact: &mut pb::Contact) {
@@ -170,8 +155,8 @@ fn redact_private_info(contact: &mut pb::Contact) {
 }
 
 
-fn list_contacts(f: &mut fs::File, redact: bool) {
-    let book = read_from_db(f);
+fn list_contacts(db: &Db, redact: bool) -> Result<(), StorageError> {
+    let book = db.read()?;
     let mut keys: Vec<&String> = book.contacts.keys().collect();
     keys.sort();
     for name in keys {
@@ -179,43 +164,266 @@ fn list_contacts(f: &mut fs::File, redact: bool) {
         if redact {
             redact_private_info(&mut contact); // Редактируем личную информацию
         }
-        println!("name: {}", name);
-        println!("last_updated: {:?}", contact.last_updated.unwrap());
-        println!("{:#?}", contact);
-        println!("-----------------------");
+        print_contact(name, &contact);
+    }
+    Ok(())
+}
+
+fn search_contacts(db: &Db, filter: &search::Filter, redact: bool) -> Result<(), StorageError> {
+    let book = db.read()?;
+    let mut keys: Vec<&String> = book.contacts.keys().collect();
+    keys.sort();
+    for name in keys {
+        let mut contact = book.contacts[name].clone();
+        if !filter.matches(name, &contact) {
+            continue;
+        }
+        if redact {
+            redact_private_info(&mut contact);
+        }
+        print_contact(name, &contact);
+    }
+    Ok(())
+}
+
+fn print_contact(name: &str, contact: &pb::Contact) {
+    println!("name: {}", name);
+    println!("last_updated: {:?}", contact.last_updated.unwrap());
+    println!("{:#?}", contact);
+    println!("-----------------------");
+}
+
+fn get_contact(db: &Db, name: &str, redact: bool) -> Result<(), Box<dyn Error>> {
+    let book = db.read()?;
+    let mut contact = book.contacts.get(name).ok_or_else(|| not_found(name))?.clone();
+    if redact {
+        redact_private_info(&mut contact);
+    }
+    print_contact(name, &contact);
+    Ok(())
+}
+
+// Правка контакта на месте; время обновления меняется, только если правка удалась
+fn edit_contact(
+    db: &Db,
+    name: &str,
+    edit: impl FnOnce(&mut pb::Contact) -> Result<(), String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut book = db.read()?;
+    let contact = book.contacts.get_mut(name).ok_or_else(|| not_found(name))?;
+    edit(contact).map_err(|e| format!("{}: {}", name, e))?;
+    contact.last_updated = Some(now());
+    db.write(&book)?;
+    Ok(())
+}
+
+fn delete_contact(db: &Db, name: &str) -> Result<(), Box<dyn Error>> {
+    let mut book = db.read()?;
+    book.contacts.remove(name).ok_or_else(|| not_found(name))?;
+    db.write(&book)?;
+    Ok(())
+}
+
+fn rename_contact(db: &Db, name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+    let mut book = db.read()?;
+    if book.contacts.contains_key(new_name) {
+        return Err(format!("Contact {} already exists", new_name).into());
+    }
+    let mut contact = book.contacts.remove(name).ok_or_else(|| not_found(name))?;
+    contact.last_updated = Some(now());
+    book.contacts.insert(new_name.to_string(), contact);
+    db.write(&book)?;
+    Ok(())
+}
+
+fn export_contacts(db: &Db, config: &Config) -> Result<(), Box<dyn Error>> {
+    let format = exchange::Format::from_params(&config.params)?;
+    // остальные параметры - те же фильтры, что у search
+    let mut filters = config.params.clone();
+    for key in ["--format", "--file", "--vcard-version"] {
+        filters.remove(key);
+    }
+    let filter = search::Filter::from_params(&filters)?;
+    let redact = config.params.contains_key("--redact");
+
+    let mut book = db.read()?;
+    book.contacts.retain(|name, contact| filter.matches(name, contact));
+    if redact {
+        book.contacts.values_mut().for_each(redact_private_info);
+    }
+    let text = exchange::export(&book, format, &config.params)?;
+    match config.params.get("--file") {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| format!("{}: {}", path, e))?;
+            println!("Exported {} contacts to {}", book.contacts.len(), path);
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+fn import_contacts(config: &Config) -> Result<(), Box<dyn Error>> {
+    let path = param(config, "--file")?;
+    let format = exchange::Format::from_params(&config.params)?;
+    let strategy = exchange::Strategy::from_param(config.params.get("--on-conflict").map(String::as_str))?;
+    let dry_run = config.params.contains_key("--dry-run");
+
+    // "-" - стандартный ввод
+    let mut text = String::new();
+    if path == "-" {
+        io::stdin().read_to_string(&mut text)?;
+    } else {
+        text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    let mut warnings = Vec::new();
+    let entries = exchange::parse(&text, format, &config.params, &mut warnings)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    for warning in warnings.drain(..) {
+        eprintln!("Warning: {}", warning);
+    }
+
+    // пробный прогон ничего не пишет, ему хватит общей блокировки
+    let db = if dry_run { Db::open_shared(DB_FILE_PATH)? } else { Db::open_exclusive(DB_FILE_PATH)? };
+    let mut book = db.read()?;
+    let changes = exchange::apply(&mut book, entries, strategy, now(), &mut warnings)?;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if dry_run {
+        for line in changes.iter().flat_map(exchange::describe) {
+            println!("{}", line);
+        }
+    } else {
+        db.write(&book)?;
     }
+
+    let count = |outcome| changes.iter().filter(|c| c.outcome == outcome).count();
+    println!(
+        "{}{} added, {} updated, {} unchanged, {} skipped",
+        if dry_run { "Dry run, nothing written: " } else { "Imported: " },
+        count(exchange::Outcome::Added),
+        count(exchange::Outcome::Updated),
+        count(exchange::Outcome::Unchanged),
+        count(exchange::Outcome::Skipped),
+    );
+    Ok(())
+}
+
+fn not_found(name: &str) -> String {
+    format!("Contact {} not found", name)
+}
+
+// Обязательный параметр команды
+fn param<'a>(config: &'a Config, key: &str) -> Result<&'a str, String> {
+    config
+        .params
+        .get(key)
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing {} for {}", key, config.command))
 }
 
 fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let mut f = open_db_file(DB_FILE_PATH);
     match config.command.as_ref() {
         "add" => {
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
             if config.params["--kind"] == "per" || config.params["--kind"] == "person" {
                 add_person(
-                    &mut f,
+                    &db,
                     &config.params["--name"],
                     &config.params["--email"],
                     &config.params["--phone"],
                     &config.params["--type"],
-                );
+                )?;
             } else if config.params["--kind"] == "cie" || config.params["--kind"] == "company" {
                 add_company(
-                    &mut f,
+                    &db,
                     &config.params["--name"],
                     &config.params["--email"],
                     &config.params["--dep"],
                     &config.params["--phone"],
                     &config.params["--type"],
-                );
+                )?;
             }
             Ok(())
         }
 
         "list" => {
-            let redact = config.params.get("--redact").is_some();
-            list_contacts(&mut f, redact);
+            let redact = config.params.contains_key("--redact");
+            let db = Db::open_shared(DB_FILE_PATH)?;
+            list_contacts(&db, redact)?;
             Ok(())
         }
+
+        "search" => {
+            let filter = search::Filter::from_params(&config.params)?;
+            let redact = config.params.contains_key("--redact");
+            let db = Db::open_shared(DB_FILE_PATH)?;
+            search_contacts(&db, &filter, redact)?;
+            Ok(())
+        }
+
+        "export" => {
+            let db = Db::open_shared(DB_FILE_PATH)?;
+            export_contacts(&db, &config)
+        }
+
+        "import" => import_contacts(&config),
+
+        "get" => {
+            let redact = config.params.contains_key("--redact");
+            let db = Db::open_shared(DB_FILE_PATH)?;
+            get_contact(&db, param(&config, "--name")?, redact)
+        }
+
+        "update" => {
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            edit_contact(&db, param(&config, "--name")?, |contact| {
+                contacts::update(contact, &config.params)
+            })
+        }
+
+        "delete" => {
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            delete_contact(&db, param(&config, "--name")?)
+        }
+
+        "rename" => {
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            rename_contact(&db, param(&config, "--name")?, param(&config, "--to")?)
+        }
+
+        "add-phone" => {
+            let (phone, kind) = (param(&config, "--phone")?, param(&config, "--type")?);
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            edit_contact(&db, param(&config, "--name")?, |contact| {
+                contacts::add_phone(contact, phone, kind)
+            })
+        }
+
+        "remove-phone" => {
+            let phone = param(&config, "--phone")?;
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            edit_contact(&db, param(&config, "--name")?, |contact| {
+                contacts::remove_phone(contact, phone)
+            })
+        }
+
+        "add-email" => {
+            let email = param(&config, "--email")?;
+            let department = config.params.get("--dep").map(String::as_str);
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            edit_contact(&db, param(&config, "--name")?, |contact| {
+                contacts::add_email(contact, email, department)
+            })
+        }
+
+        "remove-email" => {
+            let email = param(&config, "--email")?;
+            let db = Db::open_exclusive(DB_FILE_PATH)?;
+            edit_contact(&db, param(&config, "--name")?, |contact| {
+                contacts::remove_email(contact, email)
+            })
+        }
         _ => Err("Command not found")?,
     }
 }
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use prost_types::Timestamp;
+use regex::Regex;
+
+use crate::pb;
+use crate::pb::contact::Kind;
+
+// Параметры `search`; все необязательные, заданные сочетаются через И
+const FILTERS: &[&str] = &[
+    "--name",
+    "--name-regex",
+    "--email-domain",
+    "--phone-prefix",
+    "--kind",
+    "--dep",
+    "--updated-after",
+    "--updated-before",
+    "--redact",
+];
+
+/// Отбор контактов для `search`.
+///
+/// Фильтры проверяются по настоящим данным, `--redact` скрывает их
+/// только при выводе.
+#[derive(Default)]
+pub struct Filter {
+    // подстрока имени без учёта регистра
+    name: Option<String>,
+    name_regex: Option<Regex>,
+    email_domain: Option<String>,
+    phone_prefix: Option<String>,
+    kind: Option<ContactKind>,
+    department: Option<pb::company::Department>,
+    updated_after: Option<Timestamp>,
+    updated_before: Option<Timestamp>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ContactKind {
+    Person,
+    Company,
+}
+
+impl Filter {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Filter, String> {
+        if let Some(unknown) = params.keys().find(|key| !FILTERS.contains(&key.as_str())) {
+            return Err(format!("Unknown search filter {}, expected one of {}", unknown, FILTERS.join(", ")));
+        }
+        let param = |key: &str| params.get(key).map(String::as_str);
+
+        let mut filter = Filter {
+            name: param("--name").map(str::to_lowercase),
+            email_domain: param("--email-domain").map(|d| d.trim_start_matches('@').to_lowercase()),
+            phone_prefix: param("--phone-prefix").map(phone_digits),
+            ..Filter::default()
+        };
+        if let Some(pattern) = param("--name-regex") {
+            let regex = Regex::new(pattern).map_err(|e| format!("Invalid --name-regex: {}", e))?;
+            filter.name_regex = Some(regex);
+        }
+        filter.kind = match param("--kind") {
+            None => None,
+            Some("per" | "person") => Some(ContactKind::Person),
+            Some("cie" | "company") => Some(ContactKind::Company),
+            Some(other) => return Err(format!("Invalid --kind {}, expected person or company", other)),
+        };
+        filter.department = match param("--dep") {
+            None => None,
+            Some("hr") => Some(pb::company::Department::Hr),
+            Some("cs") => Some(pb::company::Department::CustomerService),
+            Some(other) => return Err(format!("Invalid --dep {}, expected hr or cs", other)),
+        };
+        if let Some(value) = param("--updated-after") {
+            filter.updated_after = Some(parse_time(value).map_err(|e| format!("Invalid --updated-after: {}", e))?);
+        }
+        if let Some(value) = param("--updated-before") {
+            filter.updated_before = Some(parse_time(value).map_err(|e| format!("Invalid --updated-before: {}", e))?);
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, name: &str, contact: &pb::Contact) -> bool {
+        if let Some(part) = &self.name {
+            if !name.to_lowercase().contains(part) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.name_regex {
+            if !regex.is_match(name) {
+                return false;
+            }
+        }
+        if let Some(kind) = self.kind {
+            let actual = match contact.kind {
+                Some(Kind::Person(_)) => Some(ContactKind::Person),
+                Some(Kind::Company(_)) => Some(ContactKind::Company),
+                None => None,
+            };
+            if actual != Some(kind) {
+                return false;
+            }
+        }
+        if let Some(domain) = &self.email_domain {
+            if !emails(contact).any(|email| in_domain(email, domain)) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.phone_prefix {
+            if !phones(contact).any(|number| phone_digits(number).starts_with(prefix.as_str())) {
+                return false;
+            }
+        }
+        if let Some(department) = self.department {
+            // отделы есть только у компании: у её адресов и телефонов
+            let department = department as i32;
+            let found = match &contact.kind {
+                Some(Kind::Company(company)) => {
+                    company.emails.iter().any(|e| e.department == department)
+                        || company.phones.iter().any(|p| p.department == department)
+                }
+                _ => false,
+            };
+            if !found {
+                return false;
+            }
+        }
+        if self.updated_after.is_some() || self.updated_before.is_some() {
+            // без времени обновления под диапазон не попадает
+            let Some(updated) = contact.last_updated.as_ref().map(key) else {
+                return false;
+            };
+            if self.updated_after.as_ref().is_some_and(|after| updated < key(after)) {
+                return false;
+            }
+            if self.updated_before.as_ref().is_some_and(|before| updated >= key(before)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn emails(contact: &pb::Contact) -> Box<dyn Iterator<Item = &str> + '_> {
+    match &contact.kind {
+        Some(Kind::Person(person)) => Box::new(std::iter::once(person.email.as_str())),
+        Some(Kind::Company(company)) => Box::new(company.emails.iter().map(|e| e.email.as_str())),
+        None => Box::new(std::iter::empty()),
+    }
+}
+
+fn phones(contact: &pb::Contact) -> Box<dyn Iterator<Item = &str> + '_> {
+    match &contact.kind {
+        Some(Kind::Person(person)) => Box::new(person.phones.iter().map(|p| p.number.as_str())),
+        Some(Kind::Company(company)) => Box::new(company.phones.iter().map(|p| p.number.as_str())),
+        None => Box::new(std::iter::empty()),
+    }
+}
+
+// Домен совпадает целиком или адрес из его поддомена: example.com подходит к mail.example.com
+fn in_domain(email: &str, domain: &str) -> bool {
+    let Some((_, actual)) = email.rsplit_once('@') else {
+        return false;
+    };
+    let actual = actual.to_lowercase();
+    actual == domain || actual.ends_with(&format!(".{}", domain))
+}
+
+// Номер без пробелов, скобок и дефисов: "+7 (495) 123-45-67" -> "+74951234567"
+fn phone_digits(number: &str) -> String {
+    number.chars().filter(|c| c.is_ascii_digit() || *c == '+').collect()
+}
+
+fn key(time: &Timestamp) -> (i64, i32) {
+    (time.seconds, time.nanos)
+}
+
+// RFC 3339, `YYYY-MM-DD HH:MM:SS` или `YYYY-MM-DD` (полночь); без зоны - UTC
+fn parse_time(value: &str) -> Result<Timestamp, String> {
+    let time = if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        time.to_utc()
+    } else if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        time.and_utc()
+    } else if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc()
+    } else {
+        return Err(format!("{:?} is not a date, expected YYYY-MM-DD or RFC 3339", value));
+    };
+    Ok(Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-01 00:00:00 UTC
+    const MARCH: i64 = 1_709_251_200;
+
+    fn filter(pairs: &[(&str, &str)]) -> Filter {
+        let params = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Filter::from_params(&params).unwrap()
+    }
+
+    fn person(email: &str, phone: &str, updated: Option<i64>) -> pb::Contact {
+        pb::Contact {
+            last_updated: updated.map(|seconds| Timestamp { seconds, nanos: 0 }),
+            kind: Some(Kind::Person(pb::Person {
+                email: email.to_string(),
+                phones: vec![pb::person::PhoneNumber {
+                    number: phone.to_string(),
+                    r#type: pb::person::phone_number::Type::Mobile as i32,
+                }],
+            })),
+        }
+    }
+
+    fn company(email: &str, email_dep: pb::company::Department, phone: &str) -> pb::Contact {
+        pb::Contact {
+            last_updated: None,
+            kind: Some(Kind::Company(pb::Company {
+                emails: vec![pb::company::EmailAddress {
+                    email: email.to_string(),
+                    department: email_dep as i32,
+                }],
+                phones: vec![pb::company::PhoneNumber {
+                    number: phone.to_string(),
+                    department: pb::company::Department::Hr as i32,
+                }],
+            })),
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(filter(&[]).matches("anyone", &pb::Contact::default()));
+    }
+
+    #[test]
+    fn name_is_a_case_insensitive_substring() {
+        let contact = person("", "", None);
+        assert!(filter(&[("--name", "VAN")]).matches("Ivan Petrov", &contact));
+        assert!(!filter(&[("--name", "olga")]).matches("Ivan Petrov", &contact));
+    }
+
+    #[test]
+    fn name_regex_matches_the_name() {
+        let contact = person("", "", None);
+        let by_regex = filter(&[("--name-regex", "^I.*v$")]);
+        assert!(by_regex.matches("Ivan Petrov", &contact));
+        assert!(!by_regex.matches("ivan petrov", &contact));
+    }
+
+    #[test]
+    fn email_domain_includes_subdomains() {
+        let by_domain = filter(&[("--email-domain", "@Example.com")]);
+        assert!(by_domain.matches("a", &person("ivan@example.com", "", None)));
+        assert!(by_domain.matches("a", &person("ivan@mail.EXAMPLE.com", "", None)));
+        assert!(!by_domain.matches("a", &person("ivan@notexample.com", "", None)));
+        assert!(!by_domain.matches("a", &person("", "", None)));
+        let hr = pb::company::Department::Hr;
+        assert!(by_domain.matches("a", &company("hr@example.com", hr, "")));
+    }
+
+    #[test]
+    fn phone_prefix_ignores_formatting() {
+        let by_prefix = filter(&[("--phone-prefix", "+7 (495)")]);
+        assert!(by_prefix.matches("a", &person("", "+7 495 123-45-67", None)));
+        assert!(!by_prefix.matches("a", &person("", "+7 812 123-45-67", None)));
+        let hr = pb::company::Department::Hr;
+        assert!(by_prefix.matches("a", &company("", hr, "+7-495-000")));
+    }
+
+    #[test]
+    fn kind_separates_people_and_companies() {
+        let people = filter(&[("--kind", "per")]);
+        let companies = filter(&[("--kind", "company")]);
+        let ivan = person("", "", None);
+        let acme = company("", pb::company::Department::Hr, "");
+        assert!(people.matches("a", &ivan) && !people.matches("a", &acme));
+        assert!(companies.matches("a", &acme) && !companies.matches("a", &ivan));
+        assert!(!people.matches("a", &pb::Contact::default()));
+    }
+
+    #[test]
+    fn department_checks_company_emails_and_phones() {
+        use pb::company::Department;
+        let cs = filter(&[("--dep", "cs")]);
+        let hr = filter(&[("--dep", "hr")]);
+        // адрес в cs, телефон в hr
+        let acme = company("cs@acme.org", Department::CustomerService, "+1");
+        assert!(cs.matches("a", &acme));
+        assert!(hr.matches("a", &acme));
+        assert!(!cs.matches("a", &company("hr@acme.org", Department::Hr, "+1")));
+        assert!(!hr.matches("a", &person("", "", None)));
+    }
+
+    #[test]
+    fn updated_range_excludes_its_end() {
+        let day = 24 * 60 * 60;
+        let march = filter(&[("--updated-after", "2024-03-01"), ("--updated-before", "2024-03-02")]);
+        assert!(march.matches("a", &person("", "", Some(MARCH))));
+        assert!(march.matches("a", &person("", "", Some(MARCH + day - 1))));
+        assert!(!march.matches("a", &person("", "", Some(MARCH + day))));
+        assert!(!march.matches("a", &person("", "", Some(MARCH - 1))));
+        assert!(!march.matches("a", &person("", "", None)));
+
+        let after = filter(&[("--updated-after", "2024-03-01T03:00:00+03:00")]);
+        assert!(after.matches("a", &person("", "", Some(MARCH))));
+        assert!(!after.matches("a", &person("", "", Some(MARCH - 1))));
+    }
+
+    #[test]
+    fn filters_combine_with_and() {
+        let combined = filter(&[
+            ("--name", "ivan"),
+            ("--kind", "person"),
+            ("--email-domain", "example.com"),
+            ("--phone-prefix", "+7"),
+            ("--updated-after", "2024-03-01"),
+        ]);
+        let ivan = person("ivan@example.com", "+7 495", Some(MARCH));
+        assert!(combined.matches("Ivan", &ivan));
+        assert!(!combined.matches("Olga", &ivan));
+        assert!(!combined.matches("Ivan", &person("ivan@other.org", "+7 495", Some(MARCH))));
+        assert!(!combined.matches("Ivan", &person("ivan@example.com", "+1 212", Some(MARCH))));
+        assert!(!combined.matches("Ivan", &person("ivan@example.com", "+7 495", Some(MARCH - 1))));
+        assert!(!combined.matches("Ivan", &company("ivan@example.com", pb::company::Department::Hr, "+7")));
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        let params = |key: &str, value: &str| HashMap::from([(key.to_string(), value.to_string())]);
+        assert!(Filter::from_params(&params("--kind", "robot")).is_err());
+        assert!(Filter::from_params(&params("--dep", "it")).is_err());
+        assert!(Filter::from_params(&params("--name-regex", "(")).is_err());
+        assert!(Filter::from_params(&params("--updated-after", "yesterday")).is_err());
+        assert!(Filter::from_params(&params("--colour", "red")).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use prost::Message;
+
+use crate::pb;
+
+// Заголовок файла: магия с версией формата, длина и CRC32 тела
+const MAGIC: &[u8; 4] = b"ABK\x01";
+const HEADER_LEN: usize = 4 + 8 + 4;
+
+/// Ошибки хранилища адресной книги.
+#[derive(Debug)]
+pub enum StorageError {
+    Io(PathBuf, io::Error),
+    /// Файл есть, но прочитать его нельзя: обрыв, чужой файл, не сходится CRC.
+    Corrupt(PathBuf, String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            StorageError::Corrupt(path, reason) => write!(
+                f,
+                "{} is corrupted: {} (restore it from a backup or move it away to start a new book)",
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+/// Адресная книга на диске, открытая под блокировкой.
+///
+/// Блокировка - рекомендательная (flock) на соседнем файле `<db>.lock`:
+/// сам файл книги при записи подменяется через rename, и блокировка на
+/// нём потерялась бы. Снимается, когда `Db` уничтожается.
+pub struct Db {
+    path: PathBuf,
+    _lock: File,
+}
+
+impl Db {
+    /// Только чтение: общая блокировка, несколько `list` не мешают друг другу.
+    pub fn open_shared(path: impl Into<PathBuf>) -> Result<Db, StorageError> {
+        Db::open(path.into(), false)
+    }
+
+    /// Чтение и запись: исключительная блокировка от чтения книги до записи,
+    /// чтобы одновременные `add` не затирали изменения друг друга.
+    pub fn open_exclusive(path: impl Into<PathBuf>) -> Result<Db, StorageError> {
+        Db::open(path.into(), true)
+    }
+
+    fn open(path: PathBuf, exclusive: bool) -> Result<Db, StorageError> {
+        let lock_path = with_suffix(&path, ".lock");
+        let io_err = |e| StorageError::Io(lock_path.clone(), e);
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(io_err)?;
+
+        let attempt = if exclusive { lock.try_lock() } else { lock.try_lock_shared() };
+        match attempt {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                eprintln!("Waiting for another process to release {}...", lock_path.display());
+                if exclusive { lock.lock() } else { lock.lock_shared() }.map_err(io_err)?;
+            }
+            Err(TryLockError::Error(e)) => return Err(io_err(e)),
+        }
+        Ok(Db { path, _lock: lock })
+    }
+
+    /// Книга целиком; нет файла или он пустой - пустая книга.
+    pub fn read(&self) -> Result<pb::AddressBook, StorageError> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(StorageError::Io(self.path.clone(), e)),
+        };
+        decode(&contents).map_err(|reason| StorageError::Corrupt(self.path.clone(), reason))
+    }
+
+    /// Записывает книгу целиком: во временный файл, fsync, rename поверх
+    /// старого. При сбое на середине остаётся старая книга, а не смесь.
+    pub fn write(&self, book: &pb::AddressBook) -> Result<(), StorageError> {
+        let tmp_path = with_suffix(&self.path, ".tmp");
+        let io_err = |path: &Path| {
+            let path = path.to_path_buf();
+            move |e| StorageError::Io(path, e)
+        };
+
+        let mut tmp = File::create(&tmp_path).map_err(io_err(&tmp_path))?;
+        tmp.write_all(&encode(book)).map_err(io_err(&tmp_path))?;
+        tmp.sync_all().map_err(io_err(&tmp_path))?;
+        drop(tmp);
+        fs::rename(&tmp_path, &self.path).map_err(io_err(&self.path))?;
+
+        // переименование попадает на диск вместе с каталогом
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir).and_then(|d| d.sync_all()).map_err(io_err(dir))
+    }
+}
+
+fn encode(book: &pb::AddressBook) -> Vec<u8> {
+    let body = book.encode_to_vec();
+    let mut contents = Vec::with_capacity(HEADER_LEN + body.len());
+    contents.extend_from_slice(MAGIC);
+    contents.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    contents.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    contents.extend_from_slice(&body);
+    contents
+}
+
+fn decode(contents: &[u8]) -> Result<pb::AddressBook, String> {
+    if contents.is_empty() {
+        return Ok(pb::AddressBook::default());
+    }
+    // файл старого формата - голый protobuf; перепишется с заголовком при первой записи
+    if !contents.starts_with(MAGIC) {
+        return pb::AddressBook::decode(contents)
+            .map_err(|e| format!("no address book header and not a legacy protobuf book ({})", e));
+    }
+    if contents.len() < HEADER_LEN {
+        return Err(format!("header truncated at {} of {} bytes", contents.len(), HEADER_LEN));
+    }
+
+    let len = u64::from_le_bytes(contents[4..12].try_into().unwrap());
+    let crc = u32::from_le_bytes(contents[12..16].try_into().unwrap());
+    let body = &contents[HEADER_LEN..];
+    if body.len() as u64 != len {
+        return Err(format!("expected {} bytes of contacts, found {}", len, body.len()));
+    }
+    if crc32fast::hash(body) != crc {
+        return Err("checksum mismatch".to_string());
+    }
+    pb::AddressBook::decode(body).map_err(|e| format!("invalid contacts data ({})", e))
+}
+
+// addressbook.db -> addressbook.db.lock
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> pb::AddressBook {
+        let mut book = pb::AddressBook::default();
+        let person = pb::Person {
+            email: "ann@example.com".to_string(),
+            phones: vec![pb::person::PhoneNumber {
+                number: "+100".to_string(),
+                r#type: 1,
+            }],
+        };
+        book.contacts.insert(
+            "Ann".to_string(),
+            pb::Contact {
+                last_updated: None,
+                kind: Some(pb::contact::Kind::Person(person)),
+            },
+        );
+        book
+    }
+
+    // Отдельный файл книги на тест, рядом с ним появятся .lock и .tmp
+    fn db_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("addressbook-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn read_file(name: &str, contents: &[u8]) -> Result<pb::AddressBook, StorageError> {
+        let path = db_path(name);
+        fs::write(&path, contents).unwrap();
+        Db::open_shared(&path).unwrap().read()
+    }
+
+    fn corrupt_reason(result: Result<pb::AddressBook, StorageError>) -> String {
+        match result {
+            Err(StorageError::Corrupt(_, reason)) => reason,
+            Err(e) => panic!("expected Corrupt, got {}", e),
+            Ok(_) => panic!("expected Corrupt, got a book"),
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let contents = encode(&book());
+        assert!(contents.starts_with(MAGIC));
+        assert_eq!(decode(&contents).unwrap(), book());
+    }
+
+    #[test]
+    fn written_book_is_read_back() {
+        let path = db_path("round-trip.db");
+        let db = Db::open_exclusive(&path).unwrap();
+        assert_eq!(db.read().unwrap(), pb::AddressBook::default());
+        db.write(&book()).unwrap();
+        assert_eq!(db.read().unwrap(), book());
+        assert!(!with_suffix(&path, ".tmp").exists());
+    }
+
+    #[test]
+    fn crc_mismatch_is_corrupt() {
+        let mut contents = encode(&book());
+        let last = contents.len() - 1;
+        contents[last] ^= 0xff;
+        assert_eq!(corrupt_reason(read_file("crc.db", &contents)), "checksum mismatch");
+    }
+
+    #[test]
+    fn truncated_header_is_corrupt() {
+        let contents = encode(&book());
+        let reason = corrupt_reason(read_file("header.db", &contents[..10]));
+        assert_eq!(reason, "header truncated at 10 of 16 bytes");
+    }
+
+    #[test]
+    fn truncated_body_is_corrupt() {
+        let contents = encode(&book());
+        let reason = corrupt_reason(read_file("body.db", &contents[..contents.len() - 3]));
+        assert!(reason.starts_with("expected "), "{}", reason);
+    }
+
+    #[test]
+    fn legacy_headerless_file_is_read() {
+        let legacy = book().encode_to_vec();
+        assert_eq!(read_file("legacy.db", &legacy).unwrap(), book());
+    }
+
+    #[test]
+    fn garbage_without_header_is_corrupt() {
+        let reason = corrupt_reason(read_file("garbage.db", b"\xff\xff\xff\xff"));
+        assert!(reason.starts_with("no address book header"), "{}", reason);
+    }
+}